@@ -0,0 +1,10 @@
+pub mod blip;
+pub mod channel;
+pub mod noise;
+pub mod output;
+pub mod pulse;
+pub mod recorder;
+pub mod ring;
+pub mod sound;
+pub mod wav;
+pub mod wave;
@@ -0,0 +1,143 @@
+#![allow(dead_code)]
+use std::f64::consts::PI;
+
+const KERNEL_WIDTH: usize = 16;
+const PHASES: usize = 64;
+// Fraction of the output Nyquist frequency let through by the kernel
+const CUTOFF: f64 = 0.9;
+
+// Band-limited synthesis in the spirit of blip_buf: amplitude changes are added as
+// windowed-sinc impulses at their exact sub-sample position, then integrated when read
+#[derive(Debug, Clone)]
+pub struct BlipBuffer {
+    pub factor: f64,
+    pub offset: f64,
+    pub deltas: Vec<f32>,
+    pub integrator: f32,
+    kernel: Vec<[f32; KERNEL_WIDTH]>,
+}
+
+impl BlipBuffer {
+    pub fn new(clock_rate: f64, sample_rate: f64) -> BlipBuffer {
+        BlipBuffer {
+            factor: sample_rate / clock_rate,
+            offset: 0.0,
+            deltas: vec![0.0; KERNEL_WIDTH],
+            integrator: 0.0,
+            kernel: build_kernel(),
+        }
+    }
+
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: f64) {
+        self.factor = sample_rate / clock_rate;
+    }
+
+    // Adds an amplitude change happening `clock` cycles after the start of the frame
+    pub fn add_delta(&mut self, clock: u32, delta: f32) {
+        let position = self.offset + clock as f64 * self.factor;
+        let index = position as usize;
+        let phase = ((position - index as f64) * PHASES as f64) as usize;
+        if self.deltas.len() < index + KERNEL_WIDTH {
+            self.deltas.resize(index + KERNEL_WIDTH, 0.0);
+        }
+        for (tap, weight) in self.kernel[phase].iter().enumerate() {
+            self.deltas[index + tap] += delta * weight;
+        }
+    }
+
+    pub fn end_frame(&mut self, clocks: u32) {
+        self.offset += clocks as f64 * self.factor;
+        let needed = self.offset as usize + KERNEL_WIDTH;
+        if self.deltas.len() < needed {
+            self.deltas.resize(needed, 0.0);
+        }
+    }
+
+    pub fn samples_available(&self) -> usize {
+        self.offset as usize
+    }
+
+    pub fn read_samples(&mut self, output: &mut Vec<f32>) {
+        let count = self.samples_available();
+        for delta in self.deltas.drain(..count) {
+            self.integrator += delta;
+            output.push(self.integrator);
+        }
+        self.offset -= count as f64;
+    }
+}
+
+fn build_kernel() -> Vec<[f32; KERNEL_WIDTH]> {
+    (0..PHASES)
+        .map(|phase| {
+            let fraction = phase as f64 / PHASES as f64;
+            let mut taps = [0.0; KERNEL_WIDTH];
+            for (tap, weight) in taps.iter_mut().enumerate() {
+                let time = tap as f64 - (KERNEL_WIDTH / 2 - 1) as f64 - fraction;
+                *weight = (sinc(time * CUTOFF) * blackman(time)) as f32;
+            }
+            // Each phase sums to 1 so an integrated delta settles at its full amplitude
+            let sum: f32 = taps.iter().sum();
+            taps.map(|weight| weight / sum)
+        })
+        .collect()
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn blackman(time: f64) -> f64 {
+    let half = KERNEL_WIDTH as f64 / 2.0;
+    let n = (time + half) / KERNEL_WIDTH as f64;
+    if !(0.0..=1.0).contains(&n) {
+        return 0.0;
+    }
+    0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_produce_samples_at_output_rate() {
+        let mut blip = BlipBuffer::new(4_194_304.0, 48_000.0);
+        blip.end_frame(4_194_304 / 8);
+        let mut output = Vec::new();
+        blip.read_samples(&mut output);
+
+        assert_eq!(output.len(), 6000);
+    }
+
+    #[test]
+    fn it_should_settle_on_step_amplitude() {
+        let mut blip = BlipBuffer::new(4_194_304.0, 48_000.0);
+        blip.add_delta(1000, 0.5);
+        blip.end_frame(100_000);
+        let mut output = Vec::new();
+        blip.read_samples(&mut output);
+
+        assert_eq!(output[0], 0.0);
+        assert!((output.last().unwrap() - 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn it_should_keep_fractional_position_across_frames() {
+        let mut blip = BlipBuffer::new(4_194_304.0, 44_100.0);
+        let mut output = Vec::new();
+        for _ in 0..10 {
+            blip.end_frame(70_224);
+            blip.read_samples(&mut output);
+        }
+
+        assert_eq!(
+            output.len(),
+            (70_224.0 * 10.0 * 44_100.0 / 4_194_304.0) as usize
+        );
+    }
+}
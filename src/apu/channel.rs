@@ -0,0 +1,144 @@
+#![allow(dead_code)]
+
+#[derive(Debug, Clone)]
+pub struct LengthCounter {
+    pub enabled: bool,
+    pub counter: u16,
+    pub max: u16,
+}
+
+impl LengthCounter {
+    pub fn new(max: u16) -> LengthCounter {
+        LengthCounter {
+            enabled: false,
+            counter: 0,
+            max,
+        }
+    }
+
+    pub fn load(&mut self, value: u16) {
+        self.counter = self.max - value;
+    }
+
+    pub fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    // Returns false once the counter expires and the channel has to stop
+    pub fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter != 0;
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    pub initial: u8,
+    pub increase: bool,
+    pub period: u8,
+    pub volume: u8,
+    pub timer: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            initial: 0,
+            increase: false,
+            period: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.initial = value >> 4;
+        self.increase = value & 0x08 == 0x08;
+        self.period = value & 0x07;
+    }
+
+    // The DAC is off when both the initial volume and the direction are 0
+    pub fn dac_enabled(value: u8) -> bool {
+        value & 0xF8 != 0
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.initial;
+        self.timer = self.period;
+    }
+
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_expire_length_counter() {
+        let mut length = LengthCounter::new(64);
+        length.enabled = true;
+        length.load(62);
+
+        assert!(length.clock());
+        assert!(!length.clock());
+    }
+
+    #[test]
+    fn it_should_not_count_when_disabled() {
+        let mut length = LengthCounter::new(64);
+        length.load(63);
+
+        assert!(length.clock());
+        assert_eq!(length.counter, 1);
+    }
+
+    #[test]
+    fn it_should_reload_full_length_on_trigger() {
+        let mut length = LengthCounter::new(256);
+        length.trigger();
+
+        assert_eq!(length.counter, 256);
+    }
+
+    #[test]
+    fn it_should_decrease_volume_every_period() {
+        let mut envelope = Envelope::new();
+        envelope.write(0xF2);
+        envelope.trigger();
+        envelope.clock();
+        assert_eq!(envelope.volume, 15);
+        envelope.clock();
+        assert_eq!(envelope.volume, 14);
+    }
+
+    #[test]
+    fn it_should_stop_increasing_volume_at_15() {
+        let mut envelope = Envelope::new();
+        envelope.write(0xF9);
+        envelope.trigger();
+        envelope.clock();
+
+        assert_eq!(envelope.volume, 15);
+    }
+}
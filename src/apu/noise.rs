@@ -0,0 +1,170 @@
+#![allow(dead_code)]
+use crate::apu::channel::{Envelope, LengthCounter};
+
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+#[derive(Debug, Clone)]
+pub struct Noise {
+    pub enabled: bool,
+    pub dac_enabled: bool,
+    pub clock_shift: u8,
+    pub short_mode: bool,
+    pub divisor_code: usize,
+    pub timer: u32,
+    pub lfsr: u16,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise {
+            enabled: false,
+            dac_enabled: false,
+            clock_shift: 0,
+            short_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+
+    pub fn write(&mut self, register: usize, value: u8) {
+        match register {
+            1 => self.length.load((value & 0x3F) as u16),
+            2 => {
+                self.envelope.write(value);
+                self.dac_enabled = Envelope::dac_enabled(value);
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            3 => {
+                self.clock_shift = value >> 4;
+                self.short_mode = value & 0x08 == 0x08;
+                self.divisor_code = (value & 0x7) as usize;
+            }
+            4 => {
+                self.length.enabled = value & 0x40 == 0x40;
+                if value & 0x80 == 0x80 {
+                    self.trigger();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger();
+        self.timer = self.period();
+        self.envelope.trigger();
+        self.lfsr = 0x7FFF;
+    }
+
+    fn period(&self) -> u32 {
+        DIVISORS[self.divisor_code] << self.clock_shift
+    }
+
+    pub fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.step_lfsr();
+        }
+    }
+
+    // In 7-bit mode the feedback is also copied to bit 6, shortening the sequence
+    fn step_lfsr(&mut self) {
+        let feedback = (self.lfsr & 0x1) ^ ((self.lfsr >> 1) & 0x1);
+        self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+        if self.short_mode {
+            self.lfsr = (self.lfsr & !0x40) | (feedback << 6);
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.enabled && self.lfsr & 0x1 == 0 {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence_length(short_mode: bool) -> usize {
+        let mut noise = Noise::new();
+        noise.short_mode = short_mode;
+        let start = noise.lfsr;
+        let mut steps = 0;
+        loop {
+            noise.step_lfsr();
+            steps += 1;
+            if noise.lfsr == start || steps > 40000 {
+                return steps;
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_repeat_after_32767_steps_in_15_bit_mode() {
+        assert_eq!(sequence_length(false), 32767);
+    }
+
+    #[test]
+    fn it_should_repeat_after_127_steps_in_7_bit_mode() {
+        let mut noise = Noise::new();
+        noise.short_mode = true;
+        for _ in 0..200 {
+            noise.step_lfsr();
+        }
+        let start = noise.lfsr & 0x7F;
+        let mut steps = 0;
+        loop {
+            noise.step_lfsr();
+            steps += 1;
+            if noise.lfsr & 0x7F == start {
+                break;
+            }
+        }
+
+        assert_eq!(steps, 127);
+    }
+
+    #[test]
+    fn it_should_compute_period_from_divisor_and_shift() {
+        let mut noise = Noise::new();
+        noise.write(3, 0x23);
+
+        assert_eq!(noise.period(), 48 << 2);
+    }
+
+    #[test]
+    fn it_should_reset_lfsr_on_trigger() {
+        let mut noise = Noise::new();
+        noise.write(2, 0xF0);
+        noise.lfsr = 0x1234;
+        noise.write(4, 0x80);
+
+        assert_eq!(noise.lfsr, 0x7FFF);
+        assert!(noise.enabled);
+    }
+}
@@ -0,0 +1,205 @@
+#![allow(dead_code)]
+use std::ffi::{c_char, c_int, c_long, c_uint, c_ulong, c_void, CStr};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use libloading::Library;
+
+use crate::apu::ring::Consumer;
+use crate::apu::sound::Apu;
+use crate::logging::Level;
+
+const LIBRARY: &str = "libasound.so.2";
+const DEVICE: &CStr = c"default";
+const STREAM_PLAYBACK: c_int = 0;
+const FORMAT_S16_LE: c_int = 2;
+const ACCESS_RW_INTERLEAVED: c_int = 3;
+const CHANNELS: usize = 2;
+// Buffered by the device on top of the ring, kept short so audio sync stays responsive
+const LATENCY_MICROS: c_uint = 40_000;
+// Frames handed to the device per write, silence stands in for what the ring lacks
+const PERIOD_FRAMES: usize = 256;
+
+type Open = unsafe extern "C" fn(*mut *mut c_void, *const c_char, c_int, c_int) -> c_int;
+type SetParams =
+    unsafe extern "C" fn(*mut c_void, c_int, c_int, c_uint, c_uint, c_int, c_uint) -> c_int;
+type WriteInterleaved = unsafe extern "C" fn(*mut c_void, *const c_void, c_ulong) -> c_long;
+type Recover = unsafe extern "C" fn(*mut c_void, c_int, c_int) -> c_int;
+type Close = unsafe extern "C" fn(*mut c_void) -> c_int;
+type StrError = unsafe extern "C" fn(c_int) -> *const c_char;
+
+// The few ALSA functions needed, looked up at run time so building needs no ALSA
+// headers and a machine without ALSA still runs, only without sound
+struct Alsa {
+    open: Open,
+    set_params: SetParams,
+    write: WriteInterleaved,
+    recover: Recover,
+    close: Close,
+    strerror: StrError,
+    // Keeps the functions above loaded
+    _library: Library,
+}
+
+impl Alsa {
+    fn load() -> Result<Alsa, String> {
+        let library = unsafe { Library::new(LIBRARY) }
+            .map_err(|error| format!("cannot load {}: {}", LIBRARY, error))?;
+        Ok(Alsa {
+            open: symbol(&library, b"snd_pcm_open\0")?,
+            set_params: symbol(&library, b"snd_pcm_set_params\0")?,
+            write: symbol(&library, b"snd_pcm_writei\0")?,
+            recover: symbol(&library, b"snd_pcm_recover\0")?,
+            close: symbol(&library, b"snd_pcm_close\0")?,
+            strerror: symbol(&library, b"snd_strerror\0")?,
+            _library: library,
+        })
+    }
+
+    fn error(&self, code: c_int) -> String {
+        unsafe { CStr::from_ptr((self.strerror)(code)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+fn symbol<T: Copy>(library: &Library, name: &[u8]) -> Result<T, String> {
+    unsafe { library.get::<T>(name) }
+        .map(|symbol| *symbol)
+        .map_err(|error| error.to_string())
+}
+
+// An open playback device, closed on drop
+struct Pcm {
+    alsa: Alsa,
+    handle: *mut c_void,
+}
+
+// Only the audio thread uses the handle once the device is open
+unsafe impl Send for Pcm {}
+
+impl Pcm {
+    fn open(sample_rate: u32) -> Result<Pcm, String> {
+        let alsa = Alsa::load()?;
+        let mut handle = ptr::null_mut();
+        let code = unsafe { (alsa.open)(&mut handle, DEVICE.as_ptr(), STREAM_PLAYBACK, 0) };
+        if code < 0 {
+            return Err(alsa.error(code));
+        }
+        let pcm = Pcm { alsa, handle };
+        // ALSA resamples when the device runs at another rate
+        let code = unsafe {
+            (pcm.alsa.set_params)(
+                pcm.handle,
+                FORMAT_S16_LE,
+                ACCESS_RW_INTERLEAVED,
+                CHANNELS as c_uint,
+                sample_rate,
+                1,
+                LATENCY_MICROS,
+            )
+        };
+        if code < 0 {
+            return Err(pcm.alsa.error(code));
+        }
+        Ok(pcm)
+    }
+
+    // Blocks until the device took every frame, an underrun is recovered from
+    fn write(&self, samples: &[i16]) -> Result<(), String> {
+        let mut remaining = samples;
+        while !remaining.is_empty() {
+            let frames = (remaining.len() / CHANNELS) as c_ulong;
+            let written =
+                unsafe { (self.alsa.write)(self.handle, remaining.as_ptr().cast(), frames) };
+            if written < 0 {
+                let code = unsafe { (self.alsa.recover)(self.handle, written as c_int, 1) };
+                if code < 0 {
+                    return Err(self.alsa.error(code));
+                }
+                continue;
+            }
+            remaining = &remaining[written as usize * CHANNELS..];
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Pcm {
+    fn drop(&mut self) {
+        unsafe { (self.alsa.close)(self.handle) };
+    }
+}
+
+// Plays the APU output on the default ALSA device from a thread of its own. The device
+// drains the ring buffer at its own pace, which is what audio sync waits for
+pub struct AudioOutput {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AudioOutput {
+    // The APU keeps its ring buffer unless the device opens, so audio sync can still
+    // tell that nothing drains it
+    pub fn open(apu: &mut Apu) -> Result<AudioOutput, String> {
+        let pcm = Pcm::open(apu.sample_rate)?;
+        let consumer = apu
+            .take_consumer()
+            .ok_or_else(|| "the sample buffer is already played".to_string())?;
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = Arc::clone(&running);
+            thread::spawn(move || play(&pcm, &consumer, &running))
+        };
+        Ok(AudioOutput {
+            running,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for AudioOutput {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// The ring only ever holds whole frames, so reading an even count keeps the channels
+// in step; a paused or muted emulator leaves the ring empty and the device plays silence
+fn play(pcm: &Pcm, consumer: &Consumer, running: &AtomicBool) {
+    let mut samples = [0.0; PERIOD_FRAMES * CHANNELS];
+    let mut period = [0; PERIOD_FRAMES * CHANNELS];
+    while running.load(Ordering::Relaxed) {
+        let read = consumer.pop_slice(&mut samples);
+        samples[read..].fill(0.0);
+        to_pcm(&samples, &mut period);
+        if let Err(error) = pcm.write(&period) {
+            log!(Level::Warn, "audio output stopped: {}", error);
+            return;
+        }
+    }
+}
+
+fn to_pcm(samples: &[f32], output: &mut [i16]) {
+    for (sample, output) in samples.iter().zip(output) {
+        *output = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_convert_samples_to_clamped_16_bit() {
+        let mut output = [0; 5];
+        to_pcm(&[0.0, 1.0, -1.0, 0.5, -3.0], &mut output);
+
+        assert_eq!(output, [0, 32767, -32767, 16383, -32767]);
+    }
+}
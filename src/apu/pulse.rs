@@ -0,0 +1,261 @@
+#![allow(dead_code)]
+use crate::apu::channel::{Envelope, LengthCounter};
+
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    pub period: u8,
+    pub negate: bool,
+    pub shift: u8,
+    pub timer: u8,
+    pub shadow: u16,
+    pub enabled: bool,
+}
+
+impl Sweep {
+    pub fn new() -> Sweep {
+        Sweep {
+            period: 0,
+            negate: false,
+            shift: 0,
+            timer: 0,
+            shadow: 0,
+            enabled: false,
+        }
+    }
+
+    fn reload_timer(&mut self) {
+        // A period of 0 is treated as 8
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pulse {
+    pub enabled: bool,
+    pub dac_enabled: bool,
+    pub duty: usize,
+    pub duty_step: usize,
+    pub frequency: u16,
+    pub timer: u32,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+    pub sweep: Option<Sweep>,
+}
+
+impl Pulse {
+    pub fn new(with_sweep: bool) -> Pulse {
+        Pulse {
+            enabled: false,
+            dac_enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep: if with_sweep { Some(Sweep::new()) } else { None },
+        }
+    }
+
+    // Register 0 is NR10 (sweep) and is ignored for the second channel
+    pub fn write(&mut self, register: usize, value: u8) {
+        match register {
+            0 => {
+                if let Some(sweep) = self.sweep.as_mut() {
+                    sweep.period = (value >> 4) & 0x7;
+                    sweep.negate = value & 0x08 == 0x08;
+                    sweep.shift = value & 0x7;
+                }
+            }
+            1 => {
+                self.duty = (value >> 6) as usize;
+                self.length.load((value & 0x3F) as u16);
+            }
+            2 => {
+                self.envelope.write(value);
+                self.dac_enabled = Envelope::dac_enabled(value);
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | (((value & 0x7) as u16) << 8);
+                self.length.enabled = value & 0x40 == 0x40;
+                if value & 0x80 == 0x80 {
+                    self.trigger();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger();
+        self.timer = self.period();
+        self.envelope.trigger();
+        let frequency = self.frequency;
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.shadow = frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            if sweep.shift != 0 {
+                self.next_sweep_frequency();
+            }
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    pub fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) & 0x7;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.enabled && DUTY_PATTERNS[self.duty][self.duty_step] == 1 {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let Some(sweep) = self.sweep.as_mut() else {
+            return;
+        };
+        if sweep.timer > 0 {
+            sweep.timer -= 1;
+        }
+        if sweep.timer != 0 {
+            return;
+        }
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+        let shift = sweep.shift;
+        let frequency = self.next_sweep_frequency();
+        if frequency <= 2047 && shift != 0 {
+            self.frequency = frequency;
+            if let Some(sweep) = self.sweep.as_mut() {
+                sweep.shadow = frequency;
+            }
+            // The new frequency is checked again for overflow right away
+            self.next_sweep_frequency();
+        }
+    }
+
+    // Going over 2047 disables the channel
+    fn next_sweep_frequency(&mut self) -> u16 {
+        let Some(sweep) = self.sweep.as_ref() else {
+            return self.frequency;
+        };
+        let delta = sweep.shadow >> sweep.shift;
+        let frequency = if sweep.negate {
+            sweep.shadow.wrapping_sub(delta)
+        } else {
+            sweep.shadow + delta
+        };
+        if frequency > 2047 {
+            self.enabled = false;
+        }
+        frequency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triggered_pulse(with_sweep: bool) -> Pulse {
+        let mut pulse = Pulse::new(with_sweep);
+        pulse.write(1, 0x80);
+        pulse.write(2, 0xF0);
+        pulse.write(3, 0x00);
+        pulse.write(4, 0x87);
+        pulse
+    }
+
+    #[test]
+    fn it_should_follow_the_duty_pattern() {
+        let mut pulse = triggered_pulse(false);
+        let mut pattern = Vec::new();
+        for _ in 0..8 {
+            for _ in 0..pulse.period() {
+                pulse.tick();
+            }
+            pattern.push(pulse.output());
+        }
+
+        assert_eq!(pattern, vec![0, 0, 0, 0, 15, 15, 15, 15]);
+    }
+
+    #[test]
+    fn it_should_not_enable_channel_with_dac_off() {
+        let mut pulse = Pulse::new(false);
+        pulse.write(2, 0x00);
+        pulse.write(4, 0x80);
+
+        assert!(!pulse.enabled);
+    }
+
+    #[test]
+    fn it_should_disable_channel_when_length_expires() {
+        let mut pulse = triggered_pulse(false);
+        pulse.write(1, 0x3F);
+        pulse.write(4, 0x40);
+        pulse.clock_length();
+
+        assert!(!pulse.enabled);
+    }
+
+    #[test]
+    fn it_should_raise_frequency_with_sweep() {
+        let mut pulse = Pulse::new(true);
+        pulse.write(0, 0x11);
+        pulse.write(2, 0xF0);
+        pulse.write(3, 0x00);
+        pulse.write(4, 0x81);
+        pulse.clock_sweep();
+
+        assert_eq!(pulse.frequency, 0x100 + 0x80);
+        assert!(pulse.enabled);
+    }
+
+    #[test]
+    fn it_should_disable_channel_on_sweep_overflow() {
+        let mut pulse = Pulse::new(true);
+        pulse.write(0, 0x11);
+        pulse.write(2, 0xF0);
+        pulse.write(3, 0xFF);
+        pulse.write(4, 0x87);
+
+        assert!(!pulse.enabled);
+    }
+}
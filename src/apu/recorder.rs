@@ -0,0 +1,127 @@
+#![allow(dead_code)]
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::apu::blip::BlipBuffer;
+use crate::apu::sound::{CHANNELS, CLOCK_HZ};
+use crate::apu::wav::WavWriter;
+
+pub const STEM_NAMES: [&str; CHANNELS] = ["pulse1", "pulse2", "wave", "noise"];
+
+// One channel panned and scaled like in the mix, with its own band-limited buffers
+#[derive(Debug)]
+struct Stem {
+    left: BlipBuffer,
+    right: BlipBuffer,
+    last_output: (f32, f32),
+    wav: WavWriter<BufWriter<File>>,
+}
+
+// Writes the mixed output to a WAV file and optionally each channel next to it,
+// "music.wav" gets "music.pulse1.wav", "music.pulse2.wav", "music.wave.wav" and "music.noise.wav"
+#[derive(Debug)]
+pub struct Recorder {
+    mix: WavWriter<BufWriter<File>>,
+    stems: Vec<Stem>,
+    error: Option<io::Error>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, sample_rate: u32, stems: bool) -> io::Result<Recorder> {
+        let mix = WavWriter::create(path, sample_rate)?;
+        let stems = if stems {
+            STEM_NAMES
+                .iter()
+                .map(|name| {
+                    Ok(Stem {
+                        left: BlipBuffer::new(CLOCK_HZ as f64, sample_rate as f64),
+                        right: BlipBuffer::new(CLOCK_HZ as f64, sample_rate as f64),
+                        last_output: (0.0, 0.0),
+                        wav: WavWriter::create(stem_path(path, name), sample_rate)?,
+                    })
+                })
+                .collect::<io::Result<Vec<Stem>>>()?
+        } else {
+            Vec::new()
+        };
+        Ok(Recorder {
+            mix,
+            stems,
+            error: None,
+        })
+    }
+
+    pub fn add_outputs(&mut self, clock: u32, outputs: &[(f32, f32); CHANNELS]) {
+        for (stem, (left, right)) in self.stems.iter_mut().zip(outputs) {
+            if *left != stem.last_output.0 {
+                stem.left.add_delta(clock, left - stem.last_output.0);
+            }
+            if *right != stem.last_output.1 {
+                stem.right.add_delta(clock, right - stem.last_output.1);
+            }
+            stem.last_output = (*left, *right);
+        }
+    }
+
+    // Stems are resampled here while the mix arrives already interleaved
+    pub fn end_frame(&mut self, clocks: u32, mix: &[f32]) {
+        let result = self.write_frame(clocks, mix);
+        if let Err(error) = result {
+            self.error.get_or_insert(error);
+        }
+    }
+
+    fn write_frame(&mut self, clocks: u32, mix: &[f32]) -> io::Result<()> {
+        for frame in mix.chunks_exact(2) {
+            self.mix.write_frame(frame[0], frame[1])?;
+        }
+        for stem in self.stems.iter_mut() {
+            stem.left.end_frame(clocks);
+            stem.right.end_frame(clocks);
+            let mut left = Vec::new();
+            let mut right = Vec::new();
+            stem.left.read_samples(&mut left);
+            stem.right.read_samples(&mut right);
+            for (left, right) in left.into_iter().zip(right) {
+                stem.wav.write_frame(left, right)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_rate(&mut self, sample_rate: f64) {
+        for stem in self.stems.iter_mut() {
+            stem.left.set_rates(CLOCK_HZ as f64, sample_rate);
+            stem.right.set_rates(CLOCK_HZ as f64, sample_rate);
+        }
+    }
+
+    // Reports the first write error that happened while recording
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.mix.finish()?;
+        for stem in self.stems {
+            stem.wav.finish()?;
+        }
+        Ok(())
+    }
+}
+
+pub fn stem_path(path: &Path, name: &str) -> PathBuf {
+    path.with_extension(format!("{}.wav", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_name_stems_after_the_mix() {
+        let path = stem_path(Path::new("out/music.wav"), "pulse1");
+
+        assert_eq!(path, PathBuf::from("out/music.pulse1.wav"));
+    }
+}
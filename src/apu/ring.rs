@@ -0,0 +1,166 @@
+#![allow(dead_code)]
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Single-producer single-consumer queue of samples, each slot stores the bits of an f32
+#[derive(Debug)]
+struct Shared {
+    slots: Vec<AtomicU32>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+#[derive(Debug)]
+pub struct Producer {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+pub struct Consumer {
+    shared: Arc<Shared>,
+}
+
+pub fn ring_buffer(capacity: usize) -> (Producer, Consumer) {
+    // One slot stays empty to tell a full buffer from an empty one
+    let shared = Arc::new(Shared {
+        slots: (0..capacity + 1).map(|_| AtomicU32::new(0)).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (
+        Producer {
+            shared: Arc::clone(&shared),
+        },
+        Consumer { shared },
+    )
+}
+
+impl Shared {
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        (head + self.slots.len() - tail) % self.slots.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len() - 1
+    }
+}
+
+impl Producer {
+    // Returns the number of samples written, the rest is dropped when the buffer is full
+    pub fn push_slice(&self, samples: &[f32]) -> usize {
+        let size = self.shared.slots.len();
+        let mut head = self.shared.head.load(Ordering::Relaxed);
+        let tail = self.shared.tail.load(Ordering::Acquire);
+        let mut written = 0;
+        for sample in samples {
+            let next = (head + 1) % size;
+            if next == tail {
+                break;
+            }
+            self.shared.slots[head].store(sample.to_bits(), Ordering::Relaxed);
+            head = next;
+            written += 1;
+        }
+        self.shared.head.store(head, Ordering::Release);
+        written
+    }
+
+    pub fn fill_ratio(&self) -> f64 {
+        self.shared.len() as f64 / self.shared.capacity() as f64
+    }
+}
+
+impl Consumer {
+    pub fn pop_slice(&self, output: &mut [f32]) -> usize {
+        let size = self.shared.slots.len();
+        let head = self.shared.head.load(Ordering::Acquire);
+        let mut tail = self.shared.tail.load(Ordering::Relaxed);
+        let mut read = 0;
+        for sample in output.iter_mut() {
+            if tail == head {
+                break;
+            }
+            *sample = f32::from_bits(self.shared.slots[tail].load(Ordering::Relaxed));
+            tail = (tail + 1) % size;
+            read += 1;
+        }
+        self.shared.tail.store(tail, Ordering::Release);
+        read
+    }
+
+    pub fn drain(&self) -> Vec<f32> {
+        let mut samples = vec![0.0; self.len()];
+        let read = self.pop_slice(&mut samples);
+        samples.truncate(read);
+        samples
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn it_should_pop_samples_in_order() {
+        let (producer, consumer) = ring_buffer(8);
+        producer.push_slice(&[0.1, 0.2, 0.3]);
+        let mut output = [0.0; 2];
+
+        assert_eq!(consumer.pop_slice(&mut output), 2);
+        assert_eq!(output, [0.1, 0.2]);
+        assert_eq!(consumer.drain(), vec![0.3]);
+    }
+
+    #[test]
+    fn it_should_drop_samples_when_full() {
+        let (producer, consumer) = ring_buffer(4);
+
+        assert_eq!(producer.push_slice(&[1.0; 6]), 4);
+        assert_eq!(producer.fill_ratio(), 1.0);
+        assert_eq!(consumer.len(), 4);
+    }
+
+    #[test]
+    fn it_should_wrap_around() {
+        let (producer, consumer) = ring_buffer(4);
+        let mut output = [0.0; 3];
+        producer.push_slice(&[1.0, 2.0, 3.0]);
+        consumer.pop_slice(&mut output);
+        producer.push_slice(&[4.0, 5.0, 6.0]);
+
+        assert_eq!(consumer.drain(), vec![4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn it_should_move_samples_across_threads() {
+        let (producer, consumer) = ring_buffer(64);
+        let writer = thread::spawn(move || {
+            let mut sent = 0;
+            while sent < 1000 {
+                let batch: Vec<f32> = (sent..(sent + 10).min(1000)).map(|n| n as f32).collect();
+                sent += producer.push_slice(&batch);
+            }
+        });
+        let mut received = Vec::new();
+        while received.len() < 1000 {
+            received.extend(consumer.drain());
+        }
+        writer.join().unwrap();
+
+        assert!(received
+            .iter()
+            .enumerate()
+            .all(|(n, sample)| *sample == n as f32));
+    }
+}
@@ -0,0 +1,516 @@
+#![allow(dead_code)]
+use std::io;
+use std::path::Path;
+
+use crate::apu::blip::BlipBuffer;
+use crate::apu::noise::Noise;
+use crate::apu::pulse::Pulse;
+use crate::apu::recorder::Recorder;
+use crate::apu::ring::{ring_buffer, Consumer, Producer};
+use crate::apu::wave::{Wave, WAVE_RAM_SIZE};
+
+pub const CLOCK_HZ: u32 = 4_194_304;
+pub const CHANNELS: usize = 4;
+
+// The frame sequencer runs at 512 Hz
+const FRAME_SEQUENCER_PERIOD: u32 = CLOCK_HZ / 512;
+const REGISTERS: usize = 0x20;
+// Synthesized audio is handed to the ring buffer every 4096 cycles, about 1 ms
+const FRAME_CLOCKS: u32 = 4096;
+// The output rate moves by at most 0.5% to keep the ring buffer half full
+const MAX_RATE_DELTA: f64 = 0.005;
+// The ring holds a quarter of a second of stereo samples
+const BUFFER_SECONDS_DIVISOR: u32 = 4;
+
+// Bits that always read back as 1, from NR10 (0xFF10) to 0xFF2F
+const READ_MASKS: [u8; REGISTERS] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+#[derive(Debug)]
+pub struct Apu {
+    pub pulse1: Pulse,
+    pub pulse2: Pulse,
+    pub wave: Wave,
+    pub noise: Noise,
+    pub registers: [u8; REGISTERS],
+    pub powered: bool,
+    pub frame_step: u8,
+    pub frame_timer: u32,
+    pub sample_rate: u32,
+    pub dynamic_rate: bool,
+    // Set while the emulation does not run at 1x, the WAV recording still gets everything
+    pub muted: bool,
+    pub clock: u32,
+    pub last_output: (f32, f32),
+    pub capacitors: [f32; 2],
+    // Capacitor charge kept per output sample, depends only on the sample rate
+    charge: f32,
+    // Samples the ring buffer had no room for
+    pub dropped: u64,
+    pub left: BlipBuffer,
+    pub right: BlipBuffer,
+    pub recorder: Option<Recorder>,
+    producer: Producer,
+    consumer: Option<Consumer>,
+}
+
+impl Apu {
+    pub fn new(sample_rate: u32) -> Apu {
+        let (producer, consumer) = sample_ring(sample_rate);
+        let mut apu = Apu {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            registers: [0; REGISTERS],
+            powered: true,
+            frame_step: 0,
+            frame_timer: 0,
+            sample_rate,
+            dynamic_rate: false,
+            muted: false,
+            clock: 0,
+            last_output: (0.0, 0.0),
+            capacitors: [0.0; 2],
+            charge: charge(sample_rate),
+            dropped: 0,
+            left: BlipBuffer::new(CLOCK_HZ as f64, sample_rate as f64),
+            right: BlipBuffer::new(CLOCK_HZ as f64, sample_rate as f64),
+            recorder: None,
+            producer,
+            consumer: Some(consumer),
+        };
+        apu.write(0xFF24, 0x77);
+        apu.write(0xFF25, 0xF3);
+        apu
+    }
+
+    // Replaces the ring buffer, a consumer taken before has to be taken again
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        let (producer, consumer) = sample_ring(sample_rate);
+        self.sample_rate = sample_rate;
+        self.charge = charge(sample_rate);
+        self.producer = producer;
+        self.consumer = Some(consumer);
+        self.left = BlipBuffer::new(CLOCK_HZ as f64, sample_rate as f64);
+        self.right = BlipBuffer::new(CLOCK_HZ as f64, sample_rate as f64);
+        self.clock = 0;
+    }
+
+    // Hands the read side of the sample ring buffer to the host audio thread
+    pub fn take_consumer(&mut self) -> Option<Consumer> {
+        self.consumer.take()
+    }
+
+    // True once the host audio thread took the consumer and drains the buffer
+    pub fn is_streaming(&self) -> bool {
+        self.consumer.is_none()
+    }
+
+    // How full the sample ring buffer is, from 0 to 1
+    pub fn buffer_fill(&self) -> f64 {
+        self.producer.fill_ratio()
+    }
+
+    pub fn start_recording(&mut self, path: &Path, stems: bool) -> io::Result<()> {
+        self.stop_recording()?;
+        self.recorder = Some(Recorder::create(path, self.sample_rate, stems)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn read(&self, address: usize) -> u8 {
+        match address {
+            0xFF26 => {
+                let power = if self.powered { 0x80 } else { 0 };
+                0x70 | power | self.channel_status()
+            }
+            0xFF10..=0xFF2F => self.registers[address - 0xFF10] | READ_MASKS[address - 0xFF10],
+            0xFF30..=0xFF3F => self.wave.ram[address - 0xFF30],
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: usize, value: u8) {
+        match address {
+            0xFF26 => self.set_power(value & 0x80 == 0x80),
+            0xFF30..=0xFF3F => self.wave.ram[address - 0xFF30] = value,
+            // Registers are read-only while the APU is off
+            _ if !self.powered => {}
+            0xFF10..=0xFF2F => {
+                self.registers[address - 0xFF10] = value;
+                match address {
+                    0xFF10..=0xFF14 => self.pulse1.write(address - 0xFF10, value),
+                    0xFF15..=0xFF19 => self.pulse2.write(address - 0xFF15, value),
+                    0xFF1A..=0xFF1E => self.wave.write(address - 0xFF1A, value),
+                    0xFF1F..=0xFF23 => self.noise.write(address - 0xFF1F, value),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Turning the APU off clears every register but leaves wave RAM untouched
+    fn set_power(&mut self, on: bool) {
+        if self.powered && !on {
+            let ram = self.wave.ram;
+            self.pulse1 = Pulse::new(true);
+            self.pulse2 = Pulse::new(false);
+            self.wave = Wave::new();
+            self.wave.ram = ram;
+            self.noise = Noise::new();
+            self.registers = [0; REGISTERS];
+        } else if !self.powered && on {
+            self.frame_step = 0;
+        }
+        self.powered = on;
+    }
+
+    fn channel_status(&self) -> u8 {
+        [
+            self.pulse1.enabled,
+            self.pulse2.enabled,
+            self.wave.enabled,
+            self.noise.enabled,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |status, (channel, enabled)| {
+            status | ((*enabled as u8) << channel)
+        })
+    }
+
+    pub fn nr50(&self) -> u8 {
+        self.registers[0x14]
+    }
+
+    pub fn nr51(&self) -> u8 {
+        self.registers[0x15]
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            if self.powered {
+                self.tick_channels();
+            }
+            let panned = self.pan(&self.channel_outputs());
+            let (left, right) = sum(&panned);
+            if let Some(recorder) = &mut self.recorder {
+                recorder.add_outputs(self.clock, &panned);
+            }
+            if left != self.last_output.0 {
+                self.left.add_delta(self.clock, left - self.last_output.0);
+            }
+            if right != self.last_output.1 {
+                self.right.add_delta(self.clock, right - self.last_output.1);
+            }
+            self.last_output = (left, right);
+            self.clock += 1;
+            if self.clock == FRAME_CLOCKS {
+                self.end_frame();
+            }
+        }
+    }
+
+    fn tick_channels(&mut self) {
+        self.frame_timer += 1;
+        if self.frame_timer == FRAME_SEQUENCER_PERIOD {
+            self.frame_timer = 0;
+            self.step_frame_sequencer();
+        }
+        self.pulse1.tick();
+        self.pulse2.tick();
+        self.wave.tick();
+        self.noise.tick();
+    }
+
+    // Length on even steps, sweep on steps 2 and 6, envelope on step 7
+    fn step_frame_sequencer(&mut self) {
+        if self.frame_step.is_multiple_of(2) {
+            self.pulse1.clock_length();
+            self.pulse2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.pulse1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.pulse1.clock_envelope();
+            self.pulse2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+        self.frame_step = (self.frame_step + 1) & 0x7;
+    }
+
+    // DAC outputs of pulse 1, pulse 2, wave and noise between -1.0 and 1.0
+    pub fn channel_outputs(&self) -> [f32; CHANNELS] {
+        [
+            dac(self.pulse1.output(), self.pulse1.dac_enabled),
+            dac(self.pulse2.output(), self.pulse2.dac_enabled),
+            dac(self.wave.output(), self.wave.dac_enabled),
+            dac(self.noise.output(), self.noise.dac_enabled),
+        ]
+    }
+
+    // Left and right contribution of each channel with NR51 panning and NR50 volume
+    pub fn pan(&self, outputs: &[f32; CHANNELS]) -> [(f32, f32); CHANNELS] {
+        let panning = self.nr51();
+        let left_volume = (((self.nr50() >> 4) & 0x7) + 1) as f32 / 8.0;
+        let right_volume = ((self.nr50() & 0x7) + 1) as f32 / 8.0;
+        let mut panned = [(0.0, 0.0); CHANNELS];
+        for (channel, output) in outputs.iter().enumerate() {
+            if panning & (0x10 << channel) != 0 {
+                panned[channel].0 = output / CHANNELS as f32 * left_volume;
+            }
+            if panning & (0x01 << channel) != 0 {
+                panned[channel].1 = output / CHANNELS as f32 * right_volume;
+            }
+        }
+        panned
+    }
+
+    pub fn mix(&self, outputs: &[f32; CHANNELS]) -> (f32, f32) {
+        sum(&self.pan(outputs))
+    }
+
+    fn end_frame(&mut self) {
+        let clocks = self.clock;
+        self.left.end_frame(clocks);
+        self.right.end_frame(clocks);
+        self.clock = 0;
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        self.left.read_samples(&mut left);
+        self.right.read_samples(&mut right);
+        let mut samples = Vec::with_capacity(left.len() * 2);
+        for (left, right) in left.into_iter().zip(right) {
+            samples.push(self.high_pass(0, left));
+            samples.push(self.high_pass(1, right));
+        }
+        if !self.muted {
+            let written = self.producer.push_slice(&samples);
+            self.dropped += (samples.len() - written) as u64;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.end_frame(clocks, &samples);
+        }
+        if self.dynamic_rate {
+            self.adjust_rate();
+        }
+    }
+
+    // Removes the DC offset like the capacitors on the output of the real hardware
+    fn high_pass(&mut self, side: usize, sample: f32) -> f32 {
+        let output = sample - self.capacitors[side];
+        self.capacitors[side] = sample - output * self.charge;
+        output
+    }
+
+    // Produces slightly fewer samples when the buffer fills up and more when it drains
+    fn adjust_rate(&mut self) {
+        let fill = self.producer.fill_ratio();
+        let rate = self.sample_rate as f64 * (1.0 + MAX_RATE_DELTA * (1.0 - 2.0 * fill));
+        self.left.set_rates(CLOCK_HZ as f64, rate);
+        self.right.set_rates(CLOCK_HZ as f64, rate);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.set_rate(rate);
+        }
+    }
+
+    // Interleaved left and right samples produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        match &self.consumer {
+            Some(consumer) => consumer.drain(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn wave_ram(&self) -> [u8; WAVE_RAM_SIZE] {
+        self.wave.ram
+    }
+}
+
+fn sum(panned: &[(f32, f32); CHANNELS]) -> (f32, f32) {
+    panned.iter().fold((0.0, 0.0), |(left, right), channel| {
+        (left + channel.0, right + channel.1)
+    })
+}
+
+// The capacitors keep 0.999958 of their charge per clock
+fn charge(sample_rate: u32) -> f32 {
+    0.999958f32.powf(CLOCK_HZ as f32 / sample_rate as f32)
+}
+
+fn sample_ring(sample_rate: u32) -> (Producer, Consumer) {
+    ring_buffer((sample_rate / BUFFER_SECONDS_DIVISOR) as usize * 2)
+}
+
+fn dac(digital: u8, enabled: bool) -> f32 {
+    if enabled {
+        digital as f32 / 7.5 - 1.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apu::recorder::{stem_path, STEM_NAMES};
+
+    #[test]
+    fn it_should_read_registers_through_masks() {
+        let mut apu = Apu::new(48_000);
+        apu.write(0xFF11, 0x80);
+        apu.write(0xFF13, 0x12);
+
+        assert_eq!(apu.read(0xFF11), 0xBF);
+        assert_eq!(apu.read(0xFF13), 0xFF);
+        assert_eq!(apu.read(0xFF15), 0xFF);
+    }
+
+    #[test]
+    fn it_should_report_channel_status_in_nr52() {
+        let mut apu = Apu::new(48_000);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0x80);
+
+        assert_eq!(apu.read(0xFF26), 0xF1);
+    }
+
+    #[test]
+    fn it_should_clear_registers_on_power_off() {
+        let mut apu = Apu::new(48_000);
+        apu.write(0xFF30, 0xAB);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF26, 0x00);
+
+        assert_eq!(apu.read(0xFF12), 0x00);
+        assert_eq!(apu.read(0xFF26), 0x70);
+        assert_eq!(apu.read(0xFF30), 0xAB);
+    }
+
+    #[test]
+    fn it_should_ignore_writes_while_powered_off() {
+        let mut apu = Apu::new(48_000);
+        apu.write(0xFF26, 0x00);
+        apu.write(0xFF24, 0x77);
+
+        assert_eq!(apu.read(0xFF24), 0x00);
+    }
+
+    #[test]
+    fn it_should_stop_channel_after_length_with_frame_sequencer() {
+        let mut apu = Apu::new(48_000);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF11, 0x3E);
+        apu.write(0xFF14, 0xC0);
+        apu.tick(FRAME_SEQUENCER_PERIOD * 3);
+
+        assert_eq!(apu.read(0xFF26) & 0x1, 0);
+    }
+
+    #[test]
+    fn it_should_produce_samples_at_host_rate() {
+        let mut apu = Apu::new(48_000);
+        apu.tick(CLOCK_HZ / 8);
+
+        assert_eq!(apu.take_samples().len(), 6000 * 2);
+    }
+
+    #[test]
+    fn it_should_hand_samples_to_the_consumer() {
+        let mut apu = Apu::new(48_000);
+        let consumer = apu.take_consumer().unwrap();
+        apu.tick(FRAME_CLOCKS * 4);
+
+        assert!(!consumer.is_empty());
+        assert!(apu.take_samples().is_empty());
+    }
+
+    #[test]
+    fn it_should_count_samples_dropped_by_a_full_ring() {
+        let mut apu = Apu::new(48_000);
+        apu.tick(CLOCK_HZ / 2);
+
+        assert_eq!(apu.take_samples().len(), 12_000 * 2);
+        assert_eq!(apu.dropped, 12_000 * 2);
+    }
+
+    #[test]
+    fn it_should_not_hand_samples_over_while_muted() {
+        let mut apu = Apu::new(48_000);
+        let consumer = apu.take_consumer().unwrap();
+        apu.muted = true;
+        apu.tick(FRAME_CLOCKS * 4);
+
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn it_should_slow_output_rate_when_buffer_fills() {
+        let mut apu = Apu::new(48_000);
+        apu.dynamic_rate = true;
+        apu.tick(CLOCK_HZ / 8);
+
+        assert!(apu.left.factor < 48_000.0 / CLOCK_HZ as f64);
+    }
+
+    #[test]
+    fn it_should_output_a_square_wave_without_dc_offset() {
+        let mut apu = Apu::new(48_000);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF13, 0x00);
+        apu.write(0xFF14, 0x86);
+        apu.tick(CLOCK_HZ / 16);
+        let samples = apu.take_samples();
+        let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+        let average = left[1000..].iter().sum::<f32>() / (left.len() - 1000) as f32;
+
+        assert!(left.iter().any(|sample| *sample > 0.1));
+        assert!(average.abs() < 0.05);
+    }
+
+    #[test]
+    fn it_should_pan_channels_with_nr51() {
+        let mut apu = Apu::new(48_000);
+        apu.write(0xFF25, 0x01);
+        let (left, right) = apu.mix(&[1.0, 0.0, 0.0, 0.0]);
+
+        assert_eq!(left, 0.0);
+        assert_eq!(right, 0.25);
+    }
+
+    #[test]
+    fn it_should_record_mix_and_channel_stems() {
+        let path = std::env::temp_dir().join(format!("apu-{}.wav", std::process::id()));
+        let mut apu = Apu::new(48_000);
+        apu.start_recording(&path, true).unwrap();
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0x86);
+        apu.tick(CLOCK_HZ / 16);
+        apu.stop_recording().unwrap();
+        let mix = std::fs::read(&path).unwrap();
+        let pulse1 = std::fs::read(stem_path(&path, "pulse1")).unwrap();
+        let pulse2 = std::fs::read(stem_path(&path, "pulse2")).unwrap();
+        for name in STEM_NAMES {
+            std::fs::remove_file(stem_path(&path, name)).unwrap();
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mix.len(), pulse1.len());
+        assert!(mix[44..].iter().any(|byte| *byte != 0));
+        assert!(pulse1[44..].iter().any(|byte| *byte != 0));
+        assert!(pulse2[44..].iter().all(|byte| *byte == 0));
+    }
+}
@@ -0,0 +1,93 @@
+#![allow(dead_code)]
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const HEADER_SIZE: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+const STEREO: u16 = 2;
+const FRAME_BYTES: u32 = (BITS_PER_SAMPLE / 8 * STEREO) as u32;
+
+// 16-bit stereo PCM, the RIFF and data sizes are patched in when the file is finished
+#[derive(Debug)]
+pub struct WavWriter<W: Write + Seek> {
+    pub output: W,
+    pub frames: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+        WavWriter::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut output: W, sample_rate: u32) -> io::Result<WavWriter<W>> {
+        output.write_all(b"RIFF")?;
+        output.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        output.write_all(b"WAVEfmt ")?;
+        output.write_all(&16u32.to_le_bytes())?;
+        output.write_all(&1u16.to_le_bytes())?;
+        output.write_all(&STEREO.to_le_bytes())?;
+        output.write_all(&sample_rate.to_le_bytes())?;
+        output.write_all(&(sample_rate * FRAME_BYTES).to_le_bytes())?;
+        output.write_all(&(FRAME_BYTES as u16).to_le_bytes())?;
+        output.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        output.write_all(b"data")?;
+        output.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter { output, frames: 0 })
+    }
+
+    pub fn write_frame(&mut self, left: f32, right: f32) -> io::Result<()> {
+        self.output.write_all(&to_pcm(left).to_le_bytes())?;
+        self.output.write_all(&to_pcm(right).to_le_bytes())?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.frames * FRAME_BYTES;
+        self.output.seek(SeekFrom::Start(4))?;
+        self.output
+            .write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.output.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.output.write_all(&data_size.to_le_bytes())?;
+        self.output.seek(SeekFrom::End(0))?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+fn to_pcm(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn it_should_patch_sizes_when_finished() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48_000).unwrap();
+        wav.write_frame(0.0, 0.0).unwrap();
+        wav.write_frame(1.0, -1.0).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 8);
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 8);
+        assert_eq!(
+            u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+            48_000
+        );
+    }
+
+    #[test]
+    fn it_should_clamp_samples_to_16_bits() {
+        assert_eq!(to_pcm(1.0), i16::MAX);
+        assert_eq!(to_pcm(-2.0), -i16::MAX);
+        assert_eq!(to_pcm(0.0), 0);
+    }
+}
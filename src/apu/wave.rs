@@ -0,0 +1,153 @@
+#![allow(dead_code)]
+use crate::apu::channel::LengthCounter;
+
+pub const WAVE_RAM_SIZE: usize = 16;
+
+// Volume codes 0-3 shift the 4-bit sample right by these amounts, 4 mutes it
+const VOLUME_SHIFTS: [u8; 4] = [4, 0, 1, 2];
+
+#[derive(Debug, Clone)]
+pub struct Wave {
+    pub enabled: bool,
+    pub dac_enabled: bool,
+    pub volume_code: usize,
+    pub frequency: u16,
+    pub timer: u32,
+    pub position: usize,
+    pub sample: u8,
+    pub length: LengthCounter,
+    pub ram: [u8; WAVE_RAM_SIZE],
+}
+
+impl Wave {
+    pub fn new() -> Wave {
+        Wave {
+            enabled: false,
+            dac_enabled: false,
+            volume_code: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            length: LengthCounter::new(256),
+            ram: [0; WAVE_RAM_SIZE],
+        }
+    }
+
+    pub fn write(&mut self, register: usize, value: u8) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 == 0x80;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(value as u16),
+            2 => self.volume_code = ((value >> 5) & 0x3) as usize,
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | (((value & 0x7) as u16) << 8);
+                self.length.enabled = value & 0x40 == 0x40;
+                if value & 0x80 == 0x80 {
+                    self.trigger();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger();
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    pub fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.position = (self.position + 1) & 0x1F;
+            let byte = self.ram[self.position / 2];
+            // The high nibble is played first
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0xF
+            };
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.enabled {
+            self.sample >> VOLUME_SHIFTS[self.volume_code]
+        } else {
+            0
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing_wave(volume: u8) -> Wave {
+        let mut wave = Wave::new();
+        wave.ram = [0xF0; WAVE_RAM_SIZE];
+        wave.ram[0] = 0x8C;
+        wave.write(0, 0x80);
+        wave.write(2, volume << 5);
+        wave.write(3, 0xFF);
+        wave.write(4, 0x87);
+        wave
+    }
+
+    #[test]
+    fn it_should_play_low_nibble_after_high_nibble() {
+        let mut wave = playing_wave(1);
+        wave.tick();
+        wave.tick();
+        assert_eq!(wave.output(), 0xC);
+        wave.tick();
+        wave.tick();
+        assert_eq!(wave.output(), 0xF);
+    }
+
+    #[test]
+    fn it_should_shift_sample_with_volume_code() {
+        let mut wave = playing_wave(2);
+        wave.tick();
+        wave.tick();
+
+        assert_eq!(wave.output(), 0x6);
+    }
+
+    #[test]
+    fn it_should_mute_with_volume_code_0() {
+        let mut wave = playing_wave(0);
+        wave.tick();
+        wave.tick();
+
+        assert_eq!(wave.output(), 0);
+    }
+
+    #[test]
+    fn it_should_stop_when_dac_is_turned_off() {
+        let mut wave = playing_wave(1);
+        wave.write(0, 0x00);
+
+        assert!(!wave.enabled);
+    }
+}
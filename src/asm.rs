@@ -0,0 +1,485 @@
+#![allow(dead_code)]
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::processor::instructions::{cb_mnemonic, OPCODE_MNEMONICS, PREFIX};
+
+const STOP: u8 = 0x10;
+const RST: u8 = 0xC7;
+const REGISTERS: [&str; 19] = [
+    "A", "B", "C", "D", "E", "H", "L", "AF", "BC", "DE", "HL", "SP", "HL+", "HL-", "HLI", "HLD",
+    "NZ", "Z", "NC",
+];
+
+// Assembles RGBDS source at address 0, panicking on errors, so tests can write
+// `cpu.memory.load(0, &asm!("ld a,[$55FF]"))` instead of poking opcodes
+#[allow(unused_macros)]
+macro_rules! asm {
+    ($source:expr) => {
+        asm!($source, 0)
+    };
+    ($source:expr, $origin:expr) => {
+        $crate::asm::assemble($source, $origin).unwrap_or_else(|error| panic!("{}", error))
+    };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "line {}: {}", self.line, self.message)
+    }
+}
+
+// How an operand expression is encoded once labels are known
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Byte,
+    Word,
+    High,
+    Relative,
+    Signed,
+}
+
+impl Operand {
+    fn size(&self) -> u16 {
+        match self {
+            Operand::Word => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Statement {
+    line: usize,
+    address: u16,
+    scope: String,
+    opcode: Vec<u8>,
+    operands: Vec<(Operand, String)>,
+}
+
+impl Statement {
+    fn size(&self) -> u16 {
+        self.opcode.len() as u16
+            + self
+                .operands
+                .iter()
+                .map(|(kind, _)| kind.size())
+                .sum::<u16>()
+    }
+}
+
+// Opcode bytes followed by the operands still to be evaluated
+type Encoded = (Vec<u8>, Vec<(Operand, String)>);
+
+struct Context<'a> {
+    labels: &'a HashMap<String, u16>,
+    scope: &'a str,
+    address: u16,
+}
+
+// Two passes: the first sizes every statement and collects labels, the second
+// evaluates operands; `.local` labels belong to the last global label
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut scope = String::new();
+    let mut address = origin;
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message: String| AsmError { line, message };
+        let mut rest = text.split(';').next().unwrap_or("").trim();
+        while let Some((label, after)) = split_label(rest) {
+            let name = if label.starts_with('.') {
+                format!("{}{}", scope, label)
+            } else {
+                scope = label.split('.').next().unwrap_or(label).to_string();
+                label.to_string()
+            };
+            if labels.insert(name.clone(), address).is_some() {
+                return Err(error(format!("label {} is defined twice", name)));
+            }
+            rest = after;
+        }
+        if rest.is_empty() {
+            continue;
+        }
+        let context = Context {
+            labels: &labels,
+            scope: &scope,
+            address,
+        };
+        let (opcode, operands) = parse_statement(rest, &context).map_err(error)?;
+        let statement = Statement {
+            line,
+            address,
+            scope: scope.clone(),
+            opcode,
+            operands,
+        };
+        address = address.wrapping_add(statement.size());
+        statements.push(statement);
+    }
+
+    let mut bytes = Vec::new();
+    for statement in &statements {
+        let context = Context {
+            labels: &labels,
+            scope: &statement.scope,
+            address: statement.address,
+        };
+        let end = statement.address.wrapping_add(statement.size());
+        bytes.extend_from_slice(&statement.opcode);
+        for (kind, expression) in &statement.operands {
+            let value = evaluate(expression, &context).and_then(|value| {
+                encode(*kind, value, end).ok_or_else(|| format!("{} is out of range", expression))
+            });
+            let value = value.map_err(|message| AsmError {
+                line: statement.line,
+                message,
+            })?;
+            bytes.extend_from_slice(&value[..kind.size() as usize]);
+        }
+    }
+    Ok(bytes)
+}
+
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let end = text.find(|c: char| !is_identifier(c))?;
+    if end == 0 || !text[end..].starts_with(':') {
+        return None;
+    }
+    let after = text[end..].trim_start_matches(':').trim_start();
+    Some((&text[..end], after))
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '@' || c == '#'
+}
+
+fn parse_statement(text: &str, context: &Context) -> Result<Encoded, String> {
+    let (word, operands) = match text.split_once(char::is_whitespace) {
+        Some((word, operands)) => (word.to_ascii_uppercase(), operands.trim()),
+        None => (text.to_ascii_uppercase(), ""),
+    };
+    let mut operands: Vec<String> = if operands.is_empty() {
+        Vec::new()
+    } else {
+        operands.split(',').map(normalize).collect()
+    };
+    match word.as_str() {
+        "DB" => return Ok((Vec::new(), data(operands, Operand::Byte))),
+        "DW" => return Ok((Vec::new(), data(operands, Operand::Word))),
+        "DS" => {
+            let [size] = &operands[..] else {
+                return Err("DS takes a size".to_string());
+            };
+            return Ok((vec![0; evaluate(size, context)? as usize], Vec::new()));
+        }
+        "RST" => {
+            let [vector] = &operands[..] else {
+                return Err("RST takes a vector".to_string());
+            };
+            return match evaluate(vector, context)? {
+                vector @ 0..=0x38 if vector % 8 == 0 => Ok((vec![RST | vector as u8], Vec::new())),
+                _ => Err(format!("{} is not a restart vector", vector)),
+            };
+        }
+        "STOP" => return Ok((vec![STOP, 0x00], Vec::new())),
+        _ => {}
+    }
+    let word = shorthand(&word, &mut operands);
+    for opcode in 0..=0xFFu8 {
+        let mnemonic = OPCODE_MNEMONICS[opcode as usize];
+        if mnemonic.is_empty() || opcode == PREFIX || opcode == STOP {
+            continue;
+        }
+        if let Some(operands) = match_mnemonic(mnemonic, word, &operands) {
+            return Ok((vec![opcode], operands));
+        }
+    }
+    for opcode in 0..=0xFFu8 {
+        if match_mnemonic(&cb_mnemonic(opcode), word, &operands).is_some() {
+            return Ok((vec![PREFIX, opcode], Vec::new()));
+        }
+    }
+    Err(format!("no instruction matches {}", text))
+}
+
+fn data(operands: Vec<String>, kind: Operand) -> Vec<(Operand, String)> {
+    operands
+        .into_iter()
+        .map(|operand| (kind, operand))
+        .collect()
+}
+
+// Brackets become parentheses like in the opcode table, RGBDS aliases are resolved
+fn normalize(operand: &str) -> String {
+    let operand: String = operand
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c {
+            '[' => '(',
+            ']' => ')',
+            c => c,
+        })
+        .collect();
+    match operand.to_ascii_uppercase().as_str() {
+        "(HLI)" => "(HL+)".to_string(),
+        "(HLD)" => "(HL-)".to_string(),
+        "($FF00+C)" => "(C)".to_string(),
+        _ => operand,
+    }
+}
+
+// `ldh a,[c]`, `sub a,b` and `add b` are accepted for the table forms
+fn shorthand<'a>(word: &'a str, operands: &mut Vec<String>) -> &'a str {
+    match word {
+        "LDH"
+            if operands
+                .iter()
+                .any(|operand| operand.eq_ignore_ascii_case("(C)")) =>
+        {
+            return "LD"
+        }
+        "SUB" | "AND" | "XOR" | "OR" | "CP"
+            if operands.len() == 2 && operands[0].eq_ignore_ascii_case("A") =>
+        {
+            operands.remove(0);
+        }
+        "ADD" | "ADC" | "SBC" if operands.len() == 1 => operands.insert(0, "A".to_string()),
+        _ => {}
+    }
+    word
+}
+
+fn match_mnemonic(
+    mnemonic: &str,
+    word: &str,
+    operands: &[String],
+) -> Option<Vec<(Operand, String)>> {
+    let (name, templates) = match mnemonic.split_once(' ') {
+        Some((name, templates)) => (name, templates.split(',').collect()),
+        None => (mnemonic, Vec::new()),
+    };
+    if name != word || templates.len() != operands.len() {
+        return None;
+    }
+    let mut matched = Vec::new();
+    for (template, operand) in templates.iter().zip(operands) {
+        if let Some(operand) = match_operand(name, template, operand)? {
+            matched.push(operand);
+        }
+    }
+    Some(matched)
+}
+
+// None when the operand does not fit, Some(None) for a literal register or condition
+fn match_operand(name: &str, template: &str, operand: &str) -> Option<Option<(Operand, String)>> {
+    let placeholders = [
+        ("n16", Operand::Word),
+        ("a16", Operand::Word),
+        ("n8", Operand::Byte),
+        ("a8", Operand::High),
+        ("e8", Operand::Relative),
+    ];
+    for (placeholder, kind) in placeholders {
+        let Some(index) = template.find(placeholder) else {
+            continue;
+        };
+        let mut prefix = &template[..index];
+        let suffix = &template[index + placeholder.len()..];
+        let kind = match kind {
+            Operand::Relative if name != "JR" => {
+                // SP+e8 keeps the sign with the expression, so SP-3 matches too
+                prefix = prefix.trim_end_matches('+');
+                Operand::Signed
+            }
+            kind => kind,
+        };
+        let start = prefix.len();
+        let end = operand.len().checked_sub(suffix.len())?;
+        if end < start
+            || !operand.get(..start)?.eq_ignore_ascii_case(prefix)
+            || !operand.get(end..)?.eq_ignore_ascii_case(suffix)
+        {
+            return None;
+        }
+        let expression = operand.get(start..end)?;
+        if expression.is_empty() || expression.contains(['(', ')']) || mentions_register(expression)
+        {
+            return None;
+        }
+        return Some(Some((kind, expression.to_string())));
+    }
+    template.eq_ignore_ascii_case(operand).then_some(None)
+}
+
+fn mentions_register(expression: &str) -> bool {
+    expression.split(['+', '-', '(', ')']).any(|term| {
+        REGISTERS
+            .iter()
+            .any(|register| register.eq_ignore_ascii_case(term))
+    }) || REGISTERS
+        .iter()
+        .any(|register| register.eq_ignore_ascii_case(expression))
+}
+
+// Sums of numbers ($hex, %binary, 0x hex, decimal), labels and `$` for the current address
+fn evaluate(expression: &str, context: &Context) -> Result<i64, String> {
+    let mut value = 0;
+    let mut sign = 1;
+    let mut term = String::new();
+    for c in expression.chars().filter(|c| !c.is_whitespace()) {
+        match c {
+            '+' | '-' if !term.is_empty() => {
+                value += sign * term_value(&term, context)?;
+                term.clear();
+                sign = if c == '-' { -1 } else { 1 };
+            }
+            '-' => sign = -sign,
+            '+' => {}
+            c => term.push(c),
+        }
+    }
+    if term.is_empty() {
+        return Err(format!("missing value in {}", expression));
+    }
+    Ok(value + sign * term_value(&term, context)?)
+}
+
+fn term_value(term: &str, context: &Context) -> Result<i64, String> {
+    let number = if term == "$" {
+        Ok(context.address as i64)
+    } else if let Some(hex) = term.strip_prefix('$') {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(hex) = term.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = term.strip_prefix('%') {
+        i64::from_str_radix(binary, 2)
+    } else if term.starts_with(|c: char| c.is_ascii_digit()) {
+        term.parse()
+    } else {
+        let name = if term.starts_with('.') {
+            format!("{}{}", context.scope, term)
+        } else {
+            term.to_string()
+        };
+        return context
+            .labels
+            .get(&name)
+            .map(|address| *address as i64)
+            .ok_or_else(|| format!("unknown label {}", name));
+    };
+    number.map_err(|_| format!("invalid number {}", term))
+}
+
+// Little-endian bytes of the operand, relative jumps count from the end of the instruction
+fn encode(kind: Operand, value: i64, end: u16) -> Option<[u8; 2]> {
+    let value = match kind {
+        Operand::Byte if (-0x80..=0xFF).contains(&value) => value,
+        Operand::Word if (-0x8000..=0xFFFF).contains(&value) => value,
+        Operand::High if (0xFF00..=0xFFFF).contains(&value) || (0..=0xFF).contains(&value) => value,
+        Operand::Signed if (-0x80..=0x7F).contains(&value) => value,
+        Operand::Relative => {
+            let offset = value - end as i64;
+            if !(-0x80..=0x7F).contains(&offset) {
+                return None;
+            }
+            offset
+        }
+        _ => return None,
+    };
+    Some((value as u16).to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::listing::Disassembler;
+
+    #[test]
+    fn it_should_assemble_operands() {
+        assert_eq!(asm!("ld a,[$55FF]"), vec![0xFA, 0xFF, 0x55]);
+        assert_eq!(asm!("LD A, $42"), vec![0x3E, 0x42]);
+        assert_eq!(asm!("ld [hl+],a"), vec![0x22]);
+        assert_eq!(asm!("ld a,[hld]"), vec![0x3A]);
+        assert_eq!(asm!("ldh [$FF40],a"), vec![0xE0, 0x40]);
+        assert_eq!(asm!("ldh a,[c]"), vec![0xF2]);
+        assert_eq!(asm!("ld hl,sp-3"), vec![0xF8, 0xFD]);
+        assert_eq!(asm!("add sp,5"), vec![0xE8, 0x05]);
+        assert_eq!(asm!("ld a,-1"), vec![0x3E, 0xFF]);
+    }
+
+    #[test]
+    fn it_should_accept_alu_shorthands() {
+        assert_eq!(asm!("sub a,b"), asm!("sub b"));
+        assert_eq!(asm!("add b"), asm!("add a,b"));
+        assert_eq!(asm!("cp %1010"), vec![0xFE, 0x0A]);
+    }
+
+    #[test]
+    fn it_should_assemble_the_cb_page_and_specials() {
+        assert_eq!(asm!("bit 7,h"), vec![0xCB, 0x7C]);
+        assert_eq!(asm!("swap [hl]"), vec![0xCB, 0x36]);
+        assert_eq!(asm!("rst $38"), vec![0xFF]);
+        assert_eq!(asm!("stop"), vec![0x10, 0x00]);
+    }
+
+    #[test]
+    fn it_should_resolve_labels_and_relative_jumps() {
+        let bytes = asm!(
+            "Start:
+                ld b,3
+            .loop: dec b
+                jr nz,.loop
+                jp Start
+                jr $-5 ; same as .loop"
+        );
+
+        assert_eq!(
+            bytes,
+            vec![0x06, 0x03, 0x05, 0x20, 0xFD, 0xC3, 0x00, 0x00, 0x18, 0xF9]
+        );
+    }
+
+    #[test]
+    fn it_should_assemble_data_at_an_origin() {
+        let bytes = asm!("call Data\nData: db 1,$FF\ndw Data\nds 2", 0x150);
+
+        assert_eq!(bytes, vec![0xCD, 0x53, 0x01, 0x01, 0xFF, 0x53, 0x01, 0, 0]);
+    }
+
+    #[test]
+    fn it_should_report_errors_with_line_numbers() {
+        let error = |source| assemble(source, 0).unwrap_err();
+
+        assert_eq!(error("nop\nld q,1").line, 2);
+        assert_eq!(error("jp Missing").message, "unknown label Missing");
+        assert_eq!(error("ld a,$100").message, "$100 is out of range");
+        assert_eq!(error("x:\nx:").message, "label x is defined twice");
+    }
+
+    #[test]
+    fn it_should_round_trip_through_the_disassembler() {
+        for opcode in 0..=0xFFu8 {
+            // STOP is always assembled with a zero padding byte
+            if OPCODE_MNEMONICS[opcode as usize].is_empty() || opcode == PREFIX || opcode == STOP {
+                continue;
+            }
+            let bytes = [opcode, 0x12, 0x34];
+            let read = |address: u16| bytes.get(address as usize).copied().unwrap_or(0);
+            let line = Disassembler::new(None).line(read, 0);
+
+            assert_eq!(asm!(&line.text), line.bytes, "{}", line.text);
+        }
+        for opcode in 0..=0xFFu8 {
+            assert_eq!(asm!(&cb_mnemonic(opcode)), vec![PREFIX, opcode]);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+#![allow(dead_code)]
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::cartridge::Cartridge;
+use crate::processor::cpu::Cpu;
+
+const RAM_START: usize = 0xA000;
+// Without a mapper only the first 8 KiB bank of the external RAM can be reached
+const RAM_WINDOW: usize = 0x2000;
+
+// Battery-backed cartridge RAM, read at power-on and written back on exit as <rom>.sav
+#[derive(Debug)]
+pub struct Battery {
+    pub path: PathBuf,
+    size: usize,
+    saved: Vec<u8>,
+}
+
+impl Battery {
+    // None for cartridges without a battery or without external RAM
+    pub fn new(directory: &Path, rom: &Path, cartridge: &Cartridge) -> Option<Battery> {
+        if !cartridge.has_battery() || cartridge.ram_size() == 0 {
+            return None;
+        }
+        let name = rom
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "game".to_string());
+        Some(Battery {
+            path: directory.join(format!("{}.sav", name)),
+            size: cartridge.ram_size().min(RAM_WINDOW),
+            saved: Vec::new(),
+        })
+    }
+
+    // A missing file is a fresh cartridge, a short one only fills the start of the RAM
+    pub fn load(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        match fs::read(&self.path) {
+            Ok(bytes) => {
+                let length = bytes.len().min(self.size);
+                cpu.memory.memory[RAM_START..RAM_START + length].copy_from_slice(&bytes[..length]);
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        self.saved = self.ram(cpu).to_vec();
+        Ok(())
+    }
+
+    // Only writes when the game changed the RAM since it was loaded or last saved
+    pub fn save(&mut self, cpu: &Cpu) -> io::Result<bool> {
+        if self.ram(cpu) == self.saved.as_slice() {
+            return Ok(false);
+        }
+        fs::write(&self.path, self.ram(cpu))?;
+        self.saved = self.ram(cpu).to_vec();
+        Ok(true)
+    }
+
+    fn ram<'a>(&self, cpu: &'a Cpu) -> &'a [u8] {
+        &cpu.memory.memory[RAM_START..RAM_START + self.size]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cartridge(kind: u8, ram: u8) -> Cartridge {
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = kind;
+        rom[0x149] = ram;
+        Cartridge::new(rom)
+    }
+
+    #[test]
+    fn it_should_only_keep_battery_backed_ram() {
+        let directory = Path::new("saves");
+
+        assert!(Battery::new(directory, Path::new("a.gb"), &cartridge(0x01, 0x02)).is_none());
+        assert!(Battery::new(directory, Path::new("a.gb"), &cartridge(0x03, 0x00)).is_none());
+        let battery = Battery::new(
+            directory,
+            Path::new("roms/zelda.gb"),
+            &cartridge(0x03, 0x03),
+        );
+        assert_eq!(battery.unwrap().path, directory.join("zelda.sav"));
+    }
+
+    #[test]
+    fn it_should_write_changed_ram_and_read_it_back() {
+        let directory = std::env::temp_dir().join(format!("battery-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let cartridge = cartridge(0x03, 0x02);
+        let mut battery = Battery::new(&directory, Path::new("test.gb"), &cartridge).unwrap();
+        let mut cpu = Cpu::new();
+        battery.load(&mut cpu).unwrap();
+        let unchanged = battery.save(&cpu).unwrap();
+        cpu.memory.set_byte(0x42, 0xA010);
+        let changed = battery.save(&cpu).unwrap();
+
+        let mut restarted = Cpu::new();
+        battery.load(&mut restarted).unwrap();
+        let size = fs::metadata(&battery.path).unwrap().len();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(!unchanged);
+        assert!(changed);
+        assert_eq!(restarted.memory.peek(0xA010), 0x42);
+        assert_eq!(size, 0x2000);
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process;
+
+const DEFAULT_CONTEXT: usize = 5;
+
+const USAGE: &str = "\
+Usage: trace-diff [--context <N>] <TRACE> <REFERENCE>
+
+Compares a trace written with --trace against a Gameboy Doctor reference log
+and shows the first instruction where they differ.
+
+Exit codes:
+  0  the traces match
+  1  the traces differ
+  2  invalid command line or unreadable file
+";
+
+#[derive(Debug, PartialEq)]
+enum Difference {
+    Line {
+        number: usize,
+        trace: String,
+        reference: String,
+    },
+    TraceEnded(usize),
+    ReferenceEnded(usize),
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (context, paths) = match parse(&args) {
+        Some(parsed) => parsed,
+        None => {
+            eprint!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let open = |path: &String| match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(error) => {
+            eprintln!("error: cannot read '{}': {}", path, error);
+            process::exit(2);
+        }
+    };
+    let mut history = VecDeque::new();
+    match first_difference(open(&paths[0]), open(&paths[1]), context, &mut history) {
+        Ok(None) => println!("traces match"),
+        Ok(Some(difference)) => {
+            report(&difference, &history);
+            process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("error: {}", error);
+            process::exit(2);
+        }
+    }
+}
+
+fn parse(args: &[String]) -> Option<(usize, Vec<String>)> {
+    let mut context = DEFAULT_CONTEXT;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => context = args.next()?.parse().ok()?,
+            _ if arg.starts_with('-') => return None,
+            _ => paths.push(arg.clone()),
+        }
+    }
+    (paths.len() == 2).then_some((context, paths))
+}
+
+// Keeps the last `context` matching lines in `history` to show what led to the difference
+fn first_difference<T: BufRead, R: BufRead>(
+    trace: T,
+    reference: R,
+    context: usize,
+    history: &mut VecDeque<String>,
+) -> io::Result<Option<Difference>> {
+    let mut trace = trace.lines();
+    let mut reference = reference.lines();
+    let mut number = 0;
+    loop {
+        number += 1;
+        match (trace.next().transpose()?, reference.next().transpose()?) {
+            (None, None) => return Ok(None),
+            (None, Some(_)) => return Ok(Some(Difference::TraceEnded(number))),
+            (Some(_), None) => return Ok(Some(Difference::ReferenceEnded(number))),
+            (Some(trace), Some(reference)) if trace.trim() != reference.trim() => {
+                return Ok(Some(Difference::Line {
+                    number,
+                    trace,
+                    reference,
+                }))
+            }
+            (Some(line), Some(_)) => {
+                history.push_back(line);
+                if history.len() > context {
+                    history.pop_front();
+                }
+            }
+        }
+    }
+}
+
+// Names of the KEY:VALUE fields that differ, like "A" or "PCMEM"
+fn differing_fields(trace: &str, reference: &str) -> Vec<String> {
+    let fields = |line: &str| -> Vec<(String, String)> {
+        line.split_whitespace()
+            .filter_map(|field| field.split_once(':'))
+            .map(|(name, value)| (name.to_string(), value.to_uppercase()))
+            .collect()
+    };
+    let reference = fields(reference);
+    fields(trace)
+        .into_iter()
+        .filter(|field| !reference.contains(field))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+fn report(difference: &Difference, history: &VecDeque<String>) {
+    let first = match difference {
+        Difference::Line { number, .. }
+        | Difference::TraceEnded(number)
+        | Difference::ReferenceEnded(number) => *number,
+    };
+    for (offset, line) in history.iter().enumerate() {
+        println!("  {:>8}  {}", first - history.len() + offset, line);
+    }
+    match difference {
+        Difference::Line {
+            number,
+            trace,
+            reference,
+        } => {
+            println!("- {:>8}  {}", number, reference);
+            println!("+ {:>8}  {}", number, trace);
+            println!(
+                "first difference at instruction {} in {}",
+                number,
+                differing_fields(trace, reference).join(", ")
+            );
+        }
+        Difference::TraceEnded(number) => {
+            println!("trace ends at instruction {} before the reference", number)
+        }
+        Difference::ReferenceEnded(number) => {
+            println!("reference ends at instruction {} before the trace", number)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02";
+    const JUMP: &str = "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,13,02,CE";
+    const WRONG: &str = "A:02 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,13,02,CE";
+
+    fn diff(trace: &str, reference: &str) -> (Option<Difference>, VecDeque<String>) {
+        let mut history = VecDeque::new();
+        let difference =
+            first_difference(trace.as_bytes(), reference.as_bytes(), 1, &mut history).unwrap();
+        (difference, history)
+    }
+
+    #[test]
+    fn it_should_match_identical_traces() {
+        let log = format!("{}\n{}\n", LINE, JUMP);
+
+        assert_eq!(diff(&log, &log).0, None);
+    }
+
+    #[test]
+    fn it_should_report_first_differing_line_with_context() {
+        let trace = format!("{}\n{}\n{}\n", LINE, LINE, WRONG);
+        let reference = format!("{}\n{}\n{}\n", LINE, LINE, JUMP);
+        let (difference, history) = diff(&trace, &reference);
+
+        assert_eq!(
+            difference,
+            Some(Difference::Line {
+                number: 3,
+                trace: WRONG.to_string(),
+                reference: JUMP.to_string()
+            })
+        );
+        assert_eq!(history, [LINE.to_string()]);
+    }
+
+    #[test]
+    fn it_should_report_shorter_trace() {
+        let reference = format!("{}\n{}\n", LINE, JUMP);
+
+        assert_eq!(diff(LINE, &reference).0, Some(Difference::TraceEnded(2)));
+    }
+
+    #[test]
+    fn it_should_name_differing_fields() {
+        assert_eq!(differing_fields(WRONG, JUMP), ["A"]);
+        assert_eq!(differing_fields(LINE, JUMP), ["PC", "PCMEM"]);
+    }
+}
@@ -0,0 +1,192 @@
+#![allow(dead_code)]
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::model::Model;
+
+const TITLE_START: usize = 0x134;
+const TITLE_END: usize = 0x143;
+const CGB_FLAG: usize = 0x143;
+const NEW_LICENSEE: usize = 0x144;
+const SGB_FLAG: usize = 0x146;
+const CARTRIDGE_TYPE: usize = 0x147;
+const RAM_SIZE: usize = 0x149;
+const OLD_LICENSEE: usize = 0x14B;
+// Cartridge types whose external RAM is kept by a battery
+const BATTERY_TYPES: [u8; 11] = [
+    0x03, 0x06, 0x09, 0x0D, 0x0F, 0x10, 0x13, 0x1B, 0x1E, 0x22, 0xFF,
+];
+const MBC2_TYPES: [u8; 2] = [0x05, 0x06];
+const MBC2_RAM_SIZE: usize = 512;
+
+#[derive(Debug)]
+pub struct Cartridge {
+    pub rom: Vec<u8>,
+}
+
+impl Cartridge {
+    pub fn new(rom: Vec<u8>) -> Cartridge {
+        Cartridge { rom }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Cartridge> {
+        Ok(Cartridge::new(fs::read(path)?))
+    }
+
+    fn header_byte(&self, position: usize) -> u8 {
+        self.rom.get(position).copied().unwrap_or(0)
+    }
+
+    pub fn title(&self) -> String {
+        // On CGB cartridges the last title byte is the CGB flag
+        let end = if self.supports_cgb() {
+            CGB_FLAG
+        } else {
+            TITLE_END + 1
+        };
+        (TITLE_START..end)
+            .map(|position| self.header_byte(position))
+            .take_while(|byte| *byte != 0)
+            .map(|byte| byte as char)
+            .collect()
+    }
+
+    pub fn title_bytes(&self) -> [u8; 16] {
+        let mut title = [0; 16];
+        for (offset, byte) in title.iter_mut().enumerate() {
+            *byte = self.header_byte(TITLE_START + offset);
+        }
+        title
+    }
+
+    // An old licensee of 0x33 means the new two-letter code is used instead
+    pub fn is_nintendo_licensed(&self) -> bool {
+        match self.header_byte(OLD_LICENSEE) {
+            0x01 => true,
+            0x33 => {
+                self.header_byte(NEW_LICENSEE) == b'0' && self.header_byte(NEW_LICENSEE + 1) == b'1'
+            }
+            _ => false,
+        }
+    }
+
+    pub fn cgb_flag(&self) -> u8 {
+        self.header_byte(CGB_FLAG)
+    }
+
+    pub fn supports_cgb(&self) -> bool {
+        self.cgb_flag() & 0x80 == 0x80
+    }
+
+    // SGB functions are only enabled with an old licensee of 0x33
+    pub fn supports_sgb(&self) -> bool {
+        self.header_byte(SGB_FLAG) == 0x03 && self.header_byte(OLD_LICENSEE) == 0x33
+    }
+
+    pub fn has_battery(&self) -> bool {
+        BATTERY_TYPES.contains(&self.header_byte(CARTRIDGE_TYPE))
+    }
+
+    // External RAM in bytes, MBC2 has its 512 half-bytes built in and no size in the header
+    pub fn ram_size(&self) -> usize {
+        if MBC2_TYPES.contains(&self.header_byte(CARTRIDGE_TYPE)) {
+            return MBC2_RAM_SIZE;
+        }
+        match self.header_byte(RAM_SIZE) {
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            _ => 0,
+        }
+    }
+
+    pub fn model(&self) -> Model {
+        if self.supports_cgb() {
+            Model::Cgb
+        } else if self.supports_sgb() {
+            Model::Sgb
+        } else {
+            Model::Dmg
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_with_header(title: &str, cgb_flag: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[TITLE_START..TITLE_START + title.len()].copy_from_slice(title.as_bytes());
+        rom[CGB_FLAG] = cgb_flag;
+        rom
+    }
+
+    #[test]
+    fn it_should_read_the_title() {
+        let cartridge = Cartridge::new(rom_with_header("TETRIS", 0x00));
+
+        assert_eq!(cartridge.title(), "TETRIS");
+    }
+
+    #[test]
+    fn it_should_detect_nintendo_licensee() {
+        let mut rom = rom_with_header("TETRIS", 0x00);
+        rom[OLD_LICENSEE] = 0x33;
+        rom[NEW_LICENSEE] = b'0';
+        rom[NEW_LICENSEE + 1] = b'1';
+
+        assert!(Cartridge::new(rom).is_nintendo_licensed());
+    }
+
+    #[test]
+    fn it_should_pick_dmg_model_without_cgb_flag() {
+        let cartridge = Cartridge::new(rom_with_header("TETRIS", 0x00));
+
+        assert_eq!(cartridge.model(), Model::Dmg);
+    }
+
+    #[test]
+    fn it_should_pick_cgb_model_with_cgb_flag() {
+        let cartridge = Cartridge::new(rom_with_header("POKEMON", 0x80));
+
+        assert_eq!(cartridge.model(), Model::Cgb);
+        assert_eq!(cartridge.title(), "POKEMON");
+    }
+
+    #[test]
+    fn it_should_pick_sgb_model_with_sgb_flag() {
+        let mut rom = rom_with_header("KIRBY", 0x00);
+        rom[SGB_FLAG] = 0x03;
+        rom[OLD_LICENSEE] = 0x33;
+
+        assert_eq!(Cartridge::new(rom).model(), Model::Sgb);
+    }
+
+    #[test]
+    fn it_should_read_battery_and_ram_size() {
+        let mut rom = rom_with_header("ZELDA", 0x00);
+        rom[CARTRIDGE_TYPE] = 0x03;
+        rom[RAM_SIZE] = 0x03;
+        let mbc2 = {
+            let mut rom = rom.clone();
+            rom[CARTRIDGE_TYPE] = 0x06;
+            Cartridge::new(rom)
+        };
+        let cartridge = Cartridge::new(rom);
+
+        assert!(cartridge.has_battery());
+        assert_eq!(cartridge.ram_size(), 0x8000);
+        assert_eq!(mbc2.ram_size(), 512);
+        assert!(!Cartridge::new(rom_with_header("TETRIS", 0x00)).has_battery());
+    }
+
+    #[test]
+    fn it_should_pick_cgb_model_for_cgb_only_games() {
+        let cartridge = Cartridge::new(rom_with_header("ZELDA", 0xC0));
+
+        assert_eq!(cartridge.model(), Model::Cgb);
+    }
+}
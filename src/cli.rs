@@ -0,0 +1,501 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::config::{DEFAULT_REWIND_INTERVAL, DEFAULT_REWIND_MEGABYTES, DEFAULT_SCALE};
+use crate::headless::StopCondition;
+use crate::logging::Level;
+use crate::model::Model;
+use crate::pacing::Sync;
+use crate::savestate::SLOTS;
+use crate::video::compatibility::PaletteCombo;
+
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_BAD_ROM: i32 = 3;
+pub const EXIT_MISMATCH: i32 = 4;
+
+const MAX_SCALE: u32 = 16;
+
+pub const USAGE: &str = "\
+Usage: game-boy [OPTIONS] <ROM>
+
+Options:
+  --boot-rom <FILE>     Run this boot ROM before the cartridge
+  --model <MODEL>       Hardware to emulate: dmg, cgb, sgb or agb (default: from the header)
+  --scale <N>           Window scale from 1 to 16 (default: 3)
+  --palette <COMBO>     Boot ROM color combo for monochrome games on CGB, e.g. left+b
+  --headless            Run without a window
+  --frames <N>          Stop after N frames
+  --until-pc <ADDR>     Stop a headless run when the CPU reaches ADDR
+  --until-mem <ADDR>=<VALUE>
+                        Stop a headless run when memory at ADDR holds VALUE
+  --screenshot <FILE>   Save the final screen as PNG
+  --reference <FILE>    Compare the final screen with a reference PNG
+  --save-dir <DIR>      Directory for battery saves (.sav) and save states, created
+                        if missing (default: next to the ROM)
+  --load-state <SLOT>   Start from save state slot 0-9, in-game 0-9 pick a slot,
+                        F2 saves to it and F4 loads it
+  --rewind-memory <MB>  Memory kept for rewinding with R, 0 disables it (default: 64)
+  --rewind-interval <N> Frames between rewind snapshots (default: 1)
+  --record <FILE>       Record the joypad input from power-on to a movie file
+  --play <FILE>         Play a movie back, stopping a headless run when it desyncs
+  --speed <FACTOR>      Emulation speed multiplier (default: 1), in-game - and =
+                        step it, Tab fast-forwards, P pauses and N advances a frame
+  --sync <MODE>         Pace frames by timer, audio buffer fill or display vsync
+                        (default: timer), the measured speed is in the window title
+  --log <LEVEL>         error, warn, info, debug or trace (default: warn)
+  --trace <FILE>        Write the CPU state before every instruction to FILE,
+                        in Gameboy Doctor format (compare logs with trace-diff)
+  --doctor              Make LY always read 0x90 like the Gameboy Doctor logs
+  --wav <FILE>          Record audio to a 16-bit stereo WAV file
+  --stems               Also record one WAV file per sound channel
+  --debug               Start in the interactive debugger instead of running,
+                        labels are read from the ROM's .sym file when present
+  --tui                 Start in the full-screen terminal debugger, works over SSH
+  --gdb <PORT>          Wait for GDB on 127.0.0.1:PORT and let it control the CPU
+  -h, --help            Print this help
+  -V, --version         Print the version
+
+Exit codes:
+  0  success
+  1  runtime error
+  2  invalid command line
+  3  ROM or boot ROM cannot be read
+  4  final screen differs from the reference or a movie desynced
+";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub rom: PathBuf,
+    pub boot_rom: Option<PathBuf>,
+    pub model: Option<Model>,
+    pub scale: u32,
+    pub palette: Option<PaletteCombo>,
+    pub headless: bool,
+    pub frames: Option<u32>,
+    pub until: Vec<StopCondition>,
+    pub screenshot: Option<PathBuf>,
+    pub reference: Option<PathBuf>,
+    pub save_dir: Option<PathBuf>,
+    pub load_state: Option<u8>,
+    pub rewind_megabytes: usize,
+    pub rewind_interval: u32,
+    pub record: Option<PathBuf>,
+    pub play: Option<PathBuf>,
+    pub speed: f64,
+    pub sync: Sync,
+    pub log_level: Level,
+    pub trace: Option<PathBuf>,
+    pub doctor: bool,
+    pub wav: Option<PathBuf>,
+    pub stems: bool,
+    pub debug: bool,
+    pub tui: bool,
+    pub gdb: Option<u16>,
+}
+
+impl Options {
+    pub fn new(rom: PathBuf) -> Options {
+        Options {
+            rom,
+            boot_rom: None,
+            model: None,
+            scale: DEFAULT_SCALE,
+            palette: None,
+            headless: false,
+            frames: None,
+            until: Vec::new(),
+            screenshot: None,
+            reference: None,
+            save_dir: None,
+            load_state: None,
+            rewind_megabytes: DEFAULT_REWIND_MEGABYTES,
+            rewind_interval: DEFAULT_REWIND_INTERVAL,
+            record: None,
+            play: None,
+            speed: 1.0,
+            sync: Sync::Timer,
+            log_level: Level::Warn,
+            trace: None,
+            doctor: false,
+            wav: None,
+            stems: false,
+            debug: false,
+            tui: false,
+            gdb: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(Box<Options>),
+    Help,
+    Version,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+// Options take their value as the next argument or after an equal sign
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut args = args.into_iter();
+    let mut rom = None;
+    let mut options = Options::new(PathBuf::new());
+    while let Some(arg) = args.next() {
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| match inline {
+            Some(value) => Ok(value.to_string()),
+            None => args
+                .next()
+                .ok_or_else(|| UsageError(format!("{} needs a value", name))),
+        };
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--boot-rom" => options.boot_rom = Some(PathBuf::from(value(&name)?)),
+            "--model" => options.model = Some(parse_model(&value(&name)?)?),
+            "--scale" => options.scale = parse_scale(&value(&name)?)?,
+            "--palette" => options.palette = Some(parse_palette(&value(&name)?)?),
+            "--headless" => options.headless = true,
+            "--frames" => options.frames = Some(parse_number(&name, &value(&name)?)?),
+            "--until-pc" => {
+                let address = parse_address(&value(&name)?)?;
+                options.until.push(StopCondition::Pc(address));
+            }
+            "--until-mem" => options.until.push(parse_memory_condition(&value(&name)?)?),
+            "--screenshot" => options.screenshot = Some(PathBuf::from(value(&name)?)),
+            "--reference" => options.reference = Some(PathBuf::from(value(&name)?)),
+            "--save-dir" => options.save_dir = Some(PathBuf::from(value(&name)?)),
+            "--load-state" => options.load_state = Some(parse_slot(&value(&name)?)?),
+            "--rewind-memory" => {
+                options.rewind_megabytes = parse_number(&name, &value(&name)?)? as usize
+            }
+            "--rewind-interval" => options.rewind_interval = parse_interval(&value(&name)?)?,
+            "--record" => options.record = Some(PathBuf::from(value(&name)?)),
+            "--play" => options.play = Some(PathBuf::from(value(&name)?)),
+            "--speed" => options.speed = parse_speed(&value(&name)?)?,
+            "--sync" => options.sync = parse_sync(&value(&name)?)?,
+            "--log" => options.log_level = parse_level(&value(&name)?)?,
+            "--trace" => options.trace = Some(PathBuf::from(value(&name)?)),
+            "--doctor" => options.doctor = true,
+            "--wav" => options.wav = Some(PathBuf::from(value(&name)?)),
+            "--stems" => options.stems = true,
+            "--debug" => options.debug = true,
+            "--tui" => options.tui = true,
+            "--gdb" => options.gdb = Some(parse_port(&value(&name)?)?),
+            _ if name.starts_with('-') => {
+                return Err(UsageError(format!("unknown option '{}'", name)))
+            }
+            _ if rom.is_some() => {
+                return Err(UsageError(format!("unexpected argument '{}'", name)))
+            }
+            _ => rom = Some(PathBuf::from(arg)),
+        }
+    }
+    if options.stems && options.wav.is_none() {
+        return Err(UsageError("--stems needs --wav".to_string()));
+    }
+    if !options.until.is_empty() && !options.headless {
+        return Err(UsageError(
+            "--until-pc and --until-mem need --headless".to_string(),
+        ));
+    }
+    let debuggers = [options.debug, options.tui, options.gdb.is_some()];
+    if debuggers.iter().filter(|enabled| **enabled).count() > 1 {
+        return Err(UsageError(
+            "only one of --debug, --tui and --gdb can be used".to_string(),
+        ));
+    }
+    if options.record.is_some() || options.play.is_some() {
+        if options.record.is_some() && options.play.is_some() {
+            return Err(UsageError(
+                "--record and --play cannot be combined".to_string(),
+            ));
+        }
+        // Movies start from power-on and run in the main loops only
+        if options.load_state.is_some() || debuggers.contains(&true) {
+            return Err(UsageError(
+                "movies cannot be used with --load-state or a debugger".to_string(),
+            ));
+        }
+    }
+    options.rom = rom.ok_or_else(|| UsageError("missing ROM path".to_string()))?;
+    Ok(Command::Run(Box::new(options)))
+}
+
+fn parse_model(value: &str) -> Result<Model, UsageError> {
+    Model::from_name(value).ok_or_else(|| {
+        UsageError(format!(
+            "unknown model '{}', expected dmg, cgb, sgb or agb",
+            value
+        ))
+    })
+}
+
+fn parse_palette(value: &str) -> Result<PaletteCombo, UsageError> {
+    PaletteCombo::from_name(value).ok_or_else(|| {
+        UsageError(format!(
+            "unknown palette '{}', expected a direction with an optional +a or +b",
+            value
+        ))
+    })
+}
+
+fn parse_level(value: &str) -> Result<Level, UsageError> {
+    Level::from_name(value).ok_or_else(|| {
+        UsageError(format!(
+            "unknown log level '{}', expected error, warn, info, debug or trace",
+            value
+        ))
+    })
+}
+
+fn parse_number(name: &str, value: &str) -> Result<u32, UsageError> {
+    value
+        .parse()
+        .map_err(|_| UsageError(format!("{} expects a number, got '{}'", name, value)))
+}
+
+// Hexadecimal with a 0x or $ prefix, decimal otherwise
+pub fn parse_value(value: &str) -> Option<u32> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .or_else(|| value.strip_prefix('$'))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_address(value: &str) -> Result<u16, UsageError> {
+    parse_value(value)
+        .and_then(|address| u16::try_from(address).ok())
+        .ok_or_else(|| UsageError(format!("invalid address '{}'", value)))
+}
+
+fn parse_memory_condition(value: &str) -> Result<StopCondition, UsageError> {
+    let (address, byte) = value
+        .split_once('=')
+        .ok_or_else(|| UsageError(format!("--until-mem expects ADDR=VALUE, got '{}'", value)))?;
+    let byte = parse_value(byte)
+        .and_then(|byte| u8::try_from(byte).ok())
+        .ok_or_else(|| UsageError(format!("invalid byte '{}'", byte)))?;
+    Ok(StopCondition::Memory(parse_address(address)?, byte))
+}
+
+fn parse_interval(value: &str) -> Result<u32, UsageError> {
+    value
+        .parse()
+        .ok()
+        .filter(|frames| *frames > 0)
+        .ok_or_else(|| {
+            UsageError(format!(
+                "--rewind-interval expects at least 1 frame, got '{}'",
+                value
+            ))
+        })
+}
+
+fn parse_slot(value: &str) -> Result<u8, UsageError> {
+    value
+        .parse()
+        .ok()
+        .filter(|slot| *slot < SLOTS)
+        .ok_or_else(|| {
+            UsageError(format!(
+                "--load-state expects a slot from 0 to 9, got '{}'",
+                value
+            ))
+        })
+}
+
+fn parse_port(value: &str) -> Result<u16, UsageError> {
+    value
+        .parse()
+        .map_err(|_| UsageError(format!("--gdb expects a port number, got '{}'", value)))
+}
+
+fn parse_scale(value: &str) -> Result<u32, UsageError> {
+    let scale = parse_number("--scale", value)?;
+    if !(1..=MAX_SCALE).contains(&scale) {
+        return Err(UsageError(format!(
+            "--scale must be between 1 and {}",
+            MAX_SCALE
+        )));
+    }
+    Ok(scale)
+}
+
+fn parse_speed(value: &str) -> Result<f64, UsageError> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(UsageError(format!(
+            "--speed expects a positive number, got '{}'",
+            value
+        ))),
+    }
+}
+
+fn parse_sync(value: &str) -> Result<Sync, UsageError> {
+    Sync::from_name(value).ok_or_else(|| {
+        UsageError(format!(
+            "unknown sync '{}', expected timer, audio or vsync",
+            value
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command, UsageError> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse_args(args) {
+            Ok(Command::Run(options)) => *options,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_should_use_defaults_with_only_a_rom() {
+        assert_eq!(
+            options(&["tetris.gb"]),
+            Options::new(PathBuf::from("tetris.gb"))
+        );
+    }
+
+    #[test]
+    fn it_should_parse_every_option() {
+        let options = options(&[
+            "--model",
+            "agb",
+            "--scale=4",
+            "--palette",
+            "down+b",
+            "--headless",
+            "--frames",
+            "60",
+            "--speed",
+            "2.5",
+            "--sync",
+            "audio",
+            "--log",
+            "debug",
+            "--boot-rom",
+            "cgb.bin",
+            "--save-dir",
+            "saves",
+            "--load-state",
+            "3",
+            "--rewind-memory",
+            "16",
+            "--rewind-interval=2",
+            "--trace",
+            "trace.log",
+            "--doctor",
+            "--wav",
+            "out.wav",
+            "--stems",
+            "--debug",
+            "game.gbc",
+        ]);
+
+        assert_eq!(options.rom, PathBuf::from("game.gbc"));
+        assert_eq!(options.model, Some(Model::Agb));
+        assert_eq!(options.scale, 4);
+        assert_eq!(options.palette, Some(PaletteCombo::DownB));
+        assert!(options.headless);
+        assert_eq!(options.frames, Some(60));
+        assert_eq!(options.speed, 2.5);
+        assert_eq!(options.sync, Sync::Audio);
+        assert_eq!(options.log_level, Level::Debug);
+        assert_eq!(options.boot_rom, Some(PathBuf::from("cgb.bin")));
+        assert_eq!(options.save_dir, Some(PathBuf::from("saves")));
+        assert_eq!(options.load_state, Some(3));
+        assert_eq!(options.rewind_megabytes, 16);
+        assert_eq!(options.rewind_interval, 2);
+        assert_eq!(options.trace, Some(PathBuf::from("trace.log")));
+        assert!(options.doctor);
+        assert_eq!(options.wav, Some(PathBuf::from("out.wav")));
+        assert!(options.stems);
+        assert!(options.debug);
+    }
+
+    #[test]
+    fn it_should_parse_movie_options() {
+        let recording = options(&["--record", "bug.gbm", "rom.gb"]);
+        let playback = options(&["--headless", "--play=bug.gbm", "rom.gb"]);
+
+        assert_eq!(recording.record, Some(PathBuf::from("bug.gbm")));
+        assert_eq!(playback.play, Some(PathBuf::from("bug.gbm")));
+    }
+
+    #[test]
+    fn it_should_parse_stop_conditions() {
+        let options = options(&[
+            "--headless",
+            "--until-pc",
+            "0x0150",
+            "--until-mem=$A000=128",
+            "rom.gb",
+        ]);
+
+        assert_eq!(
+            options.until,
+            vec![
+                StopCondition::Pc(0x150),
+                StopCondition::Memory(0xA000, 0x80)
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_reject_stop_conditions_with_a_window() {
+        assert!(parse_args(&["--until-pc", "0x100", "rom.gb"]).is_err());
+        assert!(parse_args(&["--headless", "--until-mem", "0xC000", "rom.gb"]).is_err());
+        assert!(parse_args(&["--headless", "--until-pc", "0x10000", "rom.gb"]).is_err());
+    }
+
+    #[test]
+    fn it_should_return_help_and_version() {
+        assert_eq!(parse_args(&["--help"]), Ok(Command::Help));
+        assert_eq!(parse_args(&["rom.gb", "-V"]), Ok(Command::Version));
+    }
+
+    #[test]
+    fn it_should_reject_invalid_arguments() {
+        assert!(parse_args(&[]).is_err());
+        assert!(parse_args(&["rom.gb", "--model", "gba"]).is_err());
+        assert!(parse_args(&["rom.gb", "--scale", "0"]).is_err());
+        assert!(parse_args(&["rom.gb", "--speed", "-1"]).is_err());
+        assert!(parse_args(&["rom.gb", "--sync", "gsync"]).is_err());
+        assert!(parse_args(&["rom.gb", "--frames"]).is_err());
+        assert!(parse_args(&["rom.gb", "--fast"]).is_err());
+        assert!(parse_args(&["rom.gb", "other.gb"]).is_err());
+        assert!(parse_args(&["rom.gb", "--stems"]).is_err());
+        assert!(parse_args(&["rom.gb", "--debug", "--gdb", "2345"]).is_err());
+        assert!(parse_args(&["rom.gb", "--tui", "--debug"]).is_err());
+        assert!(parse_args(&["rom.gb", "--gdb", "70000"]).is_err());
+        assert!(parse_args(&["rom.gb", "--load-state", "10"]).is_err());
+        assert!(parse_args(&["rom.gb", "--rewind-interval", "0"]).is_err());
+        assert!(parse_args(&["rom.gb", "--record", "a.gbm", "--play", "b.gbm"]).is_err());
+        assert!(parse_args(&["rom.gb", "--play", "a.gbm", "--load-state", "1"]).is_err());
+    }
+}
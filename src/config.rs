@@ -1 +1,7 @@
-pub const CLOCK_DURATION_NS: u64 = 238;
+pub const SAMPLE_RATE: u32 = 48_000;
+pub const CYCLES_PER_FRAME: u32 = 70_224;
+// 4194304 Hz / 70224 cycles, about 59.7275 frames per second
+pub const FRAME_RATE: f64 = 4_194_304.0 / CYCLES_PER_FRAME as f64;
+pub const DEFAULT_SCALE: u32 = 3;
+pub const DEFAULT_REWIND_MEGABYTES: usize = 64;
+pub const DEFAULT_REWIND_INTERVAL: u32 = 1;
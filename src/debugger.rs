@@ -0,0 +1,566 @@
+#![allow(dead_code)]
+pub mod command;
+pub mod condition;
+pub mod tui;
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::debugger::command::{Command, HELP};
+use crate::debugger::condition::Condition;
+use crate::disasm::listing::{Disassembler, Line};
+use crate::disasm::symbols::Symbols;
+use crate::processor::cpu::Cpu;
+use crate::processor::instructions::{opcode_length, OPCODE_MNEMONICS};
+use crate::processor::watch::{Access, WatchHit, Watchpoint};
+
+const PROMPT: &str = "(gb) ";
+const HISTORY_LENGTH: usize = 16;
+const LISTING_CONTEXT: usize = 3;
+const DUMP_WIDTH: u32 = 16;
+// Instructions run between calls to the interrupt check
+const INTERRUPT_STEPS: u32 = 10_000;
+
+// Set by the Ctrl-C handler, taken by the interrupt check of a running command
+static CTRL_C: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub address: Option<u16>,
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    // Without an address the condition is checked after every instruction
+    fn is_hit(&self, cpu: &mut Cpu) -> bool {
+        self.address
+            .is_none_or(|address| address == cpu.memory.pc as u16)
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.is_met(cpu))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    Done,
+    Breakpoint(usize),
+    Watch(WatchHit),
+    Interrupted,
+    Exit,
+}
+
+// Reads commands until quit or the end of the input, the CPU only runs when asked to
+#[derive(Debug)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub symbols: Option<Symbols>,
+    // Polled while running so a front end can stop a program that never hits a breakpoint
+    pub interrupt: Option<fn() -> bool>,
+    history: VecDeque<u16>,
+    next_id: usize,
+    last: Option<Command>,
+    exited: bool,
+}
+
+impl Debugger {
+    pub fn new(symbols: Option<Symbols>) -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            symbols,
+            interrupt: None,
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+            next_id: 1,
+            last: None,
+            exited: false,
+        }
+    }
+
+    // Ctrl-C pauses a running program instead of ending the debugger
+    pub fn interrupt_on_ctrl_c(&mut self) -> Result<(), ctrlc::Error> {
+        ctrlc::set_handler(|| CTRL_C.store(true, Ordering::Relaxed))?;
+        self.interrupt = Some(ctrl_c_pressed);
+        Ok(())
+    }
+
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        cpu: &mut Cpu,
+        input: R,
+        output: &mut W,
+    ) -> io::Result<()> {
+        self.print_location(cpu, output)?;
+        let mut lines = input.lines();
+        loop {
+            write!(output, "{}", PROMPT)?;
+            output.flush()?;
+            let Some(line) = lines.next() else {
+                break;
+            };
+            let line = line?;
+            let command = if line.trim().is_empty() {
+                match &self.last {
+                    Some(command) => command.clone(),
+                    None => continue,
+                }
+            } else {
+                match command::parse(&line, self.symbols.as_ref()) {
+                    Ok(command) => command,
+                    Err(error) => {
+                        writeln!(output, "{}", error)?;
+                        continue;
+                    }
+                }
+            };
+            if command == Command::Quit {
+                break;
+            }
+            // A Ctrl-C pressed at the prompt must not stop the next command
+            CTRL_C.store(false, Ordering::Relaxed);
+            self.execute(cpu, &command, output)?;
+            self.last = Some(command);
+        }
+        Ok(())
+    }
+
+    pub fn execute<W: Write>(
+        &mut self,
+        cpu: &mut Cpu,
+        command: &Command,
+        output: &mut W,
+    ) -> io::Result<()> {
+        match command {
+            Command::Step(count) => {
+                let count = *count;
+                let stop = self.run_until(cpu, |_, _, steps| steps >= count);
+                self.report(cpu, stop, output)
+            }
+            Command::Next => {
+                let stop = self.step_over(cpu);
+                self.report(cpu, stop, output)
+            }
+            Command::Out => {
+                let start = cpu.registers.sp;
+                let stop = self.run_until(cpu, |cpu, opcode, _| {
+                    OPCODE_MNEMONICS[opcode as usize].starts_with("RET") && cpu.registers.sp > start
+                });
+                self.report(cpu, stop, output)
+            }
+            Command::Continue => {
+                let stop = self.run_until(cpu, |_, _, _| false);
+                self.report(cpu, stop, output)
+            }
+            Command::Break(address, condition) => {
+                self.add_breakpoint(*address, condition.clone());
+                let breakpoint = &self.breakpoints[self.breakpoints.len() - 1];
+                writeln!(output, "{}", self.describe_breakpoint(breakpoint))
+            }
+            Command::Watch(start, end, access) => {
+                self.add_watchpoint(cpu, *start, *end, *access);
+                let watchpoints = &cpu.memory.watchpoints.list;
+                writeln!(
+                    output,
+                    "{}",
+                    describe_watchpoint(&watchpoints[watchpoints.len() - 1])
+                )
+            }
+            Command::Delete(id) => {
+                if !self.delete(cpu, *id) {
+                    writeln!(output, "no breakpoint or watchpoint {}", id)?;
+                }
+                Ok(())
+            }
+            Command::Info => self.print_info(cpu, output),
+            Command::Registers => print_registers(cpu, output),
+            Command::Examine(start, length) => dump(cpu, *start, *length, output),
+            Command::List(address, count) => self.print_listing(cpu, *address, *count, output),
+            Command::Help => write!(output, "{}", HELP),
+            Command::Quit => Ok(()),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, address: Option<u16>, condition: Option<Condition>) -> usize {
+        let id = self.take_id();
+        self.breakpoints.push(Breakpoint {
+            id,
+            address,
+            condition,
+        });
+        id
+    }
+
+    // Watchpoints live on the bus, which checks them on every CPU access
+    pub fn add_watchpoint(&mut self, cpu: &mut Cpu, start: u16, end: u16, access: Access) -> usize {
+        let id = self.take_id();
+        cpu.memory.watchpoints.list.push(Watchpoint {
+            id,
+            start,
+            end,
+            access,
+        });
+        id
+    }
+
+    // False when no breakpoint or watchpoint has this ID
+    pub fn delete(&mut self, cpu: &mut Cpu, id: usize) -> bool {
+        let watchpoints = &mut cpu.memory.watchpoints.list;
+        let before = self.breakpoints.len() + watchpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        watchpoints.retain(|watchpoint| watchpoint.id != id);
+        before != self.breakpoints.len() + watchpoints.len()
+    }
+
+    fn take_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    // Runs over CALL and RST until they return to the next instruction on the same stack
+    fn step_over(&mut self, cpu: &mut Cpu) -> Stop {
+        let pc = cpu.memory.pc as u16;
+        let opcode = cpu.memory.peek(pc as usize);
+        let mnemonic = OPCODE_MNEMONICS[opcode as usize];
+        if !mnemonic.starts_with("CALL") && !mnemonic.starts_with("RST") {
+            return self.run_until(cpu, |_, _, _| true);
+        }
+        let next = pc.wrapping_add(opcode_length(opcode) as u16);
+        let stack = cpu.registers.sp;
+        self.run_until(cpu, |cpu, _, _| {
+            cpu.memory.pc as u16 == next && cpu.registers.sp >= stack
+        })
+    }
+
+    // Steps until `done` holds after an instruction, a breakpoint or watchpoint triggers
+    // or the CPU exits; `done` gets the executed opcode and the number of steps so far
+    pub fn run_until<F: Fn(&mut Cpu, u8, u32) -> bool>(&mut self, cpu: &mut Cpu, done: F) -> Stop {
+        if self.exited {
+            return Stop::Exit;
+        }
+        cpu.memory.watchpoints.take_hit();
+        let mut steps = 0;
+        loop {
+            let pc = cpu.memory.pc as u16;
+            let opcode = cpu.memory.peek(pc as usize);
+            if self.history.back() != Some(&pc) {
+                if self.history.len() == HISTORY_LENGTH {
+                    self.history.pop_front();
+                }
+                self.history.push_back(pc);
+            }
+            if cpu.step().is_none() {
+                self.exited = true;
+                return Stop::Exit;
+            }
+            steps += 1;
+            if let Some(hit) = cpu.memory.watchpoints.take_hit() {
+                return Stop::Watch(hit);
+            }
+            if let Some(breakpoint) = self
+                .breakpoints
+                .iter()
+                .find(|breakpoint| breakpoint.is_hit(cpu))
+            {
+                return Stop::Breakpoint(breakpoint.id);
+            }
+            if done(cpu, opcode, steps) {
+                return Stop::Done;
+            }
+            if steps.is_multiple_of(INTERRUPT_STEPS)
+                && self.interrupt.is_some_and(|interrupt| interrupt())
+            {
+                return Stop::Interrupted;
+            }
+        }
+    }
+
+    fn report<W: Write>(&self, cpu: &mut Cpu, stop: Stop, output: &mut W) -> io::Result<()> {
+        match stop {
+            Stop::Done => {}
+            Stop::Breakpoint(id) => writeln!(output, "Breakpoint {} hit", id)?,
+            Stop::Watch(hit) => writeln!(
+                output,
+                "Watchpoint {}: {} ${:04X} = ${:02X}",
+                hit.id,
+                if hit.write { "write" } else { "read" },
+                hit.address,
+                hit.value
+            )?,
+            Stop::Interrupted => writeln!(output, "Interrupted")?,
+            Stop::Exit => return writeln!(output, "The CPU has exited"),
+        }
+        self.print_location(cpu, output)
+    }
+
+    fn disassembler(&self) -> Disassembler<'_> {
+        Disassembler::new(self.symbols.as_ref())
+    }
+
+    fn print_location<W: Write>(&self, cpu: &mut Cpu, output: &mut W) -> io::Result<()> {
+        let line = self.disassembler().line(
+            |address| cpu.memory.peek(address as usize),
+            cpu.memory.pc as u16,
+        );
+        writeln!(output, "=> {}", line)
+    }
+
+    // Without an address the listing starts a few executed instructions before PC,
+    // provided decoding from there lines up with PC again
+    pub fn listing(&self, cpu: &Cpu, address: Option<u16>, count: usize) -> Vec<Line> {
+        let pc = cpu.memory.pc as u16;
+        let read = |address: u16| cpu.memory.peek(address as usize);
+        let disassembler = self.disassembler();
+        match address {
+            Some(address) => disassembler.lines(read, address, count),
+            None => {
+                let start = self
+                    .history
+                    .iter()
+                    .rev()
+                    .take(LISTING_CONTEXT)
+                    .filter(|address| **address < pc)
+                    .min()
+                    .copied()
+                    .unwrap_or(pc);
+                let lines = disassembler.lines(read, start, count);
+                if lines.iter().any(|line| line.address == pc) {
+                    lines
+                } else {
+                    disassembler.lines(read, pc, count)
+                }
+            }
+        }
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.symbols
+            .as_ref()
+            .and_then(|symbols| symbols.label(address, self.disassembler().rom_bank))
+    }
+
+    fn print_listing<W: Write>(
+        &self,
+        cpu: &Cpu,
+        address: Option<u16>,
+        count: usize,
+        output: &mut W,
+    ) -> io::Result<()> {
+        let pc = cpu.memory.pc as u16;
+        for line in self.listing(cpu, address, count) {
+            if let Some(label) = self.label(line.address) {
+                writeln!(output, "{}:", label)?;
+            }
+            let marker = if line.address == pc { "=>" } else { "  " };
+            writeln!(output, "{} {}", marker, line)?;
+        }
+        Ok(())
+    }
+
+    fn print_info<W: Write>(&self, cpu: &Cpu, output: &mut W) -> io::Result<()> {
+        let descriptions = self.descriptions(cpu);
+        if descriptions.is_empty() {
+            return writeln!(output, "No breakpoints or watchpoints");
+        }
+        for description in descriptions {
+            writeln!(output, "{}", description)?;
+        }
+        Ok(())
+    }
+
+    pub fn descriptions(&self, cpu: &Cpu) -> Vec<String> {
+        let breakpoints = self
+            .breakpoints
+            .iter()
+            .map(|breakpoint| self.describe_breakpoint(breakpoint));
+        let watchpoints = cpu.memory.watchpoints.list.iter().map(describe_watchpoint);
+        breakpoints.chain(watchpoints).collect()
+    }
+
+    fn describe_breakpoint(&self, breakpoint: &Breakpoint) -> String {
+        let mut text = format!("Breakpoint {}", breakpoint.id);
+        if let Some(address) = breakpoint.address {
+            text += &format!(" at ${:04X}", address);
+            if let Some(label) = self.label(address) {
+                text += &format!(" ({})", label);
+            }
+        }
+        if let Some(condition) = &breakpoint.condition {
+            text += &format!(" if {}", condition);
+        }
+        text
+    }
+}
+
+fn ctrl_c_pressed() -> bool {
+    CTRL_C.swap(false, Ordering::Relaxed)
+}
+
+fn describe_watchpoint(watchpoint: &Watchpoint) -> String {
+    let access = match watchpoint.access {
+        Access::Read => "read",
+        Access::Write => "write",
+        Access::ReadWrite => "read/write",
+    };
+    if watchpoint.start == watchpoint.end {
+        format!(
+            "Watchpoint {} on ${:04X} ({})",
+            watchpoint.id, watchpoint.start, access
+        )
+    } else {
+        format!(
+            "Watchpoint {} on ${:04X}-${:04X} ({})",
+            watchpoint.id, watchpoint.start, watchpoint.end, access
+        )
+    }
+}
+
+fn print_registers<W: Write>(cpu: &mut Cpu, output: &mut W) -> io::Result<()> {
+    let registers = &mut cpu.registers;
+    let flags: String = ["Z", "N", "H", "C"]
+        .iter()
+        .enumerate()
+        .map(|(bit, name)| {
+            if registers.f.f & (0x80 >> bit) != 0 {
+                *name
+            } else {
+                "-"
+            }
+        })
+        .collect();
+    writeln!(
+        output,
+        "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X} Flags={}",
+        registers.af(),
+        registers.bc(),
+        registers.de(),
+        registers.hl(),
+        registers.sp,
+        cpu.memory.pc,
+        flags
+    )
+}
+
+// Sixteen bytes per line followed by their printable characters, stops at the end of memory
+fn dump<W: Write>(cpu: &Cpu, start: u16, length: u16, output: &mut W) -> io::Result<()> {
+    let end = (start as u32 + length as u32).min(0x10000);
+    for line in (start as u32..end).step_by(DUMP_WIDTH as usize) {
+        let bytes: Vec<u8> = (line..end.min(line + DUMP_WIDTH))
+            .map(|address| cpu.memory.peek(address as usize))
+            .collect();
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text: String = bytes
+            .iter()
+            .map(|byte| {
+                if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(output, "{:04X}  {:<47}  |{}|", line, hex.join(" "), text)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debug(source: &str, input: &str) -> (Cpu, String) {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0, &asm!(source));
+        let mut output = Vec::new();
+        Debugger::new(None)
+            .run(&mut cpu, input.as_bytes(), &mut output)
+            .unwrap();
+        (cpu, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn it_should_step_and_repeat_the_last_command() {
+        let (cpu, output) = debug("ld a,1\nld b,2\nld c,3\nld d,4", "s\n\nstep 2\n");
+
+        assert_eq!(cpu.memory.pc, 8);
+        assert!(output.starts_with("=> 0000  3E 01     LD A,$01\n(gb) "));
+        assert!(output.contains("=> 0004  0E 03     LD C,$03"));
+    }
+
+    #[test]
+    fn it_should_pause_a_continue_on_ctrl_c() {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0, &asm!("halt"));
+        let mut debugger = Debugger::new(None);
+        debugger.interrupt = Some(ctrl_c_pressed);
+        CTRL_C.store(true, Ordering::Relaxed);
+
+        assert_eq!(
+            debugger.run_until(&mut cpu, |_, _, _| false),
+            Stop::Interrupted
+        );
+        assert!(!CTRL_C.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn it_should_stop_at_breakpoints() {
+        let (cpu, output) = debug("ld a,1\nld b,2\nld c,3", "b $0004\nc\n");
+
+        assert_eq!(cpu.memory.pc, 4);
+        assert!(output.contains("Breakpoint 1 at $0004\n"));
+        assert!(output.contains("Breakpoint 1 hit\n=> 0004"));
+    }
+
+    #[test]
+    fn it_should_stop_on_conditional_breakpoints() {
+        let (cpu, output) = debug("ld a,$3D\ninc a\ninc a\ninc a", "break if a == 0x3F\nc\n");
+
+        assert_eq!(cpu.registers.a, 0x3F);
+        assert_eq!(cpu.memory.pc, 4);
+        assert!(output.contains("Breakpoint 1 if a == 0x3F\n"));
+    }
+
+    #[test]
+    fn it_should_stop_on_watchpoints() {
+        let (cpu, output) = debug(
+            "ld a,$42\nld [$C000],a\nld a,[$C001]\nnop",
+            "w $C000 $C001 w\nw $C001 r\nc\nc\n",
+        );
+
+        assert!(output.contains("Watchpoint 1: write $C000 = $42\n=> 0005"));
+        assert!(output.contains("Watchpoint 2: read $C001 = $00\n=> 0008"));
+        assert_eq!(cpu.memory.pc, 8);
+    }
+
+    #[test]
+    fn it_should_report_when_the_cpu_exits() {
+        let (_, output) = debug("nop", "c\ns\n");
+
+        assert_eq!(output.matches("The CPU has exited").count(), 2);
+    }
+
+    #[test]
+    fn it_should_delete_breakpoints() {
+        let (_, output) = debug("nop", "b $10\nw $C000\nd 1\ni\nd 7\n");
+
+        assert!(output.contains("(gb) Watchpoint 2 on $C000 (read/write)\n(gb) no breakpoint"));
+    }
+
+    #[test]
+    fn it_should_print_registers_and_memory() {
+        let (_, output) = debug("ld a,$41\nld [$C000],a\nscf", "s 3\nr\nx $C000 2\n");
+
+        assert!(output.contains("AF=4110 BC=0000 DE=0000 HL=0000 SP=0000 PC=0006 Flags=---C"));
+        assert!(output.contains("C000  41 00"));
+        assert!(output.contains("|A.|"));
+    }
+
+    #[test]
+    fn it_should_list_around_pc() {
+        let (_, output) = debug("ld a,1\nld b,2\nld c,3", "s 2\nl 0 4\nl\n");
+
+        assert!(output.contains("   0000  3E 01     LD A,$01\n   0002"));
+        assert!(output.contains("   0002  06 02     LD B,$02\n=> 0004  0E 03     LD C,$03"));
+    }
+}
@@ -0,0 +1,177 @@
+#![allow(dead_code)]
+use crate::debugger::condition::{parse_address, Condition};
+use crate::disasm::symbols::Symbols;
+use crate::processor::watch::Access;
+
+const DEFAULT_DUMP_LENGTH: u16 = 64;
+const DEFAULT_LISTING_LINES: usize = 10;
+
+pub const HELP: &str = "\
+Commands:
+  s, step [N]               Execute N instructions (default: 1)
+  n, next                   Step over CALL and RST
+  o, out                    Run until the current function returns
+  c, continue               Run until a breakpoint or watchpoint
+  b, break <ADDR> [if <CONDITION>]
+  b, break if <CONDITION>   Stop at ADDR, or wherever CONDITION holds (e.g. a == 0x3F)
+  w, watch <ADDR> [END] [r|w|rw]
+                            Stop when memory in ADDR..=END is read or written
+  d, delete <ID>            Remove a breakpoint or watchpoint
+  i, info                   List breakpoints and watchpoints
+  r, regs                   Print the registers and flags
+  x <ADDR> [LENGTH]         Hex dump memory (default: 64 bytes)
+  l, list [ADDR] [N]        Disassemble N instructions (default: around PC)
+  h, help                   Print this help
+  q, quit                   Leave the debugger
+An empty line repeats the last command. Addresses accept $, 0x or labels from the .sym file.
+";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Step(u32),
+    Next,
+    Out,
+    Continue,
+    Break(Option<u16>, Option<Condition>),
+    Watch(u16, u16, Access),
+    Delete(usize),
+    Info,
+    Registers,
+    Examine(u16, u16),
+    List(Option<u16>, usize),
+    Help,
+    Quit,
+}
+
+pub fn parse(line: &str, symbols: Option<&Symbols>) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or("");
+    let arguments: Vec<&str> = words.collect();
+    let address = |index: usize| -> Result<Option<u16>, String> {
+        arguments
+            .get(index)
+            .map(|text| parse_address(text, symbols))
+            .transpose()
+    };
+    let number = |index: usize| -> Result<Option<u32>, String> {
+        arguments
+            .get(index)
+            .map(|text| {
+                text.parse()
+                    .map_err(|_| format!("expected a number, got '{}'", text))
+            })
+            .transpose()
+    };
+    match name {
+        "s" | "step" => Ok(Command::Step(number(0)?.unwrap_or(1))),
+        "n" | "next" => Ok(Command::Next),
+        "o" | "out" => Ok(Command::Out),
+        "c" | "continue" => Ok(Command::Continue),
+        "b" | "break" => parse_break(&arguments, symbols),
+        "w" | "watch" => parse_watch(&arguments, symbols),
+        "d" | "delete" => number(0)?
+            .map(|id| Command::Delete(id as usize))
+            .ok_or_else(|| "delete needs an ID".to_string()),
+        "i" | "info" => Ok(Command::Info),
+        "r" | "regs" => Ok(Command::Registers),
+        "x" => {
+            let start = address(0)?.ok_or_else(|| "x needs an address".to_string())?;
+            let length = address(1)?.unwrap_or(DEFAULT_DUMP_LENGTH);
+            Ok(Command::Examine(start, length))
+        }
+        "l" | "list" => Ok(Command::List(
+            address(0)?,
+            number(1)?.map_or(DEFAULT_LISTING_LINES, |lines| lines as usize),
+        )),
+        "h" | "help" => Ok(Command::Help),
+        "q" | "quit" => Ok(Command::Quit),
+        _ => Err(format!("unknown command '{}', type help for a list", name)),
+    }
+}
+
+fn parse_break(arguments: &[&str], symbols: Option<&Symbols>) -> Result<Command, String> {
+    let (address, rest) = match arguments {
+        ["if", ..] => (None, arguments),
+        [address, rest @ ..] => (Some(parse_address(address, symbols)?), rest),
+        [] => return Err("break needs an address or a condition".to_string()),
+    };
+    let condition = match rest {
+        [] => None,
+        ["if", condition @ ..] => Some(Condition::parse(&condition.join(" "), symbols)?),
+        _ => return Err("expected 'if' after the breakpoint address".to_string()),
+    };
+    Ok(Command::Break(address, condition))
+}
+
+fn parse_watch(arguments: &[&str], symbols: Option<&Symbols>) -> Result<Command, String> {
+    let (access, arguments) = match arguments.split_last() {
+        Some((&"r", rest)) => (Access::Read, rest),
+        Some((&"w", rest)) => (Access::Write, rest),
+        Some((&"rw", rest)) => (Access::ReadWrite, rest),
+        _ => (Access::ReadWrite, arguments),
+    };
+    match arguments {
+        [start] => {
+            let start = parse_address(start, symbols)?;
+            Ok(Command::Watch(start, start, access))
+        }
+        [start, end] => {
+            let (start, end) = (parse_address(start, symbols)?, parse_address(end, symbols)?);
+            if end < start {
+                return Err("the end of a watchpoint comes before its start".to_string());
+            }
+            Ok(Command::Watch(start, end, access))
+        }
+        _ => Err("watch needs an address and an optional end".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_stepping_commands() {
+        assert_eq!(parse("s", None), Ok(Command::Step(1)));
+        assert_eq!(parse("step 20", None), Ok(Command::Step(20)));
+        assert_eq!(parse("n", None), Ok(Command::Next));
+        assert_eq!(parse("c", None), Ok(Command::Continue));
+        assert!(parse("step x", None).is_err());
+    }
+
+    #[test]
+    fn it_should_parse_breakpoints() {
+        let symbols = Symbols::parse("00:0150 Main\n");
+
+        assert_eq!(
+            parse("b Main", Some(&symbols)),
+            Ok(Command::Break(Some(0x150), None))
+        );
+        let Ok(Command::Break(None, Some(condition))) = parse("break if a == 0x3F", None) else {
+            panic!("expected a conditional breakpoint");
+        };
+        assert_eq!(condition.to_string(), "a == 0x3F");
+        assert!(parse("b $150 when a", None).is_err());
+    }
+
+    #[test]
+    fn it_should_parse_watchpoints() {
+        assert_eq!(
+            parse("w $C000", None),
+            Ok(Command::Watch(0xC000, 0xC000, Access::ReadWrite))
+        );
+        assert_eq!(
+            parse("watch $C000 $C0FF w", None),
+            Ok(Command::Watch(0xC000, 0xC0FF, Access::Write))
+        );
+        assert!(parse("w $C0FF $C000", None).is_err());
+    }
+
+    #[test]
+    fn it_should_parse_memory_commands() {
+        assert_eq!(parse("x $C000", None), Ok(Command::Examine(0xC000, 64)));
+        assert_eq!(parse("l", None), Ok(Command::List(None, 10)));
+        assert_eq!(parse("l $100 4", None), Ok(Command::List(Some(0x100), 4)));
+        assert!(parse("frobnicate", None).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+#![allow(dead_code)]
+use std::fmt;
+
+use crate::cli::parse_value;
+use crate::disasm::symbols::Symbols;
+use crate::processor::cpu::Cpu;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+impl Register {
+    fn from_name(name: &str) -> Option<Register> {
+        match name.to_ascii_lowercase().as_str() {
+            "a" => Some(Register::A),
+            "b" => Some(Register::B),
+            "c" => Some(Register::C),
+            "d" => Some(Register::D),
+            "e" => Some(Register::E),
+            "f" => Some(Register::F),
+            "h" => Some(Register::H),
+            "l" => Some(Register::L),
+            "af" => Some(Register::AF),
+            "bc" => Some(Register::BC),
+            "de" => Some(Register::DE),
+            "hl" => Some(Register::HL),
+            "sp" => Some(Register::SP),
+            "pc" => Some(Register::PC),
+            _ => None,
+        }
+    }
+
+    fn value(&self, cpu: &mut Cpu) -> u16 {
+        let registers = &mut cpu.registers;
+        match self {
+            Register::A => registers.a as u16,
+            Register::B => registers.b as u16,
+            Register::C => registers.c as u16,
+            Register::D => registers.d as u16,
+            Register::E => registers.e as u16,
+            Register::F => registers.f.f as u16,
+            Register::H => registers.h as u16,
+            Register::L => registers.l as u16,
+            Register::AF => registers.af(),
+            Register::BC => registers.bc(),
+            Register::DE => registers.de(),
+            Register::HL => registers.hl(),
+            Register::SP => registers.sp as u16,
+            Register::PC => cpu.memory.pc as u16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Register(Register),
+    Memory(u16),
+    Constant(u16),
+}
+
+impl Value {
+    // A register name, a byte of memory as [ADDR] or a number
+    fn parse(text: &str, symbols: Option<&Symbols>) -> Result<Value, String> {
+        if let Some(register) = Register::from_name(text) {
+            return Ok(Value::Register(register));
+        }
+        if let Some(address) = text
+            .strip_prefix('[')
+            .and_then(|text| text.strip_suffix(']'))
+        {
+            return Ok(Value::Memory(parse_address(address, symbols)?));
+        }
+        parse_address(text, symbols).map(Value::Constant)
+    }
+
+    fn get(&self, cpu: &mut Cpu) -> u16 {
+        match self {
+            Value::Register(register) => register.value(cpu),
+            Value::Memory(address) => cpu.memory.peek(*address as usize) as u16,
+            Value::Constant(value) => *value,
+        }
+    }
+}
+
+const COMPARISONS: [&str; 6] = ["==", "!=", "<=", ">=", "<", ">"];
+
+// A single comparison such as `a == 0x3F`, `[$C000] != 0` or `hl >= wBuffer`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    left: Value,
+    comparison: &'static str,
+    right: Value,
+    text: String,
+}
+
+impl Condition {
+    pub fn parse(text: &str, symbols: Option<&Symbols>) -> Result<Condition, String> {
+        let text = text.trim();
+        let (index, comparison) = COMPARISONS
+            .iter()
+            .find_map(|comparison| text.find(comparison).map(|index| (index, *comparison)))
+            .ok_or_else(|| format!("expected a comparison in '{}'", text))?;
+        Ok(Condition {
+            left: Value::parse(text[..index].trim(), symbols)?,
+            comparison,
+            right: Value::parse(text[index + comparison.len()..].trim(), symbols)?,
+            text: text.to_string(),
+        })
+    }
+
+    pub fn is_met(&self, cpu: &mut Cpu) -> bool {
+        let (left, right) = (self.left.get(cpu), self.right.get(cpu));
+        match self.comparison {
+            "==" => left == right,
+            "!=" => left != right,
+            "<=" => left <= right,
+            ">=" => left >= right,
+            "<" => left < right,
+            _ => left > right,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&self.text)
+    }
+}
+
+// Numbers as on the command line, or a label from the symbol file
+pub fn parse_address(text: &str, symbols: Option<&Symbols>) -> Result<u16, String> {
+    parse_value(text)
+        .and_then(|value| u16::try_from(value).ok())
+        .or_else(|| symbols.and_then(|symbols| symbols.address(text)))
+        .ok_or_else(|| format!("invalid address or value '{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_compare_registers() {
+        let mut cpu = Cpu::new();
+        let condition = Condition::parse("a == 0x3F", None).unwrap();
+
+        assert!(!condition.is_met(&mut cpu));
+        cpu.registers.a = 0x3F;
+        assert!(condition.is_met(&mut cpu));
+    }
+
+    #[test]
+    fn it_should_compare_memory_and_labels() {
+        let mut cpu = Cpu::new();
+        let symbols = Symbols::parse("00:c000 wCounter\n");
+        let condition = Condition::parse("[wCounter] >= 2", Some(&symbols)).unwrap();
+        cpu.memory.write(0xC000, 2);
+
+        assert!(condition.is_met(&mut cpu));
+        assert_eq!(condition.to_string(), "[wCounter] >= 2");
+    }
+
+    #[test]
+    fn it_should_reject_invalid_conditions() {
+        assert!(Condition::parse("a", None).is_err());
+        assert!(Condition::parse("q == 1", None).is_err());
+    }
+}
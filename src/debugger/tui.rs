@@ -0,0 +1,384 @@
+#![allow(dead_code)]
+pub mod canvas;
+pub mod panes;
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crossterm::cursor::{Hide, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{
+    self, disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+
+use crate::debugger::command::{self, Command};
+use crate::debugger::tui::canvas::{plain, Canvas, Rect, Spans, Style};
+use crate::debugger::tui::panes::{Snapshot, MEMORY_WIDTH};
+use crate::debugger::Debugger;
+use crate::disasm::symbols::Symbols;
+use crate::processor::cpu::Cpu;
+
+const SIDE_WIDTH: u16 = 24;
+const REGISTERS_HEIGHT: u16 = 9;
+const MEMORY_HEIGHT: u16 = 10;
+const MEMORY_PAGE: u16 = 0x80;
+const KEYS: &str = "s step  n next  o out  c continue  b break  : command  Tab memory  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Focus {
+    Code,
+    Memory,
+}
+
+// Full-screen front end for the debugger, drawn with plain terminal escapes so it
+// works over SSH; every command of the REPL is available after ':'
+pub struct Tui {
+    debugger: Debugger,
+    previous: Snapshot,
+    focus: Focus,
+    memory_start: u16,
+    cursor: u16,
+    low_nibble: bool,
+    input: Option<String>,
+    message: String,
+    quit: bool,
+}
+
+impl Tui {
+    pub fn new(cpu: &mut Cpu, symbols: Option<Symbols>) -> Tui {
+        let mut debugger = Debugger::new(symbols);
+        debugger.interrupt = Some(key_pressed);
+        Tui {
+            debugger,
+            previous: panes::snapshot(cpu),
+            focus: Focus::Code,
+            memory_start: 0xC000,
+            cursor: 0xC000,
+            low_nibble: false,
+            input: None,
+            message: "press a key while running to pause".to_string(),
+            quit: false,
+        }
+    }
+
+    pub fn run(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        let mut output = io::stdout();
+        let _terminal = TerminalGuard::enter(&mut output)?;
+        self.event_loop(cpu, &mut output)
+    }
+
+    fn event_loop<W: Write>(&mut self, cpu: &mut Cpu, output: &mut W) -> io::Result<()> {
+        while !self.quit {
+            let (width, height) = terminal::size()?;
+            self.render(cpu, width, height).draw(output)?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Release {
+                    self.handle_key(cpu, key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Registers and breakpoints on the left, the stack and I/O registers on the right,
+    // the disassembly in the middle and the memory editor below
+    pub fn render(&self, cpu: &mut Cpu, width: u16, height: u16) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        let top = height.saturating_sub(MEMORY_HEIGHT + 1);
+        let middle = width.saturating_sub(SIDE_WIDTH * 2);
+        let registers = Rect::new(0, 0, SIDE_WIDTH, REGISTERS_HEIGHT.min(top));
+        let breakpoints = Rect::new(0, registers.height, SIDE_WIDTH, top - registers.height);
+        let code = Rect::new(SIDE_WIDTH, 0, middle, top);
+        let stack = Rect::new(SIDE_WIDTH + middle, 0, SIDE_WIDTH, top / 2);
+        let io = Rect::new(SIDE_WIDTH + middle, stack.height, SIDE_WIDTH, top - top / 2);
+        let memory = Rect::new(0, top, width, height.saturating_sub(top + 1));
+
+        canvas.pane(
+            registers,
+            "Registers",
+            &panes::registers(cpu, &self.previous),
+        );
+        canvas.pane(
+            breakpoints,
+            "Breakpoints",
+            &panes::breakpoints(&self.debugger, cpu),
+        );
+        canvas.pane(
+            code,
+            "Disassembly",
+            &panes::disassembly(&self.debugger, cpu, code.inner_height()),
+        );
+        canvas.pane(stack, "Stack", &panes::stack(cpu, stack.inner_height()));
+        canvas.pane(io, "I/O", &panes::io_registers(cpu, io.inner_height()));
+        let cursor = (self.focus == Focus::Memory).then_some(self.cursor);
+        let title = if self.focus == Focus::Memory {
+            "Memory (editing)"
+        } else {
+            "Memory"
+        };
+        canvas.pane(
+            memory,
+            title,
+            &panes::memory(cpu, self.memory_start, cursor, memory.inner_height()),
+        );
+        let status: Spans = match &self.input {
+            Some(input) => plain(format!(":{}", input)),
+            None if self.message.is_empty() => vec![(KEYS.to_string(), Style::Dim)],
+            None => plain(self.message.clone()),
+        };
+        let mut x = 0;
+        for (text, style) in status {
+            x += canvas.print(x, height.saturating_sub(1), &text, style, width - x);
+        }
+        canvas
+    }
+
+    pub fn handle_key(&mut self, cpu: &mut Cpu, key: KeyEvent) {
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Enter => {
+                    let line = std::mem::take(input);
+                    self.input = None;
+                    match command::parse(&line, self.debugger.symbols.as_ref()) {
+                        Ok(command) => self.execute(cpu, command),
+                        Err(error) => self.message = error,
+                    }
+                }
+                KeyCode::Esc => self.input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+            return;
+        }
+        self.message.clear();
+        match (self.focus, key.code) {
+            (_, KeyCode::Tab) => {
+                self.focus = match self.focus {
+                    Focus::Code => Focus::Memory,
+                    Focus::Memory => Focus::Code,
+                };
+                self.low_nibble = false;
+            }
+            (_, KeyCode::Char(':')) => self.input = Some(String::new()),
+            (Focus::Code, KeyCode::Char('q')) => self.quit = true,
+            (Focus::Code, KeyCode::Char('s') | KeyCode::F(7)) => {
+                self.execute(cpu, Command::Step(1))
+            }
+            (Focus::Code, KeyCode::Char('n') | KeyCode::F(8)) => self.execute(cpu, Command::Next),
+            (Focus::Code, KeyCode::Char('o')) => self.execute(cpu, Command::Out),
+            (Focus::Code, KeyCode::Char('c') | KeyCode::F(5)) => {
+                self.execute(cpu, Command::Continue)
+            }
+            (Focus::Code, KeyCode::Char('b')) => self.toggle_breakpoint(cpu),
+            (Focus::Memory, code) => self.edit_memory(cpu, code),
+            _ => {}
+        }
+    }
+
+    // Runs a REPL command, its output becomes the status line; `x` moves the memory view
+    fn execute(&mut self, cpu: &mut Cpu, command: Command) {
+        if let Command::Examine(address, _) = command {
+            self.memory_start = address & !(MEMORY_WIDTH - 1);
+            self.cursor = address;
+            return;
+        }
+        if command == Command::Quit {
+            self.quit = true;
+            return;
+        }
+        if matches!(
+            command,
+            Command::Step(_) | Command::Next | Command::Out | Command::Continue
+        ) {
+            self.previous = panes::snapshot(cpu);
+        }
+        let mut output = Vec::new();
+        if let Err(error) = self.debugger.execute(cpu, &command, &mut output) {
+            self.message = error.to_string();
+            return;
+        }
+        let output = String::from_utf8_lossy(&output);
+        self.message = output
+            .lines()
+            .filter(|line| !line.starts_with("=>"))
+            .collect::<Vec<_>>()
+            .join("  ");
+    }
+
+    fn toggle_breakpoint(&mut self, cpu: &mut Cpu) {
+        let pc = cpu.memory.pc as u16;
+        let existing = self
+            .debugger
+            .breakpoints
+            .iter()
+            .find(|breakpoint| breakpoint.address == Some(pc) && breakpoint.condition.is_none())
+            .map(|breakpoint| breakpoint.id);
+        match existing {
+            Some(id) => {
+                self.debugger.delete(cpu, id);
+            }
+            None => {
+                self.debugger.add_breakpoint(Some(pc), None);
+            }
+        }
+    }
+
+    // Arrows move the cursor, hex digits overwrite the high then the low nibble
+    fn edit_memory(&mut self, cpu: &mut Cpu, code: KeyCode) {
+        let movement = match code {
+            KeyCode::Left => Some(self.cursor.wrapping_sub(1)),
+            KeyCode::Right => Some(self.cursor.wrapping_add(1)),
+            KeyCode::Up => Some(self.cursor.wrapping_sub(MEMORY_WIDTH)),
+            KeyCode::Down => Some(self.cursor.wrapping_add(MEMORY_WIDTH)),
+            KeyCode::PageUp => Some(self.cursor.wrapping_sub(MEMORY_PAGE)),
+            KeyCode::PageDown => Some(self.cursor.wrapping_add(MEMORY_PAGE)),
+            _ => None,
+        };
+        if let Some(cursor) = movement {
+            self.move_cursor(cursor);
+            return;
+        }
+        let Some(digit) = (match code {
+            KeyCode::Char(c) => c.to_digit(16),
+            _ => None,
+        }) else {
+            return;
+        };
+        let address = self.cursor as usize;
+        let value = cpu.memory.peek(address);
+        let value = if self.low_nibble {
+            (value & 0xF0) | digit as u8
+        } else {
+            (value & 0x0F) | (digit as u8) << 4
+        };
+        cpu.memory.write(address, value);
+        cpu.memory.watchpoints.take_hit();
+        if self.low_nibble {
+            self.move_cursor(self.cursor.wrapping_add(1));
+        } else {
+            self.low_nibble = true;
+        }
+    }
+
+    // The view scrolls a row at a time to keep the cursor visible
+    fn move_cursor(&mut self, cursor: u16) {
+        self.cursor = cursor;
+        self.low_nibble = false;
+        let rows = (MEMORY_HEIGHT - 2) * MEMORY_WIDTH;
+        let offset = cursor.wrapping_sub(self.memory_start);
+        if offset >= rows {
+            let row = cursor & !(MEMORY_WIDTH - 1);
+            self.memory_start = if offset > u16::MAX / 2 {
+                row
+            } else {
+                row.wrapping_sub(rows - MEMORY_WIDTH)
+            };
+        }
+    }
+}
+
+// Puts the terminal back however the TUI ends, an error or a panic included
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter<W: Write>(output: &mut W) -> io::Result<TerminalGuard> {
+        enable_raw_mode()?;
+        let guard = TerminalGuard;
+        execute!(output, EnterAlternateScreen, Hide)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+// Consumes a pending key press, used to pause a running program
+fn key_pressed() -> bool {
+    matches!(event::poll(Duration::ZERO), Ok(true))
+        && matches!(event::read(), Ok(Event::Key(key)) if key.kind != KeyEventKind::Release)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn press(tui: &mut Tui, cpu: &mut Cpu, keys: &str) {
+        for c in keys.chars() {
+            let code = match c {
+                '\t' => KeyCode::Tab,
+                '\n' => KeyCode::Enter,
+                c => KeyCode::Char(c),
+            };
+            tui.handle_key(cpu, KeyEvent::new(code, KeyModifiers::NONE));
+        }
+    }
+
+    fn setup() -> (Tui, Cpu) {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0, &asm!("ld a,1\nld b,2\nld c,3"));
+        let mut tui = Tui::new(&mut cpu, None);
+        tui.debugger.interrupt = None;
+        (tui, cpu)
+    }
+
+    #[test]
+    fn it_should_step_and_highlight_changes() {
+        let (mut tui, mut cpu) = setup();
+        press(&mut tui, &mut cpu, "s");
+        let canvas = tui.render(&mut cpu, 80, 24);
+
+        assert!(canvas.row(1).contains("AF 0100"));
+        assert_eq!(canvas.style_at(4, 1), Style::Changed);
+        assert_eq!(canvas.style_at(4, 2), Style::Normal);
+        assert!((0..24).any(|y| canvas.row(y).contains("0002  06 02     LD B,$02")));
+    }
+
+    #[test]
+    fn it_should_run_commands_and_toggle_breakpoints() {
+        let (mut tui, mut cpu) = setup();
+        press(&mut tui, &mut cpu, ":b $0004\n");
+        press(&mut tui, &mut cpu, "c");
+
+        assert_eq!(cpu.memory.pc, 4);
+        assert_eq!(tui.message, "Breakpoint 1 hit");
+        press(&mut tui, &mut cpu, "b");
+        assert!(tui.debugger.breakpoints.is_empty());
+        press(&mut tui, &mut cpu, "b");
+        assert_eq!(tui.debugger.breakpoints[0].address, Some(4));
+    }
+
+    #[test]
+    fn it_should_edit_memory() {
+        let (mut tui, mut cpu) = setup();
+        press(&mut tui, &mut cpu, ":x $C010\n\t4a2");
+
+        assert_eq!(cpu.memory.peek(0xC010), 0x4A);
+        assert_eq!(cpu.memory.peek(0xC011), 0x20);
+        assert_eq!(tui.memory_start, 0xC010);
+        let canvas = tui.render(&mut cpu, 80, 24);
+        assert!(canvas.row(14).contains("C010  4A 20"));
+    }
+
+    #[test]
+    fn it_should_scroll_memory_with_the_cursor() {
+        let (mut tui, mut cpu) = setup();
+        press(&mut tui, &mut cpu, "\t");
+        for _ in 0..8 {
+            tui.handle_key(&mut cpu, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        }
+
+        assert_eq!(tui.cursor, 0xC080);
+        assert_eq!(tui.memory_start, 0xC010);
+        tui.handle_key(&mut cpu, KeyEvent::new(KeyCode::PageUp, KeyModifiers::NONE));
+        assert_eq!(tui.memory_start, 0xC000);
+    }
+}
@@ -0,0 +1,207 @@
+#![allow(dead_code)]
+use std::io::{self, Write};
+
+use crossterm::cursor::MoveTo;
+use crossterm::queue;
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Normal,
+    Title,
+    Dim,
+    Changed,
+    Current,
+    Cursor,
+    Breakpoint,
+}
+
+// Text runs that make up one line of a pane
+pub type Spans = Vec<(String, Style)>;
+
+pub fn plain<S: Into<String>>(text: S) -> Spans {
+    vec![(text.into(), Style::Normal)]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // Rows available inside the frame
+    pub fn inner_height(&self) -> usize {
+        self.height.saturating_sub(2) as usize
+    }
+}
+
+// The whole screen is drawn into cells first then written row by row, so nothing flickers
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    pub width: u16,
+    pub height: u16,
+    cells: Vec<(char, Style)>,
+}
+
+impl Canvas {
+    pub fn new(width: u16, height: u16) -> Canvas {
+        Canvas {
+            width,
+            height,
+            cells: vec![(' ', Style::Normal); width as usize * height as usize],
+        }
+    }
+
+    // Clipped to the canvas and to `limit` columns
+    pub fn print(&mut self, x: u16, y: u16, text: &str, style: Style, limit: u16) -> u16 {
+        if y >= self.height {
+            return 0;
+        }
+        let end = x.saturating_add(limit).min(self.width);
+        let mut column = x;
+        for c in text.chars() {
+            if column >= end {
+                break;
+            }
+            self.cells[y as usize * self.width as usize + column as usize] = (c, style);
+            column += 1;
+        }
+        column - x.min(column)
+    }
+
+    pub fn pane(&mut self, area: Rect, title: &str, lines: &[Spans]) {
+        if area.width < 2 || area.height < 2 {
+            return;
+        }
+        let right = area.x + area.width - 1;
+        let bottom = area.y + area.height - 1;
+        let horizontal = "─".repeat(area.width as usize - 2);
+        self.print(
+            area.x,
+            area.y,
+            &format!("┌{}┐", horizontal),
+            Style::Dim,
+            area.width,
+        );
+        self.print(
+            area.x,
+            bottom,
+            &format!("└{}┘", horizontal),
+            Style::Dim,
+            area.width,
+        );
+        for y in area.y + 1..bottom {
+            self.print(area.x, y, "│", Style::Dim, 1);
+            self.print(right, y, "│", Style::Dim, 1);
+        }
+        self.print(
+            area.x + 2,
+            area.y,
+            &format!(" {} ", title),
+            Style::Title,
+            area.width.saturating_sub(4),
+        );
+        for (row, spans) in lines.iter().take(area.inner_height()).enumerate() {
+            let y = area.y + 1 + row as u16;
+            let mut x = area.x + 1;
+            for (text, style) in spans {
+                x += self.print(x, y, text, *style, right - x);
+            }
+        }
+    }
+
+    pub fn row(&self, y: u16) -> String {
+        let start = y as usize * self.width as usize;
+        self.cells[start..start + self.width as usize]
+            .iter()
+            .map(|(c, _)| *c)
+            .collect()
+    }
+
+    pub fn style_at(&self, x: u16, y: u16) -> Style {
+        self.cells[y as usize * self.width as usize + x as usize].1
+    }
+
+    pub fn draw<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for y in 0..self.height {
+            queue!(output, MoveTo(0, y))?;
+            let start = y as usize * self.width as usize;
+            let row = &self.cells[start..start + self.width as usize];
+            let mut index = 0;
+            while index < row.len() {
+                let style = row[index].1;
+                let run: String = row[index..]
+                    .iter()
+                    .take_while(|(_, other)| *other == style)
+                    .map(|(c, _)| *c)
+                    .collect();
+                index += run.chars().count();
+                apply(output, style)?;
+                queue!(
+                    output,
+                    Print(run),
+                    SetAttribute(Attribute::Reset),
+                    ResetColor
+                )?;
+            }
+        }
+        output.flush()
+    }
+}
+
+fn apply<W: Write>(output: &mut W, style: Style) -> io::Result<()> {
+    match style {
+        Style::Normal => Ok(()),
+        Style::Title => queue!(output, SetAttribute(Attribute::Bold)),
+        Style::Dim => queue!(output, SetForegroundColor(Color::DarkGrey)),
+        Style::Changed => queue!(output, SetForegroundColor(Color::Yellow)),
+        Style::Current | Style::Cursor => queue!(output, SetAttribute(Attribute::Reverse)),
+        Style::Breakpoint => queue!(output, SetForegroundColor(Color::Red)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_frame_panes_and_clip_lines() {
+        let mut canvas = Canvas::new(12, 4);
+        let lines = vec![
+            plain("short"),
+            vec![
+                ("long ".to_string(), Style::Normal),
+                ("line!".to_string(), Style::Changed),
+            ],
+            plain("hidden"),
+        ];
+        canvas.pane(Rect::new(0, 0, 10, 4), "Regs", &lines);
+
+        assert_eq!(canvas.row(0), "┌─ Regs ─┐  ");
+        assert_eq!(canvas.row(1), "│short   │  ");
+        assert_eq!(canvas.row(2), "│long lin│  ");
+        assert_eq!(canvas.row(3), "└────────┘  ");
+        assert_eq!(canvas.style_at(6, 2), Style::Changed);
+    }
+
+    #[test]
+    fn it_should_clip_to_the_canvas() {
+        let mut canvas = Canvas::new(4, 1);
+        canvas.print(2, 0, "abcdef", Style::Normal, 10);
+        canvas.print(0, 3, "ignored", Style::Normal, 10);
+
+        assert_eq!(canvas.row(0), "  ab");
+    }
+}
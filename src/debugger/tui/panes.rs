@@ -0,0 +1,399 @@
+#![allow(dead_code)]
+use crate::debugger::tui::canvas::{plain, Spans, Style};
+use crate::debugger::Debugger;
+use crate::processor::cpu::Cpu;
+
+pub const MEMORY_WIDTH: u16 = 16;
+
+const REGISTER_NAMES: [&str; 6] = ["AF", "BC", "DE", "HL", "SP", "PC"];
+const FLAG_NAMES: [char; 4] = ['Z', 'N', 'H', 'C'];
+
+// Hardware registers shown in the I/O pane, the ones made of fields are decoded after
+// the value. The pane leaves 14 columns for that, so the decoding is terse
+type Decode = fn(u8) -> String;
+
+const IO_REGISTERS: [(&str, u16, Option<Decode>); 27] = [
+    ("P1", 0xFF00, Some(joypad)),
+    ("SB", 0xFF01, None),
+    ("SC", 0xFF02, Some(serial_control)),
+    ("DIV", 0xFF04, None),
+    ("TIMA", 0xFF05, None),
+    ("TMA", 0xFF06, None),
+    ("TAC", 0xFF07, Some(timer_control)),
+    ("IF", 0xFF0F, Some(interrupts)),
+    ("IE", 0xFFFF, Some(interrupts)),
+    ("LCDC", 0xFF40, Some(lcd_control)),
+    ("STAT", 0xFF41, Some(lcd_status)),
+    ("SCY", 0xFF42, None),
+    ("SCX", 0xFF43, None),
+    ("LY", 0xFF44, None),
+    ("LYC", 0xFF45, None),
+    ("DMA", 0xFF46, None),
+    ("BGP", 0xFF47, Some(shades)),
+    ("OBP0", 0xFF48, Some(shades)),
+    ("OBP1", 0xFF49, Some(shades)),
+    ("WY", 0xFF4A, None),
+    ("WX", 0xFF4B, None),
+    ("KEY1", 0xFF4D, Some(speed_switch)),
+    ("VBK", 0xFF4F, None),
+    ("SVBK", 0xFF70, None),
+    ("NR50", 0xFF24, Some(master_volume)),
+    ("NR51", 0xFF25, Some(panning)),
+    ("NR52", 0xFF26, Some(sound_control)),
+];
+
+const INTERRUPT_NAMES: [char; 5] = ['V', 'L', 'T', 'S', 'J'];
+const STAT_MODES: [&str; 4] = ["hbl", "vbl", "oam", "drw"];
+const STAT_SOURCES: [char; 4] = ['H', 'V', 'O', 'Y'];
+const TIMER_RATES: [&str; 4] = ["4096Hz", "262kHz", "65kHz", "16kHz"];
+
+fn bit(value: u8, bit: u8) -> bool {
+    value & (1 << bit) != 0
+}
+
+// One letter per set bit and a dash per clear one, lowest bit first
+fn letters(value: u8, names: &[char]) -> String {
+    names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| if bit(value, index as u8) { *name } else { '-' })
+        .collect()
+}
+
+// Selecting a group pulls its line low, as do pressed keys
+fn joypad(value: u8) -> String {
+    let group = match (value >> 4) & 0x3 {
+        0b00 => "both",
+        0b01 => "btn",
+        0b10 => "dir",
+        _ => "none",
+    };
+    format!("{} {:04b}", group, value & 0xF)
+}
+
+fn serial_control(value: u8) -> String {
+    let state = if bit(value, 7) { "xfer" } else { "idle" };
+    let clock = if bit(value, 0) { "int" } else { "ext" };
+    format!("{} {}", state, clock)
+}
+
+fn timer_control(value: u8) -> String {
+    let state = if bit(value, 2) { "on" } else { "off" };
+    format!("{} {}", state, TIMER_RATES[(value & 0x3) as usize])
+}
+
+// VBlank, LCD, timer, serial and joypad
+fn interrupts(value: u8) -> String {
+    letters(value, &INTERRUPT_NAMES)
+}
+
+// Enabled layers, then the tile data and maps when they are not at 8000 and 9800
+fn lcd_control(value: u8) -> String {
+    let mut fields = Vec::new();
+    if !bit(value, 7) {
+        fields.push("off");
+    }
+    if bit(value, 0) {
+        fields.push("bg");
+    }
+    if bit(value, 1) {
+        fields.push(if bit(value, 2) { "obj16" } else { "obj" });
+    }
+    if bit(value, 5) {
+        fields.push("win");
+    }
+    if !bit(value, 4) {
+        fields.push("t88");
+    }
+    if bit(value, 3) {
+        fields.push("b9C");
+    }
+    if bit(value, 6) {
+        fields.push("w9C");
+    }
+    fields.join(" ")
+}
+
+// Mode, LY=LYC and the enabled interrupt sources
+fn lcd_status(value: u8) -> String {
+    let coincidence = if bit(value, 2) { " =LY" } else { "" };
+    format!(
+        "{}{} i:{}",
+        STAT_MODES[(value & 0x3) as usize],
+        coincidence,
+        letters(value >> 3, &STAT_SOURCES)
+    )
+}
+
+// Shade of colors 0 to 3
+fn shades(value: u8) -> String {
+    (0..4)
+        .map(|color| char::from(b'0' + ((value >> (color * 2)) & 0x3)))
+        .collect()
+}
+
+fn speed_switch(value: u8) -> String {
+    let speed = if bit(value, 7) { "double" } else { "normal" };
+    let armed = if bit(value, 0) { " armed" } else { "" };
+    format!("{}{}", speed, armed)
+}
+
+fn master_volume(value: u8) -> String {
+    format!("L{} R{}", (value >> 4) & 0x7, value & 0x7)
+}
+
+// Channels sent to each side
+fn panning(value: u8) -> String {
+    let channels = ['1', '2', '3', '4'];
+    format!(
+        "L{} R{}",
+        letters(value >> 4, &channels),
+        letters(value, &channels)
+    )
+}
+
+fn sound_control(value: u8) -> String {
+    if bit(value, 7) {
+        format!("on {}", letters(value, &['1', '2', '3', '4']))
+    } else {
+        "off".to_string()
+    }
+}
+
+pub type Snapshot = [u16; 6];
+
+pub fn snapshot(cpu: &mut Cpu) -> Snapshot {
+    let registers = &mut cpu.registers;
+    [
+        registers.af(),
+        registers.bc(),
+        registers.de(),
+        registers.hl(),
+        registers.sp as u16,
+        cpu.memory.pc as u16,
+    ]
+}
+
+// Values and flags that differ from the previous snapshot are highlighted
+pub fn registers(cpu: &mut Cpu, previous: &Snapshot) -> Vec<Spans> {
+    let current = snapshot(cpu);
+    let mut lines: Vec<Spans> = REGISTER_NAMES
+        .iter()
+        .zip(current.iter().zip(previous))
+        .map(|(name, (value, before))| {
+            vec![
+                (format!("{} ", name), Style::Normal),
+                (format!("{:04X}", value), changed(value != before)),
+            ]
+        })
+        .collect();
+    let mut flags = vec![("F  ".to_string(), Style::Normal)];
+    for (bit, name) in FLAG_NAMES.iter().enumerate() {
+        let mask = 0x80 >> bit;
+        let set = current[0] & mask != 0;
+        let text = if set { *name } else { '-' };
+        flags.push((text.to_string(), changed(set != (previous[0] & mask != 0))));
+    }
+    lines.push(flags);
+    lines
+}
+
+fn changed(changed: bool) -> Style {
+    if changed {
+        Style::Changed
+    } else {
+        Style::Normal
+    }
+}
+
+// Follows PC, breakpoints get a red marker and labels a line of their own
+pub fn disassembly(debugger: &Debugger, cpu: &Cpu, rows: usize) -> Vec<Spans> {
+    let pc = cpu.memory.pc as u16;
+    let mut lines = Vec::new();
+    for line in debugger.listing(cpu, None, rows) {
+        if let Some(label) = debugger.label(line.address) {
+            lines.push(vec![(format!("{}:", label), Style::Title)]);
+        }
+        let breakpoint = debugger
+            .breakpoints
+            .iter()
+            .any(|breakpoint| breakpoint.address == Some(line.address));
+        let marker = if breakpoint {
+            ("*".to_string(), Style::Breakpoint)
+        } else {
+            (" ".to_string(), Style::Normal)
+        };
+        let style = if line.address == pc {
+            Style::Current
+        } else {
+            Style::Normal
+        };
+        lines.push(vec![marker, (format!(" {}", line), style)]);
+    }
+    lines.truncate(rows);
+    lines
+}
+
+// Rows of MEMORY_WIDTH bytes, the cursor byte is shown in reverse video
+pub fn memory(cpu: &Cpu, start: u16, cursor: Option<u16>, rows: usize) -> Vec<Spans> {
+    (0..rows)
+        .map(|row| {
+            let address = start.wrapping_add(row as u16 * MEMORY_WIDTH);
+            let mut spans = vec![(format!("{:04X} ", address), Style::Dim)];
+            let mut text = String::new();
+            for column in 0..MEMORY_WIDTH {
+                let address = address.wrapping_add(column);
+                let byte = cpu.memory.peek(address as usize);
+                let style = if cursor == Some(address) {
+                    Style::Cursor
+                } else {
+                    Style::Normal
+                };
+                spans.push((" ".to_string(), Style::Normal));
+                spans.push((format!("{:02X}", byte), style));
+                text.push(if byte.is_ascii_graphic() {
+                    byte as char
+                } else {
+                    '.'
+                });
+            }
+            spans.push((format!("  {}", text), Style::Dim));
+            spans
+        })
+        .collect()
+}
+
+// Words from SP upwards, the top of the stack first
+pub fn stack(cpu: &Cpu, rows: usize) -> Vec<Spans> {
+    let sp = cpu.registers.sp as u16;
+    (0..rows as u16)
+        .map(|row| {
+            let address = sp.wrapping_add(row * 2);
+            let low = cpu.memory.peek(address as usize) as u16;
+            let high = cpu.memory.peek(address.wrapping_add(1) as usize) as u16;
+            let style = if row == 0 {
+                Style::Current
+            } else {
+                Style::Normal
+            };
+            vec![
+                (format!("{:04X} ", address), Style::Dim),
+                (format!("{:04X}", (high << 8) | low), style),
+            ]
+        })
+        .collect()
+}
+
+pub fn io_registers(cpu: &Cpu, rows: usize) -> Vec<Spans> {
+    IO_REGISTERS
+        .iter()
+        .take(rows)
+        .map(|(name, address, decode)| {
+            let value = cpu.memory.peek(*address as usize);
+            let mut text = format!("{:<4} {:02X}", name, value);
+            if let Some(decode) = decode {
+                text += &format!(" {}", decode(value));
+            }
+            plain(text)
+        })
+        .collect()
+}
+
+pub fn breakpoints(debugger: &Debugger, cpu: &Cpu) -> Vec<Spans> {
+    let descriptions = debugger.descriptions(cpu);
+    if descriptions.is_empty() {
+        return vec![vec![("none".to_string(), Style::Dim)]];
+    }
+    descriptions
+        .into_iter()
+        .map(|description| {
+            plain(
+                description
+                    .replace("Breakpoint ", "#")
+                    .replace("Watchpoint ", "#"),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(spans: &Spans) -> String {
+        spans.iter().map(|(text, _)| text.as_str()).collect()
+    }
+
+    #[test]
+    fn it_should_highlight_changed_registers() {
+        let mut cpu = Cpu::new();
+        let previous = snapshot(&mut cpu);
+        cpu.registers.b = 0x12;
+        cpu.registers.f.set_carry();
+        let lines = registers(&mut cpu, &previous);
+
+        assert_eq!(text(&lines[0]), "AF 0010");
+        assert_eq!(lines[0][1].1, Style::Changed);
+        assert_eq!(text(&lines[1]), "BC 1200");
+        assert_eq!(lines[2][1].1, Style::Normal);
+        assert_eq!(text(&lines[6]), "F  ---C");
+        assert_eq!(lines[6][4].1, Style::Changed);
+        assert_eq!(lines[6][1].1, Style::Normal);
+    }
+
+    #[test]
+    fn it_should_follow_pc_and_mark_breakpoints() {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0, &asm!("ld a,1\nld b,2\nld c,3"));
+        cpu.memory.pc = 2;
+        let mut debugger = Debugger::new(None);
+        debugger.add_breakpoint(Some(4), None);
+        let lines = disassembly(&debugger, &cpu, 2);
+
+        assert_eq!(text(&lines[0]), "  0002  06 02     LD B,$02");
+        assert_eq!(lines[0][1].1, Style::Current);
+        assert_eq!(lines[1][0], ("*".to_string(), Style::Breakpoint));
+    }
+
+    #[test]
+    fn it_should_show_memory_with_the_cursor() {
+        let mut cpu = Cpu::new();
+        cpu.memory.write(0xC001, b'A');
+        let lines = memory(&cpu, 0xC000, Some(0xC001), 2);
+
+        assert!(text(&lines[0]).starts_with("C000  00 41 00"));
+        assert!(text(&lines[0]).ends_with("  .A.............."));
+        assert_eq!(lines[0][4].1, Style::Cursor);
+        assert!(text(&lines[1]).starts_with("C010 "));
+    }
+
+    #[test]
+    fn it_should_show_the_stack_and_io_registers() {
+        let mut cpu = Cpu::new();
+        cpu.registers.set_sp(0xFFFC);
+        cpu.memory.write(0xFFFC, 0x50);
+        cpu.memory.write(0xFFFD, 0x01);
+
+        assert_eq!(text(&stack(&cpu, 1)[0]), "FFFC 0150");
+        cpu.memory.write(0xFF45, 0x90);
+        let io = io_registers(&cpu, IO_REGISTERS.len());
+        assert!(io.iter().any(|line| text(line) == "LYC  90"));
+        assert!(text(&io[0]).starts_with("P1   "));
+    }
+
+    #[test]
+    fn it_should_decode_io_register_fields() {
+        assert_eq!(lcd_control(0x91), "bg");
+        assert_eq!(lcd_control(0xF7), "bg obj16 win w9C");
+        assert_eq!(lcd_control(0x09), "off bg t88 b9C");
+        assert_eq!(lcd_status(0xA5), "vbl =LY i:--O-");
+        assert_eq!(lcd_status(0x43), "drw i:---Y");
+        assert_eq!(interrupts(0xE5), "V-T--");
+        assert_eq!(joypad(0xEE), "dir 1110");
+        assert_eq!(timer_control(0xFC), "on 4096Hz");
+        assert_eq!(shades(0xE4), "0123");
+        assert_eq!(panning(0xF3), "L1234 R12--");
+        assert_eq!(sound_control(0x81), "on 1---");
+        assert_eq!(speed_switch(0x80), "double");
+    }
+}
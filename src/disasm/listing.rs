@@ -0,0 +1,183 @@
+#![allow(dead_code)]
+use std::fmt;
+
+use crate::disasm::symbols::Symbols;
+use crate::processor::instructions::{cb_mnemonic, opcode_length, OPCODE_MNEMONICS, PREFIX};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        write!(
+            formatter,
+            "{:04X}  {:<8}  {}",
+            self.address,
+            bytes.join(" "),
+            self.text
+        )
+    }
+}
+
+// Decodes through the same opcode table as the CPU, memory is read through `read`
+// so ROM files and the live memory bus can both be disassembled
+pub struct Disassembler<'a> {
+    pub symbols: Option<&'a Symbols>,
+    pub rom_bank: u16,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(symbols: Option<&'a Symbols>) -> Disassembler<'a> {
+        Disassembler {
+            symbols,
+            rom_bank: 1,
+        }
+    }
+
+    pub fn line<F: Fn(u16) -> u8>(&self, read: F, address: u16) -> Line {
+        let opcode = read(address);
+        let length = opcode_length(opcode);
+        let bytes: Vec<u8> = (0..length as u16)
+            .map(|offset| read(address.wrapping_add(offset)))
+            .collect();
+        let mnemonic = OPCODE_MNEMONICS[opcode as usize];
+        let text = if opcode == PREFIX {
+            cb_mnemonic(bytes[1])
+        } else if mnemonic.is_empty() {
+            format!("DB ${:02X}", opcode)
+        } else {
+            self.operands(mnemonic, address, &bytes)
+        };
+        Line {
+            address,
+            bytes,
+            text,
+        }
+    }
+
+    pub fn lines<F: Fn(u16) -> u8>(&self, read: F, address: u16, count: usize) -> Vec<Line> {
+        let mut address = address;
+        (0..count)
+            .map(|_| {
+                let line = self.line(&read, address);
+                address = address.wrapping_add(line.bytes.len() as u16);
+                line
+            })
+            .collect()
+    }
+
+    fn operands(&self, mnemonic: &str, address: u16, bytes: &[u8]) -> String {
+        let word = || u16::from_le_bytes([bytes[1], bytes[2]]);
+        if mnemonic.contains("n16") {
+            mnemonic.replace("n16", &self.address_text(word()))
+        } else if mnemonic.contains("a16") {
+            mnemonic.replace("a16", &self.address_text(word()))
+        } else if mnemonic.contains("a8") {
+            mnemonic.replace("a8", &self.address_text(0xFF00 | bytes[1] as u16))
+        } else if mnemonic.contains("n8") {
+            mnemonic.replace("n8", &format!("${:02X}", bytes[1]))
+        } else if mnemonic.starts_with("JR") {
+            let target = address.wrapping_add(2).wrapping_add(bytes[1] as i8 as u16);
+            mnemonic.replace("e8", &self.relative_text(address, target))
+        } else if mnemonic.contains("SP+e8") {
+            mnemonic.replace("+e8", &signed(bytes[1] as i8))
+        } else if mnemonic.contains("e8") {
+            mnemonic.replace("e8", &(bytes[1] as i8).to_string())
+        } else {
+            mnemonic.to_string()
+        }
+    }
+
+    fn address_text(&self, address: u16) -> String {
+        match self.label(address) {
+            Some(label) => label.to_string(),
+            None => format!("${:04X}", address),
+        }
+    }
+
+    // Jumps without a label are shown relative to the instruction like in RGBDS, "$-5"
+    fn relative_text(&self, address: u16, target: u16) -> String {
+        if let Some(label) = self.label(target) {
+            return label.to_string();
+        }
+        match target.wrapping_sub(address) as i16 {
+            0 => "$".to_string(),
+            offset => format!("${:+}", offset),
+        }
+    }
+
+    fn label(&self, address: u16) -> Option<&str> {
+        self.symbols
+            .and_then(|symbols| symbols.label(address, self.rom_bank))
+    }
+}
+
+fn signed(value: i8) -> String {
+    format!("{:+}", value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disassemble(bytes: &[u8], symbols: Option<&Symbols>) -> String {
+        let read = |address: u16| bytes.get(address as usize - 0x150).copied().unwrap_or(0);
+        Disassembler::new(symbols).line(read, 0x150).text
+    }
+
+    #[test]
+    fn it_should_disassemble_operands() {
+        assert_eq!(disassemble(&[0x2A], None), "LD A,(HL+)");
+        assert_eq!(disassemble(&[0x3E, 0x42], None), "LD A,$42");
+        assert_eq!(disassemble(&[0x21, 0x34, 0x12], None), "LD HL,$1234");
+        assert_eq!(disassemble(&[0xE0, 0x40], None), "LDH ($FF40),A");
+        assert_eq!(disassemble(&[0xF8, 0xFD], None), "LD HL,SP-3");
+        assert_eq!(disassemble(&[0xE8, 0x05], None), "ADD SP,5");
+    }
+
+    #[test]
+    fn it_should_show_relative_jumps() {
+        assert_eq!(disassemble(&[0x20, 0xF9], None), "JR NZ,$-5");
+        assert_eq!(disassemble(&[0x18, 0x03], None), "JR $+5");
+        assert_eq!(disassemble(&[0x18, 0xFE], None), "JR $");
+    }
+
+    #[test]
+    fn it_should_disassemble_the_cb_page() {
+        assert_eq!(disassemble(&[0xCB, 0x7C], None), "BIT 7,H");
+        assert_eq!(disassemble(&[0xCB, 0x37], None), "SWAP A");
+    }
+
+    #[test]
+    fn it_should_show_illegal_opcodes_as_data() {
+        assert_eq!(disassemble(&[0xD3], None), "DB $D3");
+    }
+
+    #[test]
+    fn it_should_resolve_labels() {
+        let symbols = Symbols::parse("00:0150 Start\n00:0200 Main\n00:ff40 rLCDC\n");
+
+        assert_eq!(disassemble(&[0xC3, 0x00, 0x02], Some(&symbols)), "JP Main");
+        assert_eq!(disassemble(&[0x18, 0xFE], Some(&symbols)), "JR Start");
+        assert_eq!(disassemble(&[0xE0, 0x40], Some(&symbols)), "LDH (rLCDC),A");
+    }
+
+    #[test]
+    fn it_should_list_consecutive_instructions_with_bytes() {
+        let bytes = [0x00, 0x3E, 0x01, 0xC3, 0x50, 0x01];
+        let read = |address: u16| bytes[address as usize];
+        let lines = Disassembler::new(None).lines(read, 0, 3);
+
+        assert_eq!(lines[1].to_string(), "0001  3E 01     LD A,$01");
+        assert_eq!(lines[2].to_string(), "0003  C3 50 01  JP $0150");
+    }
+}
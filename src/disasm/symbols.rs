@@ -0,0 +1,105 @@
+#![allow(dead_code)]
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const ROM_BANK_START: u16 = 0x4000;
+const ROM_END: u16 = 0x8000;
+
+// Labels from an RGBDS .sym file, one "BB:AAAA Name" entry per line
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    labels: HashMap<(u16, u16), String>,
+    addresses: HashMap<String, (u16, u16)>,
+}
+
+impl Symbols {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Symbols> {
+        Ok(Symbols::parse(&fs::read_to_string(path)?))
+    }
+
+    // Comments and malformed lines are ignored, the first label of an address wins
+    pub fn parse(text: &str) -> Symbols {
+        let mut symbols = Symbols::default();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            let Some((location, name)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let Some((bank, address)) = location.split_once(':') else {
+                continue;
+            };
+            let (Ok(bank), Ok(address)) = (
+                u16::from_str_radix(bank, 16),
+                u16::from_str_radix(address, 16),
+            ) else {
+                continue;
+            };
+            let name = name.trim().to_string();
+            symbols
+                .labels
+                .entry((bank, address))
+                .or_insert_with(|| name.clone());
+            symbols.addresses.entry(name).or_insert((bank, address));
+        }
+        symbols
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    // Addresses in the switchable ROM area use the bank currently mapped there
+    pub fn label(&self, address: u16, rom_bank: u16) -> Option<&str> {
+        let banks: &[u16] = if address < ROM_BANK_START {
+            &[0]
+        } else if address < ROM_END {
+            &[rom_bank]
+        } else {
+            &[0, 1]
+        };
+        banks
+            .iter()
+            .find_map(|bank| self.labels.get(&(*bank, address)))
+            .map(|label| label.as_str())
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).map(|(_, address)| *address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYM: &str = "\
+; File generated by rgblink
+00:0150 Start
+00:0150 Start.loop
+01:4000 LoadTiles
+02:4000 PlayMusic
+00:c000 wBuffer ; comment
+garbage
+";
+
+    #[test]
+    fn it_should_resolve_labels_by_bank() {
+        let symbols = Symbols::parse(SYM);
+
+        assert_eq!(symbols.label(0x0150, 1), Some("Start"));
+        assert_eq!(symbols.label(0x4000, 1), Some("LoadTiles"));
+        assert_eq!(symbols.label(0x4000, 2), Some("PlayMusic"));
+        assert_eq!(symbols.label(0xC000, 1), Some("wBuffer"));
+        assert_eq!(symbols.label(0x0151, 1), None);
+    }
+
+    #[test]
+    fn it_should_find_address_of_a_label() {
+        let symbols = Symbols::parse(SYM);
+
+        assert_eq!(symbols.address("Start.loop"), Some(0x0150));
+        assert_eq!(symbols.address("Missing"), None);
+    }
+}
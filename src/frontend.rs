@@ -0,0 +1,456 @@
+#![allow(dead_code)]
+use piston::input::{Button as InputButton, Event, Key, PressEvent, ReleaseEvent};
+use piston::window::AdvancedWindow;
+use std::time::{Duration, Instant};
+
+use crate::config::CYCLES_PER_FRAME;
+use crate::joypad::Button;
+use crate::logging::Level;
+use crate::model::Model;
+use crate::movie::Session;
+use crate::pacing::{Pacer, SpeedMeter, Sync};
+use crate::processor::cpu::Cpu;
+use crate::rewind::Rewind;
+use crate::savestate::Slots;
+use crate::sgb::border::{SGB_HEIGHT, SGB_WIDTH};
+use crate::speed::{Pace, SpeedControl};
+use crate::video::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::video::screenshot::Image;
+
+// Draws RGBA pixels in the window, implemented by the graphics backend
+pub trait Display {
+    fn present(&mut self, pixels: &[u8], width: u32, height: u32);
+}
+
+// Drops every frame, for running the front end without a screen
+#[derive(Debug, Default)]
+pub struct NullDisplay;
+
+impl Display for NullDisplay {
+    fn present(&mut self, _pixels: &[u8], _width: u32, _height: u32) {}
+}
+
+#[derive(Debug)]
+pub struct Frontend {
+    pub scale: u32,
+    pub speed: SpeedControl,
+    pub sync: Sync,
+    pub frame_limit: Option<u32>,
+    pub frames: u32,
+    pub pixels: Vec<u8>,
+    pub slots: Option<Slots>,
+    pub rewind: Option<Rewind>,
+    pub rewinding: bool,
+    pub movie: Option<Session>,
+}
+
+impl Frontend {
+    pub fn new(scale: u32) -> Frontend {
+        Frontend {
+            scale: scale.max(1),
+            speed: SpeedControl::default(),
+            sync: Sync::Timer,
+            frame_limit: None,
+            frames: 0,
+            pixels: Vec::new(),
+            slots: None,
+            rewind: None,
+            rewinding: false,
+            movie: None,
+        }
+    }
+
+    // Polls input, runs the frames the speed asks for and presents them, then waits for
+    // the next Game Boy frame; the measured speed is shown in the window title
+    pub fn run<W: AdvancedWindow, D: Display>(
+        &mut self,
+        cpu: &mut Cpu,
+        window: &mut W,
+        display: &mut D,
+    ) {
+        let title = window.get_title();
+        if self.sync == Sync::Audio && !cpu.memory.apu.is_streaming() {
+            log!(
+                Level::Warn,
+                "no audio device drains the buffer, pacing by timer"
+            );
+            self.sync = Sync::Timer;
+        }
+        let mut pacer = Pacer::new(self.sync);
+        let mut meter = SpeedMeter::new();
+        while !window.should_close() {
+            while let Some(event) = window.poll_event() {
+                self.input(cpu, &event);
+            }
+            let frames = self.frames;
+            if !self.update(cpu) {
+                window.set_should_close(true);
+            }
+            let (width, height) = self.draw(cpu);
+            display.present(&self.pixels, width, height);
+            window.swap_buffers();
+            if let Some(speed) = meter.record((self.frames - frames) as u64) {
+                log!(Level::Info, "emulation speed {:.0}%", speed * 100.0);
+                window.set_title(format!("{} - {:.0}%", title, speed * 100.0));
+            }
+            if self.speed.turbo {
+                pacer.reset();
+            } else {
+                pacer.wait(|| cpu.memory.apu.buffer_fill());
+            }
+        }
+    }
+
+    fn input(&mut self, cpu: &mut Cpu, event: &Event) {
+        let live = self.movie.as_ref().is_none_or(|movie| !movie.is_playing());
+        if let Some(InputButton::Keyboard(key)) = event.press_args() {
+            match map_key(key) {
+                Some(button) if live => cpu.memory.press(button),
+                Some(_) => {}
+                None => self.hotkey(cpu, key),
+            }
+        }
+        if let Some(InputButton::Keyboard(key)) = event.release_args() {
+            if let Some(button) = map_key(key).filter(|_| live) {
+                cpu.memory.release(button);
+            } else if key == REWIND_KEY {
+                self.rewinding = false;
+            } else if key == TURBO_KEY {
+                self.speed.turbo = false;
+            }
+        }
+    }
+
+    // Runs the frames the speed asks for, or goes one snapshot back while the rewind key
+    // is held; false once the emulation is over
+    pub fn update(&mut self, cpu: &mut Cpu) -> bool {
+        if let Some(rewind) = self.rewind.as_mut().filter(|_| self.rewinding) {
+            rewind.step_back(cpu);
+            return true;
+        }
+        cpu.memory.apu.muted = !self.speed.is_normal();
+        match self.speed.pace() {
+            Pace::Frames(frames) => (0..frames).all(|_| self.frame(cpu)),
+            Pace::Uncapped => {
+                let start = Instant::now();
+                while start.elapsed() < TURBO_BUDGET {
+                    if !self.frame(cpu) {
+                        return false;
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    fn frame(&mut self, cpu: &mut Cpu) -> bool {
+        self.frames += 1;
+        if let Some(movie) = &mut self.movie {
+            movie.before_frame(cpu);
+        }
+        let running = run_frame(cpu);
+        if let Some(movie) = &mut self.movie {
+            if !movie.after_frame(cpu) {
+                if let Some(desync) = &movie.desync {
+                    log!(Level::Error, "{}", desync);
+                }
+            }
+        }
+        if let Some(rewind) = &mut self.rewind {
+            rewind.record(cpu);
+        }
+        running && self.frame_limit.is_none_or(|limit| self.frames < limit)
+    }
+
+    // Speed keys work at any time. Number keys pick the save state slot, F2 saves to it
+    // and F4 loads it; these and rewinding are disabled with a movie since they would
+    // break the recording
+    pub fn hotkey(&mut self, cpu: &mut Cpu, key: Key) {
+        match key {
+            TURBO_KEY => self.speed.turbo = true,
+            Key::P => self.speed.toggle_pause(),
+            Key::N => self.speed.advance_frame(),
+            Key::Equals => self.speed.faster(),
+            Key::Minus => self.speed.slower(),
+            _ if self.movie.is_some() => {}
+            _ => self.state_hotkey(cpu, key),
+        }
+    }
+
+    fn state_hotkey(&mut self, cpu: &mut Cpu, key: Key) {
+        if key == REWIND_KEY {
+            self.rewinding = self.rewind.is_some();
+            return;
+        }
+        let Some(slots) = &mut self.slots else {
+            return;
+        };
+        if let Some(slot) = slot_key(key) {
+            slots.slot = slot;
+            log!(Level::Info, "save state slot {}", slot);
+            return;
+        }
+        let (result, action) = match key {
+            Key::F2 => (slots.save(cpu, slots.slot), "saved state to"),
+            Key::F4 => (slots.load(cpu, slots.slot), "loaded state from"),
+            _ => return,
+        };
+        match result {
+            Ok(path) => log!(Level::Info, "{} '{}'", action, path.display()),
+            Err(error) => log!(
+                Level::Warn,
+                "cannot use save state slot {}: {}",
+                slots.slot,
+                error
+            ),
+        }
+    }
+
+    pub fn draw(&mut self, cpu: &mut Cpu) -> (u32, u32) {
+        draw_screen(cpu, self.scale, &mut self.pixels)
+    }
+}
+
+// Scales the screen up to the window size, with the SGB border when there is one
+pub fn draw_screen(cpu: &mut Cpu, scale: u32, pixels: &mut Vec<u8>) -> (u32, u32) {
+    let (screen, width, height) = if cpu.memory.model == Model::Sgb {
+        (cpu.memory.sgb_screen(), SGB_WIDTH, SGB_HEIGHT)
+    } else {
+        (&cpu.memory.ppu.framebuffer[..], SCREEN_WIDTH, SCREEN_HEIGHT)
+    };
+    scale_frame(screen, width, scale, pixels);
+    (width as u32 * scale, height as u32 * scale)
+}
+
+pub fn capture(cpu: &mut Cpu) -> Image {
+    let mut pixels = Vec::new();
+    let (width, height) = draw_screen(cpu, 1, &mut pixels);
+    Image {
+        width,
+        height,
+        pixels,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameEnd {
+    Frame,
+    Exit,
+    Stopped,
+}
+
+// Runs until the PPU finishes a frame, bounded when the LCD is off; false once the CPU exits
+pub fn run_frame(cpu: &mut Cpu) -> bool {
+    run_frame_until(cpu, |_| false) != FrameEnd::Exit
+}
+
+// Same as run_frame but checks the stop condition after every instruction
+pub fn run_frame_until<F: Fn(&Cpu) -> bool>(cpu: &mut Cpu, stop: F) -> FrameEnd {
+    let mut cycles = 0;
+    while cycles < CYCLES_PER_FRAME {
+        match cpu.step() {
+            Some(elapsed) => cycles += elapsed,
+            None => return FrameEnd::Exit,
+        }
+        if stop(cpu) {
+            return FrameEnd::Stopped;
+        }
+        if cpu.memory.ppu.take_frame() {
+            break;
+        }
+    }
+    FrameEnd::Frame
+}
+
+// Held down to rewind, R is not a Game Boy button
+pub const REWIND_KEY: Key = Key::R;
+// Held down to run as fast as the host can
+pub const TURBO_KEY: Key = Key::Tab;
+// Turbo keeps each update this long so the window still gets input and redraws
+const TURBO_BUDGET: Duration = Duration::from_millis(15);
+
+pub fn map_key(key: Key) -> Option<Button> {
+    match key {
+        Key::Right => Some(Button::Right),
+        Key::Left => Some(Button::Left),
+        Key::Up => Some(Button::Up),
+        Key::Down => Some(Button::Down),
+        Key::X => Some(Button::A),
+        Key::Z => Some(Button::B),
+        Key::Backspace | Key::RShift => Some(Button::Select),
+        Key::Return => Some(Button::Start),
+        _ => None,
+    }
+}
+
+fn slot_key(key: Key) -> Option<u8> {
+    let slots = [
+        Key::D0,
+        Key::D1,
+        Key::D2,
+        Key::D3,
+        Key::D4,
+        Key::D5,
+        Key::D6,
+        Key::D7,
+        Key::D8,
+        Key::D9,
+    ];
+    slots
+        .iter()
+        .position(|slot| *slot == key)
+        .map(|slot| slot as u8)
+}
+
+// Converts RGB555 colors to RGBA8, each pixel becomes a scale x scale square
+pub fn scale_frame(screen: &[u16], width: usize, scale: u32, pixels: &mut Vec<u8>) {
+    let scale = scale as usize;
+    pixels.clear();
+    for line in screen.chunks_exact(width) {
+        for _ in 0..scale {
+            for color in line {
+                let rgba = rgba(*color);
+                for _ in 0..scale {
+                    pixels.extend_from_slice(&rgba);
+                }
+            }
+        }
+    }
+}
+
+pub fn rgba(color: u16) -> [u8; 4] {
+    let expand = |value: u16| ((value << 3) | (value >> 2)) as u8;
+    [
+        expand(color & 0x1F),
+        expand((color >> 5) & 0x1F),
+        expand((color >> 10) & 0x1F),
+        0xFF,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piston::window::{NoWindow, WindowSettings};
+    use std::path::Path;
+
+    #[test]
+    fn it_should_expand_rgb555_to_rgba() {
+        assert_eq!(rgba(0x7FFF), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(rgba(0x001F), [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(rgba(0x0000), [0x00, 0x00, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn it_should_scale_each_pixel_to_a_square() {
+        let mut pixels = Vec::new();
+        scale_frame(&[0x7FFF, 0x0000], 2, 2, &mut pixels);
+
+        assert_eq!(pixels.len(), 4 * 2 * 4);
+        assert_eq!(&pixels[0..8], &[0xFF; 8]);
+        assert_eq!(&pixels[8..12], &[0x00, 0x00, 0x00, 0xFF]);
+        assert_eq!(&pixels[16..20], &[0xFF; 4]);
+    }
+
+    #[test]
+    fn it_should_map_keys_to_buttons() {
+        assert_eq!(map_key(Key::X), Some(Button::A));
+        assert_eq!(map_key(Key::Return), Some(Button::Start));
+        assert_eq!(map_key(Key::Q), None);
+    }
+
+    #[test]
+    fn it_should_pick_slots_with_number_keys() {
+        let mut frontend = Frontend::new(1);
+        frontend.slots = Some(Slots::new(Path::new("saves"), Path::new("game.gb")));
+        frontend.hotkey(&mut Cpu::new(), Key::D7);
+
+        assert_eq!(frontend.slots.unwrap().slot, 7);
+        assert_eq!(slot_key(Key::D0), Some(0));
+        assert_eq!(slot_key(Key::A), None);
+    }
+
+    #[test]
+    fn it_should_rewind_while_the_key_is_held() {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0, &asm!("halt"));
+        let mut frontend = Frontend::new(1);
+        frontend.rewind = Some(Rewind::new(1, 1));
+        for frame in 1..=3 {
+            cpu.memory.set_byte(frame, 0xC000);
+            assert!(frontend.update(&mut cpu));
+        }
+        frontend.hotkey(&mut cpu, REWIND_KEY);
+        assert!(frontend.update(&mut cpu));
+
+        assert!(frontend.rewinding);
+        assert_eq!(frontend.frames, 3);
+        assert_eq!(cpu.memory.peek(0xC000), 2);
+    }
+
+    #[test]
+    fn it_should_follow_the_speed_hotkeys() {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0, &asm!("halt"));
+        let mut frontend = Frontend::new(1);
+        frontend.hotkey(&mut cpu, Key::Equals);
+        frontend.update(&mut cpu);
+        assert_eq!(frontend.frames, 2);
+        assert!(cpu.memory.apu.muted);
+
+        frontend.hotkey(&mut cpu, Key::P);
+        frontend.update(&mut cpu);
+        assert_eq!(frontend.frames, 2);
+        frontend.hotkey(&mut cpu, Key::N);
+        frontend.update(&mut cpu);
+        assert_eq!(frontend.frames, 3);
+
+        frontend.hotkey(&mut cpu, Key::P);
+        frontend.hotkey(&mut cpu, Key::Minus);
+        frontend.update(&mut cpu);
+        assert_eq!(frontend.frames, 4);
+        assert!(!cpu.memory.apu.muted);
+    }
+
+    #[test]
+    fn it_should_pace_by_timer_without_an_audio_device() {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0, &asm!("halt"));
+        let mut frontend = Frontend::new(1);
+        frontend.sync = Sync::Audio;
+        frontend.frame_limit = Some(3);
+        let mut window = NoWindow::new(&WindowSettings::new("test", [160, 144]));
+        frontend.run(&mut cpu, &mut window, &mut NullDisplay);
+
+        assert_eq!(frontend.sync, Sync::Timer);
+        assert_eq!(frontend.frames, 3);
+    }
+
+    #[test]
+    fn it_should_stop_frame_on_condition() {
+        let mut cpu = Cpu::new();
+        for address in 0..0x10 {
+            cpu.memory.set_byte(0x00, address);
+        }
+
+        let end = run_frame_until(&mut cpu, |cpu| cpu.memory.pc == 0x04);
+        assert_eq!(end, FrameEnd::Stopped);
+        assert_eq!(cpu.memory.pc, 0x04);
+    }
+
+    #[test]
+    fn it_should_capture_the_screen_at_native_size() {
+        let mut cpu = Cpu::new();
+        let image = capture(&mut cpu);
+
+        assert_eq!((image.width, image.height), (160, 144));
+        assert_eq!(image.pixels.len(), 160 * 144 * 4);
+    }
+
+    #[test]
+    fn it_should_stop_frame_when_cpu_exits() {
+        let mut cpu = Cpu::new();
+
+        assert!(!run_frame(&mut cpu));
+    }
+}
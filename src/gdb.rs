@@ -0,0 +1,493 @@
+#![allow(dead_code)]
+pub mod packet;
+
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::debugger::{Debugger, Stop};
+use crate::gdb::packet::{
+    decode_hex, encode_hex, parse_hex, read_packet, write_packet, Incoming, INTERRUPT,
+};
+use crate::logging::Level;
+use crate::processor::cpu::Cpu;
+use crate::processor::watch::Access;
+
+// Instructions run between checks for a Ctrl-C from GDB while continuing
+const CHUNK_STEPS: u32 = 10_000;
+const SIGINT: &str = "S02";
+const SIGTRAP: &str = "S05";
+const REGISTERS: usize = 6;
+// Advertised in qSupported, in hex there; a memory read replies two digits per byte
+const PACKET_SIZE: u32 = 0x1000;
+const MAX_READ: u32 = PACKET_SIZE / 2;
+
+// Registers are sent as af, bc, de, hl, sp, pc, each 16-bit little-endian
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <architecture>gbz80</architecture>
+  <feature name="org.gnu.gdb.sm83.cpu">
+    <reg name="af" bitsize="16" type="int"/>
+    <reg name="bc" bitsize="16" type="int"/>
+    <reg name="de" bitsize="16" type="int"/>
+    <reg name="hl" bitsize="16" type="data_ptr"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+// A connection that can tell whether GDB asked to stop a running program
+pub trait Connection: Read + Write {
+    fn interrupted(&mut self) -> io::Result<bool>;
+}
+
+impl Connection for TcpStream {
+    // Polls without blocking, acknowledgements still in the socket are dropped
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = loop {
+            match self.peek(&mut byte) {
+                Ok(1) if byte[0] == b'+' || byte[0] == b'-' => {
+                    self.read_exact(&mut byte)?;
+                }
+                Ok(1) if byte[0] == INTERRUPT => break self.read_exact(&mut byte).map(|_| true),
+                Ok(_) => break Ok(false),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break Ok(false),
+                Err(error) => break Err(error),
+            }
+        };
+        self.set_nonblocking(false)?;
+        result
+    }
+}
+
+enum Reply {
+    Send(String),
+    Close(Option<String>),
+}
+
+// Serves the remote serial protocol on top of the debugger's stepping and breakpoints
+pub struct GdbStub {
+    debugger: Debugger,
+    breakpoints: HashMap<u16, usize>,
+    watchpoints: HashMap<(u8, u16, u16), usize>,
+    ack: bool,
+}
+
+// Waits for one GDB connection on localhost and serves it until GDB detaches
+pub fn listen(cpu: &mut Cpu, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    log!(Level::Info, "waiting for GDB on {}", listener.local_addr()?);
+    let (mut stream, address) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    log!(Level::Info, "GDB connected from {}", address);
+    GdbStub::new().serve(cpu, &mut stream)
+}
+
+impl GdbStub {
+    pub fn new() -> GdbStub {
+        GdbStub {
+            debugger: Debugger::new(None),
+            breakpoints: HashMap::new(),
+            watchpoints: HashMap::new(),
+            ack: true,
+        }
+    }
+
+    pub fn serve<C: Connection>(&mut self, cpu: &mut Cpu, connection: &mut C) -> io::Result<()> {
+        while let Some(incoming) = read_packet(connection, self.ack)? {
+            let reply = match incoming {
+                Incoming::Interrupt => Reply::Send(SIGINT.to_string()),
+                Incoming::Packet(packet) => self.handle(cpu, &packet, connection)?,
+            };
+            match reply {
+                Reply::Send(data) => write_packet(connection, &data)?,
+                Reply::Close(data) => {
+                    if let Some(data) = data {
+                        write_packet(connection, &data)?;
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle<C: Connection>(
+        &mut self,
+        cpu: &mut Cpu,
+        packet: &str,
+        connection: &mut C,
+    ) -> io::Result<Reply> {
+        let (command, arguments) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => SIGTRAP.to_string(),
+            "g" => encode_hex(&read_registers(cpu)),
+            "G" => match decode_hex(arguments) {
+                Some(bytes) if bytes.len() == REGISTERS * 2 => {
+                    for (index, value) in bytes.chunks_exact(2).enumerate() {
+                        write_register(cpu, index, u16::from_le_bytes([value[0], value[1]]));
+                    }
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "p" => match parse_hex(arguments).map(|index| index as usize) {
+                Some(index) if index < REGISTERS => {
+                    encode_hex(&read_registers(cpu)[index * 2..index * 2 + 2])
+                }
+                _ => "E01".to_string(),
+            },
+            "P" => self.write_register_packet(cpu, arguments),
+            "m" => match parse_range(arguments).filter(|(_, length)| *length <= MAX_READ) {
+                Some((address, length)) => {
+                    let bytes: Vec<u8> = (0..length)
+                        .map(|offset| {
+                            cpu.memory
+                                .peek(address.wrapping_add(offset as u16) as usize)
+                        })
+                        .collect();
+                    encode_hex(&bytes)
+                }
+                None => "E01".to_string(),
+            },
+            "M" => self.write_memory(cpu, arguments),
+            "c" | "s" => {
+                if let Some(address) = parse_hex(arguments) {
+                    cpu.memory.pc = address as u16 as usize;
+                }
+                return self.resume(cpu, connection, command == "s");
+            }
+            "Z" | "z" => self.toggle_point(cpu, packet),
+            "H" => "OK".to_string(),
+            "k" => return Ok(Reply::Close(None)),
+            "D" => return Ok(Reply::Close(Some("OK".to_string()))),
+            _ => self.query(packet),
+        };
+        Ok(Reply::Send(reply))
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+",
+                PACKET_SIZE
+            );
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, length)) = parse_range(range) else {
+                return "E01".to_string();
+            };
+            let start = (offset as usize).min(TARGET_XML.len());
+            let end = (start + length as usize).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { "l" } else { "m" };
+            return format!("{}{}", marker, &TARGET_XML[start..end]);
+        }
+        match packet {
+            "QStartNoAckMode" => {
+                self.ack = false;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn write_register_packet(&mut self, cpu: &mut Cpu, arguments: &str) -> String {
+        let Some((index, value)) = arguments.split_once('=') else {
+            return "E01".to_string();
+        };
+        match (parse_hex(index), decode_hex(value)) {
+            (Some(index), Some(value)) if (index as usize) < REGISTERS && value.len() == 2 => {
+                write_register(
+                    cpu,
+                    index as usize,
+                    u16::from_le_bytes([value[0], value[1]]),
+                );
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    // Goes through the bus so writes to registers and banked memory behave as on hardware
+    fn write_memory(&mut self, cpu: &mut Cpu, arguments: &str) -> String {
+        let Some((range, data)) = arguments.split_once(':') else {
+            return "E01".to_string();
+        };
+        match (parse_range(range), decode_hex(data)) {
+            (Some((address, length)), Some(bytes)) if bytes.len() == length as usize => {
+                for (offset, byte) in bytes.iter().enumerate() {
+                    cpu.memory
+                        .write(address.wrapping_add(offset as u16) as usize, *byte);
+                }
+                cpu.memory.watchpoints.take_hit();
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    // Z0/Z1 are breakpoints, Z2 to Z4 write, read and access watchpoints of `kind` bytes
+    fn toggle_point(&mut self, cpu: &mut Cpu, packet: &str) -> String {
+        let insert = packet.starts_with('Z');
+        let mut fields = packet[1..].split([',', ';']);
+        let (Some(kind), Some(address), Some(length)) = (
+            fields.next().and_then(|kind| kind.parse::<u8>().ok()),
+            fields.next().and_then(parse_hex),
+            fields.next().and_then(parse_hex),
+        ) else {
+            return "E01".to_string();
+        };
+        let address = address as u16;
+        match kind {
+            0 | 1 if insert => {
+                if !self.breakpoints.contains_key(&address) {
+                    let id = self.debugger.add_breakpoint(Some(address), None);
+                    self.breakpoints.insert(address, id);
+                }
+            }
+            0 | 1 => {
+                if let Some(id) = self.breakpoints.remove(&address) {
+                    self.debugger.delete(cpu, id);
+                }
+            }
+            2..=4 => {
+                let end = address.wrapping_add((length as u16).max(1) - 1);
+                let key = (kind, address, end);
+                if insert {
+                    if !self.watchpoints.contains_key(&key) {
+                        let access = match kind {
+                            2 => Access::Write,
+                            3 => Access::Read,
+                            _ => Access::ReadWrite,
+                        };
+                        let id = self.debugger.add_watchpoint(cpu, address, end, access);
+                        self.watchpoints.insert(key, id);
+                    }
+                } else if let Some(id) = self.watchpoints.remove(&key) {
+                    self.debugger.delete(cpu, id);
+                }
+            }
+            _ => return String::new(),
+        }
+        "OK".to_string()
+    }
+
+    // Continuing runs in chunks so a Ctrl-C from GDB can stop the program
+    fn resume<C: Connection>(
+        &mut self,
+        cpu: &mut Cpu,
+        connection: &mut C,
+        step: bool,
+    ) -> io::Result<Reply> {
+        loop {
+            let stop = if step {
+                self.debugger.run_until(cpu, |_, _, _| true)
+            } else {
+                self.debugger
+                    .run_until(cpu, |_, _, steps| steps >= CHUNK_STEPS)
+            };
+            let reply = match stop {
+                Stop::Done if !step => {
+                    if connection.interrupted()? {
+                        SIGINT.to_string()
+                    } else {
+                        continue;
+                    }
+                }
+                Stop::Done | Stop::Breakpoint(_) => SIGTRAP.to_string(),
+                Stop::Interrupted => SIGINT.to_string(),
+                Stop::Watch(hit) => {
+                    let access = cpu
+                        .memory
+                        .watchpoints
+                        .list
+                        .iter()
+                        .find(|watchpoint| watchpoint.id == hit.id)
+                        .map(|watchpoint| watchpoint.access);
+                    let reason = match access {
+                        Some(Access::Write) => "watch",
+                        Some(Access::Read) => "rwatch",
+                        _ => "awatch",
+                    };
+                    format!("T05{}:{:x};", reason, hit.address)
+                }
+                Stop::Exit => return Ok(Reply::Close(Some("W00".to_string()))),
+            };
+            return Ok(Reply::Send(reply));
+        }
+    }
+}
+
+fn read_registers(cpu: &mut Cpu) -> Vec<u8> {
+    let registers = &mut cpu.registers;
+    [
+        registers.af(),
+        registers.bc(),
+        registers.de(),
+        registers.hl(),
+        registers.sp as u16,
+        cpu.memory.pc as u16,
+    ]
+    .iter()
+    .flat_map(|value| value.to_le_bytes())
+    .collect()
+}
+
+fn write_register(cpu: &mut Cpu, index: usize, value: u16) {
+    match index {
+        0 => cpu.registers.set_af(value),
+        1 => cpu.registers.set_bc(value),
+        2 => cpu.registers.set_de(value),
+        3 => cpu.registers.set_hl(value),
+        4 => cpu.registers.set_sp(value),
+        _ => cpu.memory.pc = value as usize,
+    }
+}
+
+// ADDR,LENGTH in hex as used by memory packets and qXfer
+fn parse_range(text: &str) -> Option<(u16, u32)> {
+    let (address, length) = text.split_once(',')?;
+    Some((parse_hex(address)? as u16, parse_hex(length)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // A scripted client on the other end of a real socket, returning every reply
+    fn session(source: &str, script: &'static [&'static str]) -> (Cpu, Vec<String>) {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0, &asm!(source));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut replies = Vec::new();
+            for packet in script {
+                write_packet(&mut stream, packet).unwrap();
+                match read_packet(&mut stream, true).unwrap() {
+                    Some(Incoming::Packet(reply)) => replies.push(reply),
+                    other => panic!("unexpected {:?} after {}", other, packet),
+                }
+            }
+            replies
+        });
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_nodelay(true).unwrap();
+        GdbStub::new().serve(&mut cpu, &mut stream).unwrap();
+        (cpu, client.join().unwrap())
+    }
+
+    #[test]
+    fn it_should_answer_queries() {
+        let (_, replies) = session(
+            "nop",
+            &[
+                "qSupported:xmlRegisters=i386",
+                "?",
+                "qXfer:features:read:target.xml:0,20",
+                "vMustReplyEmpty",
+                "D",
+            ],
+        );
+
+        assert!(replies[0].contains("qXfer:features:read+"));
+        assert_eq!(replies[1], "S05");
+        assert_eq!(replies[2], format!("m{}", &TARGET_XML[..0x20]));
+        assert_eq!(replies[3], "");
+        assert_eq!(replies[4], "OK");
+    }
+
+    #[test]
+    fn it_should_read_and_write_registers() {
+        let (cpu, replies) = session(
+            "ld a,$42\nld [$C000],a",
+            &[
+                "s",
+                "g",
+                "P0=0012",
+                "p0",
+                "G0100020003000400feff0000",
+                "p5",
+                "D",
+            ],
+        );
+
+        assert_eq!(replies[0], "S05");
+        assert_eq!(replies[1], "004200000000000000000200");
+        assert_eq!(replies[3], "0012");
+        assert_eq!(replies[5], "0000");
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+        assert_eq!(cpu.registers.b, 0x00);
+        assert_eq!(cpu.registers.c, 0x02);
+    }
+
+    #[test]
+    fn it_should_read_and_write_memory() {
+        let (cpu, replies) = session(
+            "nop",
+            &[
+                "m0,3",
+                "Mc000,2:aabb",
+                "mc000,2",
+                "M0,2:1",
+                "m0,ffffffff",
+                "m0,800",
+                "D",
+            ],
+        );
+
+        assert_eq!(replies[0], "00fcfc");
+        assert_eq!(replies[1], "OK");
+        assert_eq!(replies[2], "aabb");
+        assert_eq!(replies[3], "E01");
+        assert_eq!(replies[4], "E01");
+        assert_eq!(replies[5].len(), 0x1000);
+        assert_eq!(cpu.memory.read(0xC001), 0xBB);
+    }
+
+    #[test]
+    fn it_should_stop_at_breakpoints_and_watchpoints() {
+        let (cpu, replies) = session(
+            "ld a,$42\nld [$C000],a\nnop\nnop",
+            &[
+                "Z2,c000,1",
+                "c",
+                "Z0,6,1",
+                "c",
+                "p5",
+                "z0,6,1",
+                "z2,c000,1",
+                "c",
+            ],
+        );
+
+        assert_eq!(replies[1], "T05watch:c000;");
+        assert_eq!(replies[3], "S05");
+        assert_eq!(replies[4], "0600");
+        assert_eq!(replies[7], "W00");
+        assert!(cpu.memory.watchpoints.is_empty());
+    }
+
+    #[test]
+    fn it_should_remove_a_watchpoint_inserted_twice() {
+        let (cpu, replies) = session(
+            "ld a,$42\nld [$C000],a",
+            &["Z2,c000,1", "Z2,c000,1", "z2,c000,1", "c"],
+        );
+
+        assert_eq!(replies[..3], ["OK", "OK", "OK"]);
+        assert_eq!(replies[3], "W00");
+        assert!(cpu.memory.watchpoints.is_empty());
+    }
+}
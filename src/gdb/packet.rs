@@ -0,0 +1,193 @@
+#![allow(dead_code)]
+use std::io::{self, Read, Write};
+
+// Sent by GDB outside of any packet when the user presses Ctrl-C
+pub const INTERRUPT: u8 = 0x03;
+const ESCAPE: u8 = b'}';
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Incoming {
+    Packet(String),
+    Interrupt,
+}
+
+// Reads the next `$data#checksum` packet, None once the connection is closed;
+// acknowledgements are skipped and bad checksums are asked again with '-'
+pub fn read_packet<C: Read + Write>(connection: &mut C, ack: bool) -> io::Result<Option<Incoming>> {
+    loop {
+        let Some(byte) = read_byte(connection)? else {
+            return Ok(None);
+        };
+        match byte {
+            INTERRUPT => return Ok(Some(Incoming::Interrupt)),
+            b'$' => {}
+            _ => continue,
+        }
+        let mut data = Vec::new();
+        let mut sum: u8 = 0;
+        loop {
+            match read_byte(connection)? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(byte) => {
+                    sum = sum.wrapping_add(byte);
+                    data.push(byte);
+                }
+            }
+        }
+        let mut checksum = [0; 2];
+        connection.read_exact(&mut checksum)?;
+        let valid = std::str::from_utf8(&checksum)
+            .ok()
+            .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+            == Some(sum);
+        if ack {
+            connection.write_all(if valid { b"+" } else { b"-" })?;
+            connection.flush()?;
+        }
+        if valid {
+            return Ok(Some(Incoming::Packet(
+                String::from_utf8_lossy(&unescape(&data)).into_owned(),
+            )));
+        }
+    }
+}
+
+pub fn write_packet<W: Write>(writer: &mut W, data: &str) -> io::Result<()> {
+    let data = escape(data.as_bytes());
+    writer.write_all(b"$")?;
+    writer.write_all(&data)?;
+    write!(writer, "#{:02x}", checksum(&data))?;
+    writer.flush()
+}
+
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match reader.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for byte in data {
+        if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+            escaped.extend_from_slice(&[ESCAPE, byte ^ 0x20]);
+        } else {
+            escaped.push(*byte);
+        }
+    }
+    escaped
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut bytes = data.iter();
+    let mut unescaped = Vec::with_capacity(data.len());
+    while let Some(byte) = bytes.next() {
+        match byte {
+            &ESCAPE => unescaped.extend(bytes.next().map(|byte| byte ^ 0x20)),
+            byte => unescaped.push(*byte),
+        }
+    }
+    unescaped
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+pub fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    struct Duplex {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buffer)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.output.write(buffer)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn duplex(input: &[u8]) -> Duplex {
+        Duplex {
+            input: Cursor::new(input.to_vec()),
+            output: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn it_should_read_packets_and_acknowledge_them() {
+        let mut connection = duplex(b"+$g#67$m0,1#fa\x03");
+
+        let packet = read_packet(&mut connection, true).unwrap();
+        assert_eq!(packet, Some(Incoming::Packet("g".to_string())));
+        let packet = read_packet(&mut connection, true).unwrap();
+        assert_eq!(packet, Some(Incoming::Packet("m0,1".to_string())));
+        let packet = read_packet(&mut connection, true).unwrap();
+        assert_eq!(packet, Some(Incoming::Interrupt));
+        assert_eq!(read_packet(&mut connection, true).unwrap(), None);
+        assert_eq!(connection.output, b"++");
+    }
+
+    #[test]
+    fn it_should_reject_bad_checksums() {
+        let mut connection = duplex(b"$g#00$g#67");
+
+        let packet = read_packet(&mut connection, true).unwrap();
+        assert_eq!(packet, Some(Incoming::Packet("g".to_string())));
+        assert_eq!(connection.output, b"-+");
+    }
+
+    #[test]
+    fn it_should_escape_special_characters() {
+        let mut output = Vec::new();
+        write_packet(&mut output, "a#b").unwrap();
+
+        assert_eq!(output, b"$a}\x03b#43");
+        let mut connection = duplex(&output);
+        let packet = read_packet(&mut connection, false).unwrap();
+        assert_eq!(packet, Some(Incoming::Packet("a#b".to_string())));
+        assert!(connection.output.is_empty());
+    }
+
+    #[test]
+    fn it_should_convert_hex() {
+        assert_eq!(encode_hex(&[0x0A, 0xFF]), "0aff");
+        assert_eq!(decode_hex("0aFF"), Some(vec![0x0A, 0xFF]));
+        assert_eq!(decode_hex("0a0"), None);
+        assert_eq!(parse_hex("c000"), Some(0xC000));
+    }
+}
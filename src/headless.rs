@@ -0,0 +1,106 @@
+#![allow(dead_code)]
+use crate::frontend::{run_frame_until, FrameEnd};
+use crate::logging::Level;
+use crate::movie::Session;
+use crate::processor::cpu::Cpu;
+
+// Ends a headless run as soon as the CPU reaches an address or memory holds a value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopCondition {
+    Pc(u16),
+    Memory(u16, u8),
+}
+
+impl StopCondition {
+    pub fn is_met(&self, cpu: &Cpu) -> bool {
+        match *self {
+            StopCondition::Pc(address) => cpu.memory.pc == address as usize,
+            StopCondition::Memory(address, value) => cpu.memory.peek(address as usize) == value,
+        }
+    }
+}
+
+// Runs as fast as possible until the frame limit, a stop condition, a movie desync or
+// the CPU exits, returns the number of frames completed
+pub fn run_headless(
+    cpu: &mut Cpu,
+    frames: Option<u32>,
+    conditions: &[StopCondition],
+    mut movie: Option<&mut Session>,
+) -> u32 {
+    let mut count = 0;
+    while frames.is_none_or(|limit| count < limit) {
+        if let Some(movie) = movie.as_deref_mut() {
+            movie.before_frame(cpu);
+        }
+        match run_frame_until(cpu, |cpu| {
+            conditions.iter().any(|condition| condition.is_met(cpu))
+        }) {
+            FrameEnd::Frame => {
+                count += 1;
+                if let Some(movie) = movie.as_deref_mut() {
+                    if !movie.after_frame(cpu) {
+                        break;
+                    }
+                }
+            }
+            FrameEnd::Exit => {
+                log!(Level::Info, "CPU exited after {} frames", count);
+                break;
+            }
+            FrameEnd::Stopped => {
+                log!(
+                    Level::Info,
+                    "stop condition met at PC {:#06x}",
+                    cpu.memory.pc
+                );
+                break;
+            }
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::watch::{Access, Watchpoint};
+
+    #[test]
+    fn it_should_stop_after_frame_limit() {
+        let mut cpu = Cpu::new();
+        for address in 0..0x8000 {
+            cpu.memory.set_byte(0x00, address);
+        }
+
+        assert_eq!(run_headless(&mut cpu, Some(2), &[], None), 2);
+    }
+
+    #[test]
+    fn it_should_stop_when_memory_holds_value() {
+        let mut cpu = Cpu::new();
+        cpu.memory.set_byte(0x3E, 0x00);
+        cpu.memory.set_byte(0x42, 0x01);
+        cpu.memory.set_byte(0xEA, 0x02);
+        cpu.memory.set_byte(0x00, 0x03);
+        cpu.memory.set_byte(0xC0, 0x04);
+        let condition = StopCondition::Memory(0xC000, 0x42);
+
+        assert_eq!(run_headless(&mut cpu, None, &[condition], None), 0);
+        assert_eq!(cpu.memory.pc, 0x05);
+    }
+
+    #[test]
+    fn it_should_not_trigger_watchpoints() {
+        let mut cpu = Cpu::new();
+        cpu.memory.watchpoints.list.push(Watchpoint {
+            id: 1,
+            start: 0xC000,
+            end: 0xC000,
+            access: Access::Read,
+        });
+
+        assert!(!StopCondition::Memory(0xC000, 0x42).is_met(&cpu));
+        assert_eq!(cpu.memory.watchpoints.take_hit(), None);
+    }
+}
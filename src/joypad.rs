@@ -0,0 +1,201 @@
+#![allow(dead_code)]
+
+pub const JOYPAD_INTERRUPT: u8 = 0x10;
+
+// Select bits are active low as well, P14 for directions and P15 for actions
+const SELECT_DIRECTIONS: u8 = 0x10;
+const SELECT_ACTIONS: u8 = 0x20;
+const SELECT_MASK: u8 = SELECT_DIRECTIONS | SELECT_ACTIONS;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+// In the order of their bit in the pressed mask
+pub const BUTTONS: [Button; 8] = [
+    Button::Right,
+    Button::Left,
+    Button::Up,
+    Button::Down,
+    Button::A,
+    Button::B,
+    Button::Select,
+    Button::Start,
+];
+
+impl Button {
+    // Directions use the low nibble and actions the high one, in P1 line order
+    pub fn mask(&self) -> u8 {
+        match self {
+            Button::Right => 0x01,
+            Button::Left => 0x02,
+            Button::Up => 0x04,
+            Button::Down => 0x08,
+            Button::A => 0x10,
+            Button::B => 0x20,
+            Button::Select => 0x40,
+            Button::Start => 0x80,
+        }
+    }
+
+    fn opposite(&self) -> Option<Button> {
+        match self {
+            Button::Right => Some(Button::Left),
+            Button::Left => Some(Button::Right),
+            Button::Up => Some(Button::Down),
+            Button::Down => Some(Button::Up),
+            _ => None,
+        }
+    }
+}
+
+// P1 lines are active low, a button only shows up when its group is selected with a 0
+#[derive(Debug, Clone)]
+pub struct Joypad {
+    pub select: u8,
+    pub pressed: u8,
+    pub block_opposite: bool,
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad {
+            select: SELECT_MASK,
+            pressed: 0,
+            block_opposite: false,
+        }
+    }
+
+    // Returns the joypad interrupt when a selected line goes from high to low
+    pub fn press(&mut self, button: Button) -> u8 {
+        let before = self.lines();
+        if self.block_opposite {
+            // The most recent direction wins, the opposite one stays released
+            if let Some(opposite) = button.opposite() {
+                self.pressed &= !opposite.mask();
+            }
+        }
+        self.pressed |= button.mask();
+        self.interrupt(before)
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.pressed &= !button.mask();
+    }
+
+    pub fn read(&self) -> u8 {
+        0xC0 | self.select | self.lines()
+    }
+
+    // Selecting a group with buttons already held also pulls lines low
+    pub fn write(&mut self, value: u8) -> u8 {
+        let before = self.lines();
+        self.select = value & SELECT_MASK;
+        self.interrupt(before)
+    }
+
+    fn lines(&self) -> u8 {
+        let mut pressed = 0;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            pressed |= self.pressed & 0x0F;
+        }
+        if self.select & SELECT_ACTIONS == 0 {
+            pressed |= self.pressed >> 4;
+        }
+        !pressed & 0x0F
+    }
+
+    fn interrupt(&self, before: u8) -> u8 {
+        if before & !self.lines() != 0 {
+            JOYPAD_INTERRUPT
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Values written to P1 to select a single group
+    const DIRECTIONS: u8 = SELECT_ACTIONS;
+    const ACTIONS: u8 = SELECT_DIRECTIONS;
+
+    #[test]
+    fn it_should_read_unselected_lines_as_released() {
+        let mut joypad = Joypad::new();
+        joypad.press(Button::A);
+
+        assert_eq!(joypad.read(), 0xFF);
+    }
+
+    #[test]
+    fn it_should_expose_the_selected_group() {
+        let mut joypad = Joypad::new();
+        joypad.press(Button::Start);
+        joypad.press(Button::Left);
+        joypad.write(DIRECTIONS);
+
+        assert_eq!(joypad.read(), 0xC0 | DIRECTIONS | 0x0D);
+        joypad.write(ACTIONS);
+        assert_eq!(joypad.read(), 0xC0 | ACTIONS | 0x07);
+    }
+
+    #[test]
+    fn it_should_combine_groups_when_both_are_selected() {
+        let mut joypad = Joypad::new();
+        joypad.press(Button::Right);
+        joypad.press(Button::B);
+        joypad.write(0x00);
+
+        assert_eq!(joypad.read(), 0xC0 | 0x0C);
+    }
+
+    #[test]
+    fn it_should_request_interrupt_on_high_to_low_transition() {
+        let mut joypad = Joypad::new();
+        joypad.write(DIRECTIONS);
+
+        assert_eq!(joypad.press(Button::Down), JOYPAD_INTERRUPT);
+        assert_eq!(joypad.press(Button::Down), 0);
+        assert_eq!(joypad.press(Button::A), 0);
+    }
+
+    #[test]
+    fn it_should_request_interrupt_when_selecting_a_held_group() {
+        let mut joypad = Joypad::new();
+        joypad.press(Button::A);
+
+        assert_eq!(joypad.write(ACTIONS), JOYPAD_INTERRUPT);
+    }
+
+    #[test]
+    fn it_should_block_opposite_directions() {
+        let mut joypad = Joypad::new();
+        joypad.block_opposite = true;
+        joypad.write(DIRECTIONS);
+        joypad.press(Button::Left);
+        joypad.press(Button::Right);
+
+        assert_eq!(joypad.read() & 0x0F, 0x0E);
+    }
+
+    #[test]
+    fn it_should_allow_opposite_directions_by_default() {
+        let mut joypad = Joypad::new();
+        joypad.write(DIRECTIONS);
+        joypad.press(Button::Up);
+        joypad.press(Button::Down);
+
+        assert_eq!(joypad.read() & 0x0F, 0x03);
+    }
+}
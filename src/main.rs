@@ -1,27 +1,357 @@
-use crate::config::*;
-use std::time::{Duration, Instant};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
 
+use glutin_window::GlutinWindow;
+use piston::window::WindowSettings;
+
+use crate::apu::output::AudioOutput;
+use crate::battery::Battery;
+use crate::cartridge::Cartridge;
+use crate::cli::{
+    Command, Options, EXIT_BAD_ROM, EXIT_FAILURE, EXIT_MISMATCH, EXIT_SUCCESS, EXIT_USAGE,
+};
+use crate::debugger::tui::Tui;
+use crate::debugger::Debugger;
+use crate::disasm::symbols::Symbols;
+use crate::frontend::{capture, Frontend};
+use crate::headless::run_headless;
+use crate::logging::Level;
+use crate::model::Model;
+use crate::movie::{Desync, Movie, MovieError, Session};
+use crate::pacing::Sync;
 use crate::processor::cpu::Cpu;
+use crate::processor::trace::Tracer;
+use crate::rewind::Rewind;
+use crate::savestate::{Slots, StateError};
+use crate::speed::SpeedControl;
+use crate::video::display::GlDisplay;
+use crate::video::screenshot::Image;
+
+#[macro_use]
+mod logging;
+#[macro_use]
+mod asm;
 
+mod apu;
+mod battery;
+mod cartridge;
+mod cli;
 mod config;
+mod debugger;
+mod disasm;
+mod frontend;
+mod gdb;
+mod headless;
+mod joypad;
+mod model;
+mod movie;
+mod pacing;
 mod processor;
+mod rewind;
+mod savestate;
+mod serial;
+mod sgb;
+mod speed;
+#[cfg(test)]
+mod testroms;
+mod video;
+
+// Failures after the command line was accepted, each with its own exit code
+#[derive(Debug)]
+enum RunError {
+    Rom(PathBuf, io::Error),
+    BootRom(PathBuf, io::Error),
+    Io(String, io::Error),
+    Mismatch(PathBuf, usize),
+    State(PathBuf, StateError),
+    Movie(PathBuf, MovieError),
+    Desync(PathBuf, Desync),
+    Window(String),
+}
+
+impl RunError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            RunError::Rom(..) | RunError::BootRom(..) => EXIT_BAD_ROM,
+            RunError::Io(..) | RunError::State(..) | RunError::Movie(..) | RunError::Window(..) => {
+                EXIT_FAILURE
+            }
+            RunError::Mismatch(..) | RunError::Desync(..) => EXIT_MISMATCH,
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Rom(path, error) => {
+                write!(formatter, "cannot read ROM '{}': {}", path.display(), error)
+            }
+            RunError::BootRom(path, error) => {
+                write!(
+                    formatter,
+                    "cannot read boot ROM '{}': {}",
+                    path.display(),
+                    error
+                )
+            }
+            RunError::Io(context, error) => write!(formatter, "{}: {}", context, error),
+            RunError::Mismatch(path, pixels) => write!(
+                formatter,
+                "screen differs from '{}' in {} pixels",
+                path.display(),
+                pixels
+            ),
+            RunError::State(path, error) => write!(
+                formatter,
+                "cannot load save state '{}': {}",
+                path.display(),
+                error
+            ),
+            RunError::Movie(path, error) => {
+                write!(
+                    formatter,
+                    "cannot play movie '{}': {}",
+                    path.display(),
+                    error
+                )
+            }
+            RunError::Desync(path, desync) => write!(formatter, "'{}': {}", path.display(), desync),
+            RunError::Window(error) => write!(formatter, "cannot open window: {}", error),
+        }
+    }
+}
 
 fn main() {
-    let mut cpu = Cpu::new();
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            process::exit(EXIT_SUCCESS);
+        }
+        Ok(Command::Version) => {
+            println!("game-boy {}", env!("CARGO_PKG_VERSION"));
+            process::exit(EXIT_SUCCESS);
+        }
+        Err(error) => {
+            eprintln!(
+                "error: {}\n\nRun with --help to see the available options.",
+                error
+            );
+            process::exit(EXIT_USAGE);
+        }
+    };
+    logging::set_level(options.log_level);
+    if let Err(error) = run(&options) {
+        eprintln!("error: {}", error);
+        process::exit(error.exit_code());
+    }
+}
 
-    main_loop(&mut cpu);
+fn run(options: &Options) -> Result<(), RunError> {
+    let cartridge = Cartridge::from_file(&options.rom)
+        .map_err(|error| RunError::Rom(options.rom.clone(), error))?;
+    let model = options.model.unwrap_or_else(|| cartridge.model());
+    log!(
+        Level::Info,
+        "running '{}' on {:?}",
+        cartridge.title(),
+        model
+    );
+    let mut cpu = Cpu::from_cartridge_on(&cartridge, model);
+    if options.palette.is_some() {
+        cpu.memory
+            .apply_compatibility_palette(&cartridge, options.palette);
+    }
+    if let Some(path) = &options.boot_rom {
+        let boot_rom = fs::read(path).map_err(|error| RunError::BootRom(path.clone(), error))?;
+        cpu.load_boot_rom(boot_rom);
+    }
+    if let Some(directory) = &options.save_dir {
+        fs::create_dir_all(directory)
+            .map_err(|error| io_error("cannot create save directory", directory, error))?;
+    }
+    let mut battery = Battery::new(&save_directory(options), &options.rom, &cartridge);
+    if let Some(battery) = &mut battery {
+        battery
+            .load(&mut cpu)
+            .map_err(|error| io_error("cannot read battery save", &battery.path, error))?;
+    }
+    cpu.memory.doctor = options.doctor;
+    if let Some(path) = &options.trace {
+        let tracer =
+            Tracer::create(path).map_err(|error| io_error("cannot create trace", path, error))?;
+        cpu.tracer = Some(tracer);
+    }
+    if let Some(path) = &options.wav {
+        cpu.memory
+            .apu
+            .start_recording(path, options.stems)
+            .map_err(|error| io_error("cannot create WAV file", path, error))?;
+    }
+    if let Some(slot) = options.load_state {
+        let slots = state_slots(options);
+        slots
+            .load(&mut cpu, slot)
+            .map_err(|error| RunError::State(slots.path(slot), error))?;
+    }
+    let mut movie = start_movie(&cpu, options)?;
+
+    if options.debug {
+        let mut debugger = Debugger::new(load_symbols(options));
+        if let Err(error) = debugger.interrupt_on_ctrl_c() {
+            log!(Level::Warn, "Ctrl-C cannot pause the program: {}", error);
+        }
+        debugger
+            .run(&mut cpu, io::stdin().lock(), &mut io::stdout())
+            .map_err(|error| RunError::Io("debugger".to_string(), error))?;
+    } else if options.tui {
+        Tui::new(&mut cpu, load_symbols(options))
+            .run(&mut cpu)
+            .map_err(|error| RunError::Io("terminal debugger".to_string(), error))?;
+    } else if let Some(port) = options.gdb {
+        gdb::listen(&mut cpu, port)
+            .map_err(|error| RunError::Io(format!("GDB server on port {}", port), error))?;
+    } else if options.headless {
+        run_headless(&mut cpu, options.frames, &options.until, movie.as_mut());
+    } else {
+        movie = open_window(&mut cpu, options, movie)?;
+    }
+    // A movie played back must not overwrite the player's own save
+    if let Some(battery) = battery.as_mut().filter(|_| options.play.is_none()) {
+        if battery
+            .save(&cpu)
+            .map_err(|error| io_error("cannot write battery save", &battery.path, error))?
+        {
+            log!(Level::Info, "saved '{}'", battery.path.display());
+        }
+    }
+    finish(&mut cpu, options, movie)
+}
+
+fn start_movie(cpu: &Cpu, options: &Options) -> Result<Option<Session>, RunError> {
+    if options.record.is_some() {
+        return Ok(Some(Session::record(cpu)));
+    }
+    let Some(path) = &options.play else {
+        return Ok(None);
+    };
+    let movie = Movie::load(path)
+        .and_then(|movie| Session::play(movie, cpu))
+        .map_err(|error| RunError::Movie(path.clone(), error))?;
+    Ok(Some(movie))
 }
 
-fn main_loop(cpu: &mut Cpu) {
-    loop {
-        let now = Instant::now();
-        if cpu.run() == 0 {
-            break;
+// Flushes the recordings then checks the movie and the final screen, mismatches are
+// reported last
+fn finish(cpu: &mut Cpu, options: &Options, movie: Option<Session>) -> Result<(), RunError> {
+    if let Some(path) = &options.wav {
+        cpu.memory
+            .apu
+            .stop_recording()
+            .map_err(|error| io_error("cannot write WAV file", path, error))?;
+    }
+    if let (Some(tracer), Some(path)) = (cpu.tracer.take(), &options.trace) {
+        tracer
+            .finish()
+            .map_err(|error| io_error("cannot write trace", path, error))?;
+    }
+    if let (Some(movie), Some(path)) = (&movie, &options.record) {
+        movie
+            .movie
+            .save(path)
+            .map_err(|error| io_error("cannot write movie", path, error))?;
+    }
+    let screen = capture(cpu);
+    if let Some(path) = &options.screenshot {
+        screen
+            .save(path)
+            .map_err(|error| io_error("cannot write screenshot", path, error))?;
+    }
+    if let (Some(desync), Some(path)) = (movie.and_then(|movie| movie.desync), &options.play) {
+        return Err(RunError::Desync(path.clone(), desync));
+    }
+    if let Some(path) = &options.reference {
+        let reference =
+            Image::load(path).map_err(|error| io_error("cannot read reference", path, error))?;
+        let pixels = screen.difference(&reference);
+        if pixels > 0 {
+            return Err(RunError::Mismatch(path.clone(), pixels));
         }
-        tick(now);
     }
+    Ok(())
+}
+
+// RGBDS writes the symbol file next to the ROM with the same name
+fn load_symbols(options: &Options) -> Option<Symbols> {
+    let path = options.rom.with_extension("sym");
+    let symbols = Symbols::load(&path).ok()?;
+    log!(Level::Info, "loaded labels from '{}'", path.display());
+    Some(symbols)
 }
 
-fn tick(now: Instant) {
-    while now.elapsed() < Duration::from_nanos(CLOCK_DURATION_NS) {}
+// States sit with the other save files, or next to the ROM without --save-dir
+fn state_slots(options: &Options) -> Slots {
+    let mut slots = Slots::new(&save_directory(options), &options.rom);
+    slots.slot = options.load_state.unwrap_or(0);
+    slots
+}
+
+// Battery saves and save states go next to the ROM unless --save-dir is given
+fn save_directory(options: &Options) -> PathBuf {
+    match &options.save_dir {
+        Some(directory) => directory.clone(),
+        None => options
+            .rom
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    }
+}
+
+fn io_error(context: &str, path: &Path, error: io::Error) -> RunError {
+    RunError::Io(format!("{} '{}'", context, path.display()), error)
+}
+
+// Runs in a window until it is closed or the emulation ends, returns the movie so it
+// can be saved and checked
+fn open_window(
+    cpu: &mut Cpu,
+    options: &Options,
+    movie: Option<Session>,
+) -> Result<Option<Session>, RunError> {
+    let (width, height) = if cpu.memory.model == Model::Sgb {
+        (sgb::border::SGB_WIDTH, sgb::border::SGB_HEIGHT)
+    } else {
+        (video::ppu::SCREEN_WIDTH, video::ppu::SCREEN_HEIGHT)
+    };
+    let size = [width as u32 * options.scale, height as u32 * options.scale];
+    let settings = WindowSettings::new("Game Boy", size)
+        .exit_on_esc(true)
+        .vsync(options.sync == Sync::Vsync);
+    let mut window =
+        GlutinWindow::new(&settings).map_err(|error| RunError::Window(error.to_string()))?;
+    let mut display = GlDisplay::new(&mut window).map_err(RunError::Window)?;
+    // Playing is optional, audio sync falls back to the timer without it
+    let _audio = AudioOutput::open(&mut cpu.memory.apu)
+        .map_err(|error| log!(Level::Warn, "no audio output: {}", error))
+        .ok();
+    let mut frontend = Frontend::new(options.scale);
+    frontend.speed = SpeedControl::new(options.speed);
+    frontend.sync = options.sync;
+    frontend.frame_limit = options.frames;
+    frontend.slots = Some(state_slots(options));
+    if options.rewind_megabytes > 0 && movie.is_none() {
+        frontend.rewind = Some(Rewind::new(
+            options.rewind_megabytes,
+            options.rewind_interval,
+        ));
+    }
+    frontend.movie = movie;
+    frontend.run(cpu, &mut window, &mut display);
+    Ok(frontend.movie)
 }
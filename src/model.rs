@@ -0,0 +1,33 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+    Dmg,
+    Cgb,
+    Sgb,
+    Agb,
+}
+
+impl Model {
+    // The GBA runs Game Boy games on its CGB hardware
+    pub fn is_cgb(&self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Model::Dmg => "dmg",
+            Model::Cgb => "cgb",
+            Model::Sgb => "sgb",
+            Model::Agb => "agb",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Model> {
+        match name.to_lowercase().as_str() {
+            "dmg" => Some(Model::Dmg),
+            "cgb" => Some(Model::Cgb),
+            "sgb" => Some(Model::Sgb),
+            "agb" => Some(Model::Agb),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,548 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::joypad::BUTTONS;
+use crate::model::Model;
+use crate::processor::cpu::Cpu;
+use crate::savestate::codec::crc32;
+use crate::savestate::snapshot;
+use crate::video::compatibility::PaletteCombo;
+
+const MAGIC: &str = "game-boy movie";
+pub const VERSION: u32 = 1;
+// Recordings hash the state once per second of play
+pub const HASH_INTERVAL: usize = 60;
+// One letter per button in mask bit order, a dot when released
+const BUTTON_LETTERS: [char; 8] = ['R', 'L', 'U', 'D', 'A', 'B', 's', 'S'];
+const TITLE: Range<usize> = 0x134..0x144;
+const SRAM: Range<usize> = 0xA000..0xC000;
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    NotAMovie,
+    Version(u32),
+    Parse(usize, String),
+    Mismatch(String),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Io(error) => write!(formatter, "{}", error),
+            MovieError::NotAMovie => formatter.write_str("not a movie"),
+            MovieError::Version(version) => write!(
+                formatter,
+                "movie version {} is not supported, this build reads version {}",
+                version, VERSION
+            ),
+            MovieError::Parse(line, message) => write!(formatter, "line {}: {}", line, message),
+            MovieError::Mismatch(message) => write!(formatter, "recorded with {}", message),
+        }
+    }
+}
+
+impl From<io::Error> for MovieError {
+    fn from(error: io::Error) -> MovieError {
+        MovieError::Io(error)
+    }
+}
+
+// Everything that makes power-on differ from one run to the next. No mapper emulates
+// an RTC yet, the start time is kept so movies stay valid once one does
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub title: String,
+    pub model: Model,
+    pub rtc: u64,
+    pub sram: u32,
+    // Hash of the boot ROM run at power-on, None when starting straight in the game
+    pub boot_rom: Option<u32>,
+    pub palette: Option<PaletteCombo>,
+}
+
+impl Header {
+    pub fn of(cpu: &Cpu) -> Header {
+        let title = TITLE
+            .map(|address| cpu.memory.peek(address))
+            .take_while(|byte| *byte != 0)
+            .map(|byte| byte as char)
+            .collect();
+        let sram: Vec<u8> = SRAM.map(|address| cpu.memory.peek(address)).collect();
+        Header {
+            title,
+            model: cpu.memory.model,
+            rtc: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or(0),
+            sram: crc32(&sram),
+            boot_rom: (!cpu.memory.boot_rom.is_empty()).then(|| crc32(&cpu.memory.boot_rom)),
+            palette: cpu.memory.palette,
+        }
+    }
+
+    // The RTC time is not compared, it only seeds the clock
+    fn check(&self, actual: &Header) -> Result<(), MovieError> {
+        if self.title != actual.title {
+            return Err(MovieError::Mismatch(format!("'{}'", self.title)));
+        }
+        if self.model != actual.model {
+            return Err(MovieError::Mismatch(format!("model {}", self.model.name())));
+        }
+        if self.sram != actual.sram {
+            return Err(MovieError::Mismatch(
+                "different cartridge RAM contents".to_string(),
+            ));
+        }
+        if self.boot_rom != actual.boot_rom {
+            let boot_rom = match self.boot_rom {
+                Some(_) => "a different boot ROM",
+                None => "no boot ROM",
+            };
+            return Err(MovieError::Mismatch(boot_rom.to_string()));
+        }
+        if self.palette != actual.palette {
+            return Err(MovieError::Mismatch(format!(
+                "palette {}",
+                palette_name(self.palette)
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub buttons: u8,
+    pub hash: Option<u32>,
+}
+
+// A text file: the header, then one line per frame with the buttons held during it
+// and, every hash interval, the hash of the machine state at its end
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub header: Header,
+    pub frames: Vec<Frame>,
+}
+
+impl Movie {
+    pub fn new(cpu: &Cpu) -> Movie {
+        Movie {
+            header: Header::of(cpu),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Movie, MovieError> {
+        Movie::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn parse(text: &str) -> Result<Movie, MovieError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+        let version = lines
+            .next()
+            .and_then(|(_, line)| line.strip_prefix(MAGIC))
+            .and_then(|version| version.trim().parse().ok())
+            .ok_or(MovieError::NotAMovie)?;
+        if version != VERSION {
+            return Err(MovieError::Version(version));
+        }
+        let (mut title, mut model, mut rtc, mut sram) = (None, None, None, None);
+        let (mut boot_rom, mut palette) = (None, None);
+        let mut end = 1;
+        for (number, line) in lines.by_ref() {
+            end = number;
+            let error = |message: &str| MovieError::Parse(number, message.to_string());
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "title" => title = Some(value.to_string()),
+                "model" => model = Some(Model::from_name(value).ok_or_else(|| error("bad model"))?),
+                "rtc" => rtc = Some(value.parse().map_err(|_| error("bad RTC time"))?),
+                "sram" => {
+                    sram = Some(u32::from_str_radix(value, 16).map_err(|_| error("bad SRAM hash"))?)
+                }
+                "boot" => {
+                    boot_rom = Some(match value {
+                        "none" => None,
+                        _ => Some(
+                            u32::from_str_radix(value, 16)
+                                .map_err(|_| error("bad boot ROM hash"))?,
+                        ),
+                    })
+                }
+                "palette" => {
+                    palette = Some(match value {
+                        "auto" => None,
+                        _ => Some(
+                            PaletteCombo::from_name(value).ok_or_else(|| error("bad palette"))?,
+                        ),
+                    })
+                }
+                "input" => break,
+                _ => return Err(error("unknown header field")),
+            }
+        }
+        let header = match (title, model, rtc, sram, boot_rom, palette) {
+            (Some(title), Some(model), Some(rtc), Some(sram), Some(boot_rom), Some(palette)) => {
+                Header {
+                    title,
+                    model,
+                    rtc,
+                    sram,
+                    boot_rom,
+                    palette,
+                }
+            }
+            _ => return Err(MovieError::Parse(end, "incomplete header".to_string())),
+        };
+        let frames = lines
+            .map(|(number, line)| {
+                parse_frame(line)
+                    .ok_or_else(|| MovieError::Parse(number, format!("bad input '{}'", line)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Movie { header, frames })
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(formatter, "{} {}", MAGIC, VERSION)?;
+        writeln!(formatter, "title {}", self.header.title)?;
+        writeln!(formatter, "model {}", self.header.model.name())?;
+        writeln!(formatter, "rtc {}", self.header.rtc)?;
+        writeln!(formatter, "sram {:08x}", self.header.sram)?;
+        match self.header.boot_rom {
+            Some(hash) => writeln!(formatter, "boot {:08x}", hash)?,
+            None => writeln!(formatter, "boot none")?,
+        }
+        writeln!(formatter, "palette {}", palette_name(self.header.palette))?;
+        writeln!(formatter, "input")?;
+        for frame in &self.frames {
+            write!(formatter, "{}", button_text(frame.buttons))?;
+            if let Some(hash) = frame.hash {
+                write!(formatter, " {:08x}", hash)?;
+            }
+            writeln!(formatter)?;
+        }
+        Ok(())
+    }
+}
+
+fn palette_name(palette: Option<PaletteCombo>) -> &'static str {
+    palette.map_or("auto", |combo| combo.name())
+}
+
+fn button_text(buttons: u8) -> String {
+    BUTTON_LETTERS
+        .iter()
+        .enumerate()
+        .map(|(bit, letter)| {
+            if buttons & (1 << bit) != 0 {
+                *letter
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+fn parse_frame(line: &str) -> Option<Frame> {
+    let (buttons, hash) = match line.split_once(' ') {
+        Some((buttons, hash)) => (buttons, Some(u32::from_str_radix(hash, 16).ok()?)),
+        None => (line, None),
+    };
+    if buttons.chars().count() != BUTTON_LETTERS.len() {
+        return None;
+    }
+    let mut mask = 0;
+    for (bit, (letter, expected)) in buttons.chars().zip(BUTTON_LETTERS).enumerate() {
+        match letter {
+            '.' => {}
+            _ if letter == expected => mask |= 1 << bit,
+            _ => return None,
+        }
+    }
+    Some(Frame {
+        buttons: mask,
+        hash,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Recording,
+    Playing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Desync {
+    pub frame: usize,
+    pub expected: u32,
+    pub found: u32,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "movie desynced at frame {}: state hash {:08x}, recorded {:08x}",
+            self.frame, self.found, self.expected
+        )
+    }
+}
+
+// Records or plays a movie, the run loops call it around every frame
+#[derive(Debug)]
+pub struct Session {
+    pub movie: Movie,
+    pub mode: Mode,
+    pub frame: usize,
+    pub hash_interval: usize,
+    pub desync: Option<Desync>,
+}
+
+impl Session {
+    // Has to start from power-on, before the first instruction
+    pub fn record(cpu: &Cpu) -> Session {
+        Session {
+            movie: Movie::new(cpu),
+            mode: Mode::Recording,
+            frame: 0,
+            hash_interval: HASH_INTERVAL,
+            desync: None,
+        }
+    }
+
+    pub fn play(movie: Movie, cpu: &Cpu) -> Result<Session, MovieError> {
+        movie.header.check(&Header::of(cpu))?;
+        Ok(Session {
+            movie,
+            mode: Mode::Playing,
+            frame: 0,
+            hash_interval: HASH_INTERVAL,
+            desync: None,
+        })
+    }
+
+    // Live input is ignored until the recorded frames run out
+    pub fn is_playing(&self) -> bool {
+        self.mode == Mode::Playing && self.frame < self.movie.frames.len()
+    }
+
+    pub fn before_frame(&mut self, cpu: &mut Cpu) {
+        match self.mode {
+            Mode::Recording => self.movie.frames.push(Frame {
+                buttons: cpu.memory.joypad.pressed,
+                hash: None,
+            }),
+            Mode::Playing => {
+                if let Some(frame) = self.movie.frames.get(self.frame) {
+                    set_buttons(cpu, frame.buttons);
+                }
+            }
+        }
+    }
+
+    // False the first time the state differs from the recording. Playback checks every
+    // frame that has a hash, whatever interval it was recorded with
+    pub fn after_frame(&mut self, cpu: &Cpu) -> bool {
+        let number = self.frame;
+        self.frame += 1;
+        let Some(frame) = self.movie.frames.get_mut(number) else {
+            return true;
+        };
+        match self.mode {
+            Mode::Recording if self.frame.is_multiple_of(self.hash_interval) => {
+                frame.hash = Some(crc32(&snapshot(cpu)));
+            }
+            Mode::Playing if self.desync.is_none() => {
+                let Some(expected) = frame.hash else {
+                    return true;
+                };
+                let found = crc32(&snapshot(cpu));
+                if found != expected {
+                    self.desync = Some(Desync {
+                        frame: number,
+                        expected,
+                        found,
+                    });
+                    return false;
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+// Presses and releases go through the bus so the joypad interrupt fires as it did live
+fn set_buttons(cpu: &mut Cpu, buttons: u8) {
+    for button in BUTTONS {
+        let wanted = buttons & button.mask() != 0;
+        let held = cpu.memory.joypad.pressed & button.mask() != 0;
+        if wanted && !held {
+            cpu.memory.press(button);
+        } else if !wanted && held {
+            cpu.memory.release(button);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::frontend::run_frame;
+    use crate::joypad::Button;
+
+    const TEST_INTERVAL: usize = 4;
+
+    fn power_on() -> Cpu {
+        let mut rom = vec![0; 0x8000];
+        rom[TITLE.start..TITLE.start + 5].copy_from_slice(b"MOVIE");
+        // Halted, P1 selects both button groups so every press raises the interrupt
+        rom[0x100] = asm!("halt")[0];
+        let mut cpu = Cpu::from_cartridge(&Cartridge::new(rom));
+        cpu.memory.set_byte(0x00, 0xFF00);
+        cpu
+    }
+
+    fn record(frames: usize) -> Movie {
+        let mut cpu = power_on();
+        let mut session = Session::record(&cpu);
+        session.hash_interval = TEST_INTERVAL;
+        for frame in 0..frames {
+            if frame % 7 == 0 {
+                cpu.memory.press(Button::A);
+            } else if frame % 7 == 3 {
+                cpu.memory.release(Button::A);
+            }
+            cpu.memory.press(Button::Right);
+            session.before_frame(&mut cpu);
+            run_frame(&mut cpu);
+            assert!(session.after_frame(&cpu));
+        }
+        session.movie
+    }
+
+    #[test]
+    fn it_should_record_buttons_and_hashes() {
+        let movie = record(TEST_INTERVAL * 4);
+
+        assert_eq!(movie.header.title, "MOVIE");
+        assert_eq!(movie.header.model, Model::Dmg);
+        assert_eq!(movie.frames.len(), TEST_INTERVAL * 4);
+        assert_eq!(movie.frames[0].buttons, 0x11);
+        assert_eq!(movie.frames[3].buttons, 0x01);
+        assert!(movie.frames[TEST_INTERVAL - 1].hash.is_some());
+        assert!(movie.frames[TEST_INTERVAL].hash.is_none());
+    }
+
+    #[test]
+    fn it_should_play_back_in_sync() {
+        let movie = record(TEST_INTERVAL * 4);
+        let mut cpu = power_on();
+        let mut session = Session::play(movie, &cpu).unwrap();
+        while session.is_playing() {
+            session.before_frame(&mut cpu);
+            run_frame(&mut cpu);
+            assert!(session.after_frame(&cpu));
+        }
+
+        assert_eq!(session.frame, TEST_INTERVAL * 4);
+        assert_eq!(session.desync, None);
+    }
+
+    #[test]
+    fn it_should_detect_a_desync() {
+        let movie = record(TEST_INTERVAL * 4);
+        let mut cpu = power_on();
+        let mut session = Session::play(movie, &cpu).unwrap();
+        let mut in_sync = true;
+        while session.is_playing() {
+            // Something the recording did not see, as a nondeterministic bug would
+            if session.frame == 10 {
+                cpu.memory.set_byte(0x01, 0xC000);
+            }
+            session.before_frame(&mut cpu);
+            run_frame(&mut cpu);
+            in_sync &= session.after_frame(&cpu);
+        }
+
+        assert!(!in_sync);
+        assert_eq!(session.desync.unwrap().frame, 11);
+    }
+
+    #[test]
+    fn it_should_round_trip_through_text() {
+        let movie = record(TEST_INTERVAL + 1);
+        let text = movie.to_string();
+
+        assert!(text.starts_with("game-boy movie 1\ntitle MOVIE\nmodel dmg\n"));
+        assert!(text.contains("\ninput\nR...A...\n"));
+        assert_eq!(Movie::parse(&text).unwrap(), movie);
+    }
+
+    #[test]
+    fn it_should_refuse_another_game() {
+        let movie = record(1);
+        let mut cpu = power_on();
+        cpu.memory.set_byte(0x12, 0xA000);
+
+        let error = Session::play(movie, &cpu).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "recorded with different cartridge RAM contents"
+        );
+    }
+
+    #[test]
+    fn it_should_refuse_another_boot_rom_or_palette() {
+        let movie = record(1);
+        let mut cpu = power_on();
+        cpu.memory.boot_rom = vec![0x31, 0xFE, 0xFF];
+        let boot_rom = Session::play(movie.clone(), &cpu).unwrap_err();
+        let mut cpu = power_on();
+        cpu.memory.palette = Some(PaletteCombo::DownB);
+        let mut colored = movie.clone();
+        colored.header.palette = Some(PaletteCombo::LeftA);
+        let palette = Session::play(colored, &cpu).unwrap_err();
+
+        assert_eq!(boot_rom.to_string(), "recorded with no boot ROM");
+        assert_eq!(palette.to_string(), "recorded with palette left+a");
+        assert!(Session::play(movie, &power_on()).is_ok());
+    }
+
+    #[test]
+    fn it_should_reject_bad_files() {
+        assert!(matches!(Movie::parse("PNG"), Err(MovieError::NotAMovie)));
+        assert!(matches!(
+            Movie::parse("game-boy movie 9\n"),
+            Err(MovieError::Version(9))
+        ));
+        assert!(matches!(
+            Movie::parse(
+                "game-boy movie 1\ntitle A\nmodel dmg\nrtc 0\nsram 0\nboot none\npalette auto\ninput\nRLUD\n"
+            ),
+            Err(MovieError::Parse(9, _))
+        ));
+        assert!(matches!(
+            Movie::parse("game-boy movie 1\ntitle A\ninput\n"),
+            Err(MovieError::Parse(3, _))
+        ));
+    }
+}
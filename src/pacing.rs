@@ -0,0 +1,196 @@
+#![allow(dead_code)]
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::FRAME_RATE;
+
+// Sleeping is only accurate to a millisecond or two, the rest is spun away
+const SPIN_MARGIN: Duration = Duration::from_micros(1500);
+// Further behind than this and the pacer starts over instead of rushing to catch up
+const MAX_LAG: u32 = 4;
+// Audio sync keeps the sample buffer about half full, like the dynamic rate does
+pub const AUDIO_TARGET_FILL: f64 = 0.5;
+// How long audio sync sleeps between looks at the buffer
+const AUDIO_POLL: Duration = Duration::from_millis(1);
+// How often the measured speed is reported
+const MEASURE_PERIOD: Duration = Duration::from_secs(1);
+
+// What decides when the next frame starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sync {
+    // The host clock, at the Game Boy's own frame rate
+    Timer,
+    // Waits for the audio device to drain the sample buffer
+    Audio,
+    // Presenting blocks until the display refreshes, so the pacer does not wait at all
+    Vsync,
+}
+
+impl Sync {
+    pub fn from_name(name: &str) -> Option<Sync> {
+        match name.to_lowercase().as_str() {
+            "timer" => Some(Sync::Timer),
+            "audio" => Some(Sync::Audio),
+            "vsync" => Some(Sync::Vsync),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Pacer {
+    pub sync: Sync,
+    pub period: Duration,
+    deadline: Instant,
+}
+
+impl Pacer {
+    pub fn new(sync: Sync) -> Pacer {
+        Pacer {
+            sync,
+            period: Duration::from_secs_f64(1.0 / FRAME_RATE),
+            deadline: Instant::now(),
+        }
+    }
+
+    // Waits for the end of the current frame. The deadline advances by whole periods
+    // so rounding never drifts, fill is the audio buffer fill ratio for audio sync
+    pub fn wait(&mut self, fill: impl Fn() -> f64) {
+        self.deadline += self.period;
+        let now = Instant::now();
+        if now > self.deadline + self.period * MAX_LAG {
+            self.deadline = now;
+            return;
+        }
+        match self.sync {
+            Sync::Timer => sleep_until(self.deadline),
+            Sync::Audio => {
+                // Nobody draining the buffer must not stall the emulation, past one
+                // extra period it goes on anyway
+                let limit = self.deadline + self.period;
+                while fill() > AUDIO_TARGET_FILL && Instant::now() < limit {
+                    thread::sleep(AUDIO_POLL);
+                }
+                self.deadline = Instant::now();
+            }
+            Sync::Vsync => self.deadline = now,
+        }
+    }
+
+    // Starts timing over from now, after running uncapped or being paused by the host
+    pub fn reset(&mut self) {
+        self.deadline = Instant::now();
+    }
+}
+
+// Sleeps most of the way, then spins for the last stretch
+pub fn sleep_until(deadline: Instant) {
+    let now = Instant::now();
+    if deadline > now + SPIN_MARGIN {
+        thread::sleep(deadline - now - SPIN_MARGIN);
+    }
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+// Emulated frames against wall time, 1.0 is the speed of a real Game Boy
+#[derive(Debug)]
+pub struct SpeedMeter {
+    start: Instant,
+    frames: u64,
+}
+
+impl SpeedMeter {
+    pub fn new() -> SpeedMeter {
+        SpeedMeter {
+            start: Instant::now(),
+            frames: 0,
+        }
+    }
+
+    // Counts frames and returns the speed once per measuring period
+    pub fn record(&mut self, frames: u64) -> Option<f64> {
+        self.frames += frames;
+        let elapsed = self.start.elapsed();
+        if elapsed < MEASURE_PERIOD {
+            return None;
+        }
+        let speed = speed(self.frames, elapsed);
+        self.start = Instant::now();
+        self.frames = 0;
+        Some(speed)
+    }
+}
+
+impl Default for SpeedMeter {
+    fn default() -> SpeedMeter {
+        SpeedMeter::new()
+    }
+}
+
+pub fn speed(frames: u64, elapsed: Duration) -> f64 {
+    frames as f64 / elapsed.as_secs_f64() / FRAME_RATE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_pace_at_the_game_boy_frame_rate() {
+        let mut pacer = Pacer::new(Sync::Timer);
+        let start = Instant::now();
+        for _ in 0..6 {
+            pacer.wait(|| 0.0);
+        }
+        let elapsed = start.elapsed();
+
+        assert!(elapsed >= pacer.period * 6);
+        assert!(elapsed < pacer.period * 6 + Duration::from_millis(100));
+        assert_eq!(pacer.period.as_micros(), 16_742);
+    }
+
+    #[test]
+    fn it_should_start_over_when_far_behind() {
+        let mut pacer = Pacer::new(Sync::Timer);
+        thread::sleep(pacer.period * (MAX_LAG + 2));
+        let start = Instant::now();
+        pacer.wait(|| 0.0);
+        pacer.wait(|| 0.0);
+
+        assert!(start.elapsed() < pacer.period * 2);
+    }
+
+    #[test]
+    fn it_should_not_stall_on_a_full_audio_buffer() {
+        let mut pacer = Pacer::new(Sync::Audio);
+        let start = Instant::now();
+        pacer.wait(|| 1.0);
+
+        assert!(start.elapsed() < pacer.period * 3);
+    }
+
+    #[test]
+    fn it_should_not_wait_with_an_empty_audio_buffer() {
+        let mut pacer = Pacer::new(Sync::Audio);
+        let start = Instant::now();
+        pacer.wait(|| 0.0);
+
+        assert!(start.elapsed() < pacer.period);
+    }
+
+    #[test]
+    fn it_should_measure_speed_against_the_frame_rate() {
+        assert!((speed(120, Duration::from_secs_f64(120.0 / FRAME_RATE)) - 1.0).abs() < 1e-9);
+        assert!((speed(60, Duration::from_secs_f64(30.0 / FRAME_RATE)) - 2.0).abs() < 1e-9);
+        assert_eq!(SpeedMeter::new().record(10), None);
+    }
+
+    #[test]
+    fn it_should_parse_sync_names() {
+        assert_eq!(Sync::from_name("Audio"), Some(Sync::Audio));
+        assert_eq!(Sync::from_name("vsync"), Some(Sync::Vsync));
+        assert_eq!(Sync::from_name("never"), None);
+    }
+}
@@ -6,3 +6,5 @@ pub mod load;
 pub mod maths;
 pub mod memorybus;
 pub mod registers;
+pub mod trace;
+pub mod watch;
@@ -1,13 +1,21 @@
 #![allow(dead_code)]
-use crate::processor::instructions::Instruction;
+use crate::cartridge::Cartridge;
+use crate::logging::Level;
+use crate::model::Model;
+use crate::processor::instructions::{Instruction, OPCODE_CYCLES};
 use crate::processor::memorybus::MemoryBus;
 use crate::processor::registers::Registers;
+use crate::processor::trace::Tracer;
+
+const LD_B_B: u8 = 0x40;
 
 #[derive(Debug)]
 pub struct Cpu {
     pub registers: Registers,
     pub memory: MemoryBus,
     pub pause: bool,
+    pub tracer: Option<Tracer>,
+    pub breakpoint: bool,
 }
 
 impl Cpu {
@@ -16,29 +24,89 @@ impl Cpu {
             registers: Registers::new(),
             memory: MemoryBus::new(),
             pause: false,
+            tracer: None,
+            breakpoint: false,
         }
     }
 
-    pub fn run(self: &mut Cpu) -> u8 {
-        loop {
-            if !self.is_halted() {
-                let opcode = self.memory.fetch_next_instruction();
-                if let Some(instruction) = Instruction::from_byte(opcode) {
-                    let is_over = self.execute(instruction);
-                    if is_over {
-                        break;
-                    }
+    pub fn from_cartridge(cartridge: &Cartridge) -> Cpu {
+        Cpu::from_cartridge_on(cartridge, cartridge.model())
+    }
+
+    // Runs the cartridge on the given hardware instead of the one its header asks for
+    pub fn from_cartridge_on(cartridge: &Cartridge, model: Model) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.memory.load_cartridge_on(cartridge, model);
+        cpu.boot();
+        cpu
+    }
+
+    // Starts at 0 with cleared registers and lets the boot ROM initialize the hardware
+    pub fn load_boot_rom(&mut self, boot_rom: Vec<u8>) {
+        self.registers = Registers::new();
+        self.memory.boot_rom = boot_rom;
+        self.memory.pc = 0;
+    }
+
+    // Register values left by the boot ROM, games read A to detect CGB hardware
+    fn boot(&mut self) {
+        match self.memory.model {
+            Model::Dmg | Model::Sgb => {
+                self.registers.set_af(0x01B0);
+                self.registers.set_bc(0x0013);
+                self.registers.set_de(0x00D8);
+                self.registers.set_hl(0x014D);
+            }
+            Model::Cgb | Model::Agb => {
+                self.registers.set_af(0x1180);
+                // B tells games they run on a GBA
+                let b = if self.memory.model == Model::Agb {
+                    0x01
                 } else {
-                    println!("Unknown opcode: {:#x}", opcode);
-                }
+                    0x00
+                };
+                self.registers.set_bc(b << 8);
+                self.registers.set_de(0xFF56);
+                self.registers.set_hl(0x000D);
             }
         }
+        self.registers.set_sp(0xFFFE);
+        self.memory.pc = 0x100;
+    }
+
+    pub fn run(self: &mut Cpu) -> u8 {
+        while self.step().is_some() {}
         1
     }
 
+    pub fn step(&mut self) -> Option<u32> {
+        let mut cycles = 4;
+        if !self.is_halted() {
+            if let Some(tracer) = &mut self.tracer {
+                tracer.record(&self.registers, &self.memory);
+            }
+            let opcode = self.memory.fetch_next_instruction();
+            // Test ROMs use LD B,B as a software breakpoint
+            if opcode == LD_B_B {
+                self.breakpoint = true;
+            }
+            if let Some(instruction) = Instruction::from_byte(opcode) {
+                let is_over = self.execute(instruction);
+                if is_over {
+                    return None;
+                }
+                cycles = OPCODE_CYCLES[opcode as usize];
+            } else {
+                log!(Level::Warn, "unknown opcode {:#04x}", opcode);
+            }
+        }
+        Some(self.memory.tick(cycles))
+    }
+
     fn execute(&mut self, instruction: Instruction) -> bool {
         match instruction {
             Instruction::Halt => self.halt(),
+            Instruction::Stop => self.stop(),
             Instruction::Daa() => self.daa(),
             Instruction::Scf() => self.scf(),
             Instruction::Inc(target) => self.inc_dispatch(target),
@@ -70,3 +138,64 @@ impl Cpu {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_boot_cgb_cartridge_with_cgb_registers() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = 0xC0;
+        let cpu = Cpu::from_cartridge(&Cartridge::new(rom));
+
+        assert_eq!(cpu.registers.a, 0x11);
+        assert_eq!(cpu.memory.pc, 0x100);
+        assert!(cpu.memory.cgb_mode);
+    }
+
+    #[test]
+    fn it_should_boot_agb_with_b_set() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = 0x80;
+        let mut cpu = Cpu::from_cartridge_on(&Cartridge::new(rom), Model::Agb);
+
+        assert_eq!(cpu.registers.af(), 0x1180);
+        assert_eq!(cpu.registers.bc(), 0x0100);
+        assert!(cpu.memory.cgb_mode);
+    }
+
+    #[test]
+    fn it_should_run_boot_rom_until_it_is_unmapped() {
+        let mut cpu = Cpu::from_cartridge(&Cartridge::new(vec![0x00; 0x8000]));
+        cpu.load_boot_rom(vec![0x3E; 0x100]);
+
+        assert_eq!(cpu.memory.pc, 0);
+        assert_eq!(cpu.memory.fetch_byte_at(0x0000), 0x3E);
+        cpu.memory.set_byte(0x01, 0xFF50);
+        assert_eq!(cpu.memory.fetch_byte_at(0x0000), 0x00);
+    }
+
+    #[test]
+    fn it_should_flag_ld_b_b_as_breakpoint() {
+        let mut cpu = Cpu::new();
+        cpu.memory.set_byte(0x00, 0x00);
+        cpu.memory.set_byte(LD_B_B, 0x01);
+        cpu.step();
+        assert!(!cpu.breakpoint);
+
+        cpu.step();
+        assert!(cpu.breakpoint);
+    }
+
+    #[test]
+    fn it_should_advance_the_ppu_with_instruction_cycles() {
+        let mut cpu = Cpu::new();
+        cpu.memory.set_byte(0x00, 0);
+        cpu.memory.set_byte(0x01, 1);
+
+        assert_eq!(cpu.step(), Some(4));
+        assert_eq!(cpu.step(), Some(12));
+        assert_eq!(cpu.memory.ppu.dots, 16);
+    }
+}
@@ -1,3 +1,4 @@
 pub mod comp;
 pub mod halt;
 pub mod scf;
+pub mod stop;
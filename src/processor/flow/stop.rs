@@ -0,0 +1,42 @@
+use crate::processor::cpu::Cpu;
+
+impl Cpu {
+    // STOP is followed by a padding byte, on CGB it performs an armed speed switch
+    pub fn stop(&mut self) {
+        self.memory.fetch_next_byte();
+        if !self.memory.switch_speed() {
+            self.halt();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+
+    #[test]
+    fn it_should_switch_to_double_speed_when_armed() {
+        let mut cpu = Cpu::new();
+        cpu.memory.set_model(Model::Cgb, true);
+        cpu.memory.set_byte(0x01, 0xFF4D);
+        cpu.memory.set_byte(0x10, 0);
+        cpu.memory.set_byte(0x00, 1);
+        cpu.step();
+
+        assert!(cpu.memory.double_speed);
+        assert_eq!(cpu.memory.fetch_byte_at(0xFF4D), 0xFE);
+        assert_eq!(cpu.memory.pc, 2);
+    }
+
+    #[test]
+    fn it_should_run_the_ppu_at_half_rate_in_double_speed() {
+        let mut cpu = Cpu::new();
+        cpu.memory.set_model(Model::Cgb, true);
+        cpu.memory.double_speed = true;
+        cpu.memory.set_byte(0x00, 0);
+        cpu.step();
+
+        assert_eq!(cpu.memory.ppu.dots, 2);
+    }
+}
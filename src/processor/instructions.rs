@@ -22,6 +22,7 @@ pub enum Instruction {
     Load8(TargetLd8),
     LoadByteA(ByteTarget),
     Halt,
+    Stop,
     Nop,
     Daa(),
     Scf(),
@@ -99,7 +100,264 @@ pub enum ByteTarget {
     HLm,
 }
 
+impl IncTarget {
+    fn operand(&self) -> &'static str {
+        match self {
+            IncTarget::A => "A",
+            IncTarget::B => "B",
+            IncTarget::C => "C",
+            IncTarget::D => "D",
+            IncTarget::E => "E",
+            IncTarget::H => "H",
+            IncTarget::L => "L",
+            IncTarget::BC => "BC",
+            IncTarget::DE => "DE",
+            IncTarget::SP => "SP",
+            IncTarget::HL => "HL",
+            IncTarget::HLFlags => "(HL)",
+        }
+    }
+}
+
+impl ArithmeticTarget {
+    fn operand(&self) -> &'static str {
+        match self {
+            ArithmeticTarget::A => "A",
+            ArithmeticTarget::B => "B",
+            ArithmeticTarget::C => "C",
+            ArithmeticTarget::D => "D",
+            ArithmeticTarget::E => "E",
+            ArithmeticTarget::H => "H",
+            ArithmeticTarget::L => "L",
+            ArithmeticTarget::HL => "(HL)",
+        }
+    }
+}
+
+impl Target {
+    fn operand(&self) -> &'static str {
+        match self {
+            Target::A => "A",
+            Target::B => "B",
+            Target::C => "C",
+            Target::D => "D",
+            Target::E => "E",
+            Target::H => "H",
+            Target::L => "L",
+            Target::HL => "(HL)",
+        }
+    }
+}
+
+impl TargetLd8 {
+    // Register targets take an immediate, the others move A through memory
+    fn operands(&self) -> &'static str {
+        match self {
+            TargetLd8::A => "LD A,n8",
+            TargetLd8::B => "LD B,n8",
+            TargetLd8::C => "LD C,n8",
+            TargetLd8::D => "LD D,n8",
+            TargetLd8::E => "LD E,n8",
+            TargetLd8::H => "LD H,n8",
+            TargetLd8::L => "LD L,n8",
+            TargetLd8::HL => "LD (HL),n8",
+            TargetLd8::Abc => "LD A,(BC)",
+            TargetLd8::Ade => "LD A,(DE)",
+            TargetLd8::AHLp => "LD A,(HL+)",
+            TargetLd8::AHLm => "LD A,(HL-)",
+            TargetLd8::A8A => "LDH (a8),A",
+            TargetLd8::AA8 => "LDH A,(a8)",
+            TargetLd8::C8A => "LD (C),A",
+            TargetLd8::AC8 => "LD A,(C)",
+        }
+    }
+}
+
+impl Load16Target {
+    fn operands(&self) -> &'static str {
+        match self {
+            Load16Target::BC => "LD BC,n16",
+            Load16Target::DE => "LD DE,n16",
+            Load16Target::HL => "LD HL,n16",
+            Load16Target::SP => "LD SP,n16",
+            Load16Target::A16A => "LD (a16),A",
+            Load16Target::AA16 => "LD A,(a16)",
+        }
+    }
+}
+
+impl ByteTarget {
+    fn operand(&self) -> &'static str {
+        match self {
+            ByteTarget::BC => "(BC)",
+            ByteTarget::DE => "(DE)",
+            ByteTarget::HLp => "(HL+)",
+            ByteTarget::HLm => "(HL-)",
+        }
+    }
+}
+
+// Clock cycles taken by each opcode when no branch is taken
+#[rustfmt::skip]
+pub const OPCODE_CYCLES: [u32; 256] = [
+    4, 12, 8, 8, 4, 4, 8, 4, 20, 8, 8, 8, 4, 4, 8, 4,
+    4, 12, 8, 8, 4, 4, 8, 4, 12, 8, 8, 8, 4, 4, 8, 4,
+    8, 12, 8, 8, 4, 4, 8, 4, 8, 8, 8, 8, 4, 4, 8, 4,
+    8, 12, 8, 8, 12, 12, 12, 4, 8, 8, 8, 8, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    8, 8, 8, 8, 8, 8, 4, 8, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    8, 12, 12, 16, 12, 16, 8, 16, 8, 16, 12, 4, 12, 24, 8, 16,
+    8, 12, 12, 0, 12, 16, 8, 16, 8, 16, 12, 0, 12, 0, 8, 16,
+    12, 12, 8, 0, 0, 16, 8, 16, 16, 4, 16, 0, 0, 0, 8, 16,
+    12, 12, 8, 4, 0, 16, 8, 16, 12, 8, 16, 4, 0, 0, 8, 16,
+];
+
+// Mnemonics with operand placeholders: n8/n16 immediates, a8/a16 addresses and e8
+// signed offsets. Empty entries are illegal opcodes, 0xCB prefixes the second page
+#[rustfmt::skip]
+pub const OPCODE_MNEMONICS: [&str; 256] = [
+    "NOP", "LD BC,n16", "LD (BC),A", "INC BC", "INC B", "DEC B", "LD B,n8", "RLCA",
+    "LD (a16),SP", "ADD HL,BC", "LD A,(BC)", "DEC BC", "INC C", "DEC C", "LD C,n8", "RRCA",
+    "STOP", "LD DE,n16", "LD (DE),A", "INC DE", "INC D", "DEC D", "LD D,n8", "RLA",
+    "JR e8", "ADD HL,DE", "LD A,(DE)", "DEC DE", "INC E", "DEC E", "LD E,n8", "RRA",
+    "JR NZ,e8", "LD HL,n16", "LD (HL+),A", "INC HL", "INC H", "DEC H", "LD H,n8", "DAA",
+    "JR Z,e8", "ADD HL,HL", "LD A,(HL+)", "DEC HL", "INC L", "DEC L", "LD L,n8", "CPL",
+    "JR NC,e8", "LD SP,n16", "LD (HL-),A", "INC SP", "INC (HL)", "DEC (HL)", "LD (HL),n8", "SCF",
+    "JR C,e8", "ADD HL,SP", "LD A,(HL-)", "DEC SP", "INC A", "DEC A", "LD A,n8", "CCF",
+    "LD B,B", "LD B,C", "LD B,D", "LD B,E", "LD B,H", "LD B,L", "LD B,(HL)", "LD B,A",
+    "LD C,B", "LD C,C", "LD C,D", "LD C,E", "LD C,H", "LD C,L", "LD C,(HL)", "LD C,A",
+    "LD D,B", "LD D,C", "LD D,D", "LD D,E", "LD D,H", "LD D,L", "LD D,(HL)", "LD D,A",
+    "LD E,B", "LD E,C", "LD E,D", "LD E,E", "LD E,H", "LD E,L", "LD E,(HL)", "LD E,A",
+    "LD H,B", "LD H,C", "LD H,D", "LD H,E", "LD H,H", "LD H,L", "LD H,(HL)", "LD H,A",
+    "LD L,B", "LD L,C", "LD L,D", "LD L,E", "LD L,H", "LD L,L", "LD L,(HL)", "LD L,A",
+    "LD (HL),B", "LD (HL),C", "LD (HL),D", "LD (HL),E", "LD (HL),H", "LD (HL),L", "HALT", "LD (HL),A",
+    "LD A,B", "LD A,C", "LD A,D", "LD A,E", "LD A,H", "LD A,L", "LD A,(HL)", "LD A,A",
+    "ADD A,B", "ADD A,C", "ADD A,D", "ADD A,E", "ADD A,H", "ADD A,L", "ADD A,(HL)", "ADD A,A",
+    "ADC A,B", "ADC A,C", "ADC A,D", "ADC A,E", "ADC A,H", "ADC A,L", "ADC A,(HL)", "ADC A,A",
+    "SUB B", "SUB C", "SUB D", "SUB E", "SUB H", "SUB L", "SUB (HL)", "SUB A",
+    "SBC A,B", "SBC A,C", "SBC A,D", "SBC A,E", "SBC A,H", "SBC A,L", "SBC A,(HL)", "SBC A,A",
+    "AND B", "AND C", "AND D", "AND E", "AND H", "AND L", "AND (HL)", "AND A",
+    "XOR B", "XOR C", "XOR D", "XOR E", "XOR H", "XOR L", "XOR (HL)", "XOR A",
+    "OR B", "OR C", "OR D", "OR E", "OR H", "OR L", "OR (HL)", "OR A",
+    "CP B", "CP C", "CP D", "CP E", "CP H", "CP L", "CP (HL)", "CP A",
+    "RET NZ", "POP BC", "JP NZ,a16", "JP a16", "CALL NZ,a16", "PUSH BC", "ADD A,n8", "RST $00",
+    "RET Z", "RET", "JP Z,a16", "PREFIX", "CALL Z,a16", "CALL a16", "ADC A,n8", "RST $08",
+    "RET NC", "POP DE", "JP NC,a16", "", "CALL NC,a16", "PUSH DE", "SUB n8", "RST $10",
+    "RET C", "RETI", "JP C,a16", "", "CALL C,a16", "", "SBC A,n8", "RST $18",
+    "LDH (a8),A", "POP HL", "LD (C),A", "", "", "PUSH HL", "AND n8", "RST $20",
+    "ADD SP,e8", "JP HL", "LD (a16),A", "", "", "", "XOR n8", "RST $28",
+    "LDH A,(a8)", "POP AF", "LD A,(C)", "DI", "", "PUSH AF", "OR n8", "RST $30",
+    "LD HL,SP+e8", "LD SP,HL", "LD A,(a16)", "EI", "", "", "CP n8", "RST $38",
+];
+
+pub const PREFIX: u8 = 0xCB;
+
+const CB_OPERATIONS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+const CB_BIT_OPERATIONS: [&str; 3] = ["BIT", "RES", "SET"];
+const CB_TARGETS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+
+// The second page is regular: the operation in the top bits, the register in the low 3
+pub fn cb_mnemonic(opcode: u8) -> String {
+    let target = CB_TARGETS[(opcode & 0x7) as usize];
+    match opcode >> 6 {
+        0 => format!("{} {}", CB_OPERATIONS[(opcode >> 3) as usize], target),
+        group => format!(
+            "{} {},{}",
+            CB_BIT_OPERATIONS[group as usize - 1],
+            (opcode >> 3) & 0x7,
+            target
+        ),
+    }
+}
+
+// Size in bytes including the opcode, STOP is followed by a padding byte
+pub fn opcode_length(opcode: u8) -> usize {
+    let mnemonic = OPCODE_MNEMONICS[opcode as usize];
+    if mnemonic.contains("n16") || mnemonic.contains("a16") {
+        3
+    } else if ["n8", "a8", "e8"]
+        .iter()
+        .any(|operand| mnemonic.contains(operand))
+        || opcode == PREFIX
+        || opcode == 0x10
+    {
+        2
+    } else {
+        1
+    }
+}
+
 impl Instruction {
+    // First word of the mnemonic
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::Exit => "EXIT",
+            Instruction::Add(_) => "ADD",
+            Instruction::AddC(_) => "ADC",
+            Instruction::Sub(_) => "SUB",
+            Instruction::SubC(_) => "SBC",
+            Instruction::And(_) => "AND",
+            Instruction::Xor(_) => "XOR",
+            Instruction::Or(_) => "OR",
+            Instruction::Cp(_) => "CP",
+            Instruction::Load8(TargetLd8::A8A) | Instruction::Load8(TargetLd8::AA8) => "LDH",
+            Instruction::LoadB(_)
+            | Instruction::LoadC(_)
+            | Instruction::LoadD(_)
+            | Instruction::LoadE(_)
+            | Instruction::LoadH(_)
+            | Instruction::LoadL(_)
+            | Instruction::LoadA(_)
+            | Instruction::LoadHL(_)
+            | Instruction::Load16(_)
+            | Instruction::Load8(_)
+            | Instruction::LoadByteA(_) => "LD",
+            Instruction::Halt => "HALT",
+            Instruction::Stop => "STOP",
+            Instruction::Nop => "NOP",
+            Instruction::Daa() => "DAA",
+            Instruction::Scf() => "SCF",
+            Instruction::Inc(_) => "INC",
+            Instruction::Dec(_) => "DEC",
+        }
+    }
+
+    // Full mnemonic with operands in the form OPCODE_MNEMONICS uses, so the decoder and
+    // the table can be compared opcode by opcode
+    pub fn mnemonic(&self) -> String {
+        match self {
+            Instruction::Add(target) | Instruction::AddC(target) | Instruction::SubC(target) => {
+                format!("{} A,{}", self.name(), target.operand())
+            }
+            Instruction::Sub(target)
+            | Instruction::And(target)
+            | Instruction::Xor(target)
+            | Instruction::Or(target)
+            | Instruction::Cp(target) => format!("{} {}", self.name(), target.operand()),
+            Instruction::LoadB(target) => format!("LD B,{}", target.operand()),
+            Instruction::LoadC(target) => format!("LD C,{}", target.operand()),
+            Instruction::LoadD(target) => format!("LD D,{}", target.operand()),
+            Instruction::LoadE(target) => format!("LD E,{}", target.operand()),
+            Instruction::LoadH(target) => format!("LD H,{}", target.operand()),
+            Instruction::LoadL(target) => format!("LD L,{}", target.operand()),
+            Instruction::LoadA(target) => format!("LD A,{}", target.operand()),
+            Instruction::LoadHL(target) => format!("LD (HL),{}", target.operand()),
+            Instruction::Load16(target) => target.operands().to_string(),
+            Instruction::Load8(target) => target.operands().to_string(),
+            Instruction::LoadByteA(target) => format!("LD {},A", target.operand()),
+            Instruction::Inc(target) | Instruction::Dec(target) => {
+                format!("{} {}", self.name(), target.operand())
+            }
+            _ => self.name().to_string(),
+        }
+    }
+
     pub fn from_byte(byte: u8) -> Option<Instruction> {
         match byte {
             0xFC => Some(Instruction::Exit),
@@ -118,6 +376,7 @@ impl Instruction {
             0x0D => Some(Instruction::Dec(IncTarget::C)),
             0x0E => Some(Instruction::Load8(TargetLd8::C)),
 
+            0x10 => Some(Instruction::Stop),
             0x11 => Some(Instruction::Load16(Load16Target::DE)),
             0x12 => Some(Instruction::LoadByteA(ByteTarget::DE)),
             0x13 => Some(Instruction::Inc(IncTarget::DE)),
@@ -303,3 +562,61 @@ impl Instruction {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0xFC is illegal on hardware, the emulator uses it to stop running
+    #[test]
+    fn it_should_decode_like_the_mnemonic_table() {
+        for opcode in 0..=0xFFu8 {
+            if let Some(instruction) = Instruction::from_byte(opcode) {
+                if opcode != 0xFC {
+                    assert_eq!(
+                        instruction.mnemonic(),
+                        OPCODE_MNEMONICS[opcode as usize],
+                        "opcode {:#04x}",
+                        opcode
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_only_give_cycles_to_legal_opcodes() {
+        for opcode in 0..=0xFFu8 {
+            let legal = !OPCODE_MNEMONICS[opcode as usize].is_empty();
+            assert_eq!(
+                OPCODE_CYCLES[opcode as usize] != 0,
+                legal,
+                "{:#04x}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn it_should_name_cb_opcodes() {
+        assert_eq!(cb_mnemonic(0x00), "RLC B");
+        assert_eq!(cb_mnemonic(0x37), "SWAP A");
+        assert_eq!(cb_mnemonic(0x7C), "BIT 7,H");
+        assert_eq!(cb_mnemonic(0x86), "RES 0,(HL)");
+        assert_eq!(cb_mnemonic(0xFF), "SET 7,A");
+    }
+
+    #[test]
+    fn it_should_size_instructions_from_operands() {
+        assert_eq!(opcode_length(0x00), 1);
+        assert_eq!(opcode_length(0x3E), 2);
+        assert_eq!(opcode_length(0x20), 2);
+        assert_eq!(opcode_length(0x10), 2);
+        assert_eq!(opcode_length(0xCB), 2);
+        assert_eq!(opcode_length(0x21), 3);
+        assert_eq!(opcode_length(0xEA), 3);
+        assert_eq!(opcode_length(0x2A), 1);
+        assert_eq!(opcode_length(0x36), 2);
+        assert_eq!(opcode_length(0xCF), 1);
+    }
+}
@@ -29,9 +29,7 @@ mod tests {
     #[test]
     fn it_should_load_16_from_memory_to_a() {
         let mut cpu = Cpu::new();
-        cpu.memory.set_byte(0xFA, 0);
-        cpu.memory.set_byte(0xFF, 0x1);
-        cpu.memory.set_byte(0x55, 0x2);
+        cpu.memory.load(0, &asm!("ld a,[$55FF]"));
         cpu.memory.set_byte(0x13, 0x55FF);
 
         cpu.run();
@@ -41,9 +39,7 @@ mod tests {
     #[test]
     fn it_should_load_a_from_memory_to_16() {
         let mut cpu = Cpu::new();
-        cpu.memory.set_byte(0xEA, 0);
-        cpu.memory.set_byte(0x55, 0x1);
-        cpu.memory.set_byte(0xFF, 0x2);
+        cpu.memory.load(0, &asm!("ld [$FF55],a"));
         cpu.registers.a = 0x13;
 
         cpu.run();
@@ -54,9 +50,7 @@ mod tests {
     #[test]
     fn it_should_load_16_from_memory_to_bc() {
         let mut cpu = Cpu::new();
-        cpu.memory.set_byte(0x01, 0);
-        cpu.memory.set_byte(0xaf, 0x1);
-        cpu.memory.set_byte(0x23, 0x2);
+        cpu.memory.load(0, &asm!("ld bc,$23AF"));
 
         cpu.run();
 
@@ -66,9 +60,7 @@ mod tests {
     #[test]
     fn it_should_load_16_from_memory_to_de() {
         let mut cpu = Cpu::new();
-        cpu.memory.set_byte(0x11, 0);
-        cpu.memory.set_byte(0xaf, 0x1);
-        cpu.memory.set_byte(0x23, 0x2);
+        cpu.memory.load(0, &asm!("ld de,$23AF"));
 
         cpu.run();
 
@@ -78,9 +70,7 @@ mod tests {
     #[test]
     fn it_should_load_16_from_memory_to_hl() {
         let mut cpu = Cpu::new();
-        cpu.memory.set_byte(0x21, 0);
-        cpu.memory.set_byte(0xaf, 0x1);
-        cpu.memory.set_byte(0x23, 0x2);
+        cpu.memory.load(0, &asm!("ld hl,$23AF"));
 
         cpu.run();
 
@@ -90,9 +80,7 @@ mod tests {
     #[test]
     fn it_should_load_16_from_memory_to_sp() {
         let mut cpu = Cpu::new();
-        cpu.memory.set_byte(0x31, 0);
-        cpu.memory.set_byte(0xaf, 0x1);
-        cpu.memory.set_byte(0x23, 0x2);
+        cpu.memory.load(0, &asm!("ld sp,$23AF"));
 
         cpu.run();
 
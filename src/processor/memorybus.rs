@@ -1,15 +1,304 @@
 #![allow(dead_code)]
+use std::cell::RefCell;
+
+use crate::apu::sound::Apu;
+use crate::cartridge::Cartridge;
+use crate::config::SAMPLE_RATE;
+use crate::joypad::{Button, Joypad};
+use crate::model::Model;
+use crate::processor::watch::Watchpoints;
+use crate::serial::Serial;
+use crate::sgb::commands::Sgb;
+use crate::video::compatibility::{self, PaletteCombo};
+use crate::video::hdma::Hdma;
+use crate::video::ppu::Ppu;
+
+const WRAM_BANK_SIZE: usize = 0x1000;
+const WRAM_BANKS: usize = 8;
+const ROM_SIZE: usize = 0x8000;
+const INTERRUPT_FLAG: usize = 0xFF0F;
+const ADDRESS_MASK: usize = 0xFFFF;
+
+// A read or write seen on the flat bus, the single step vectors list them per cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusAccess {
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
 #[derive(Debug)]
 pub struct MemoryBus {
-    pub memory: [u8; 0xFFFF],
+    pub memory: [u8; 0x10000],
     pub pc: usize,
+    pub model: Model,
+    pub cgb_mode: bool,
+    pub ppu: Ppu,
+    pub wram: Vec<u8>,
+    pub wram_bank: usize,
+    pub hdma: Hdma,
+    pub double_speed: bool,
+    pub speed_switch_armed: bool,
+    pub stall_cycles: u32,
+    pub sgb: Sgb,
+    pub apu: Apu,
+    pub joypad: Joypad,
+    pub serial: Serial,
+    pub boot_rom: Vec<u8>,
+    // Button combo chosen for a monochrome game on CGB, None when picked by title
+    pub palette: Option<PaletteCombo>,
+    pub flat: bool,
+    pub doctor: bool,
+    pub watchpoints: Watchpoints,
+    // Only filled on the flat bus, reads take &self like the watchpoints
+    pub accesses: RefCell<Vec<BusAccess>>,
 }
 
 impl MemoryBus {
     pub fn new() -> MemoryBus {
         MemoryBus {
-            memory: [0xFC; 0xFFFF],
+            memory: [0xFC; 0x10000],
             pc: 0,
+            model: Model::Dmg,
+            cgb_mode: false,
+            ppu: Ppu::new(Model::Dmg, false),
+            wram: vec![0; WRAM_BANK_SIZE * WRAM_BANKS],
+            wram_bank: 1,
+            hdma: Hdma::new(),
+            double_speed: false,
+            speed_switch_armed: false,
+            stall_cycles: 0,
+            sgb: Sgb::new(),
+            apu: Apu::new(SAMPLE_RATE),
+            joypad: Joypad::new(),
+            serial: Serial::new(),
+            boot_rom: Vec::new(),
+            palette: None,
+            flat: false,
+            doctor: false,
+            watchpoints: Watchpoints::default(),
+            accesses: RefCell::new(Vec::new()),
+        }
+    }
+
+    // Plain 64K of RAM without hardware registers or components, for CPU test vectors
+    pub fn flat() -> MemoryBus {
+        MemoryBus {
+            flat: true,
+            ..MemoryBus::new()
+        }
+    }
+
+    pub fn set_model(&mut self, model: Model, cgb_mode: bool) {
+        self.model = model;
+        self.cgb_mode = model.is_cgb() && cgb_mode;
+        self.ppu = Ppu::new(model, self.cgb_mode);
+    }
+
+    pub fn load_cartridge(&mut self, cartridge: &Cartridge) {
+        self.load_cartridge_on(cartridge, cartridge.model());
+    }
+
+    pub fn load_cartridge_on(&mut self, cartridge: &Cartridge, model: Model) {
+        self.set_model(model, cartridge.supports_cgb());
+        let size = cartridge.rom.len().min(ROM_SIZE);
+        self.memory[..size].copy_from_slice(&cartridge.rom[..size]);
+        self.apply_compatibility_palette(cartridge, None);
+    }
+
+    // Colorizes monochrome games on CGB hardware the way the boot ROM does
+    pub fn apply_compatibility_palette(
+        &mut self,
+        cartridge: &Cartridge,
+        combo: Option<PaletteCombo>,
+    ) {
+        self.palette = combo;
+        if self.model.is_cgb() && !self.cgb_mode {
+            let palette = compatibility::select_palette(cartridge, combo);
+            self.ppu
+                .set_compatibility_palettes(&palette.bg, &palette.obj0, &palette.obj1);
+        }
+    }
+
+    pub fn read(&self, position: usize) -> u8 {
+        let value = self.peek(position);
+        if !self.watchpoints.is_empty() {
+            self.watchpoints.check(position, value, false);
+        }
+        value
+    }
+
+    // Reads without triggering watchpoints, for instruction fetches and the debugger
+    pub fn peek(&self, position: usize) -> u8 {
+        match position {
+            _ if self.flat => {
+                let value = self.memory[position];
+                self.accesses.borrow_mut().push(BusAccess {
+                    address: position as u16,
+                    value,
+                    write: false,
+                });
+                value
+            }
+            _ if self.boot_rom_mapped(position) => self.boot_rom[position],
+            // Gameboy Doctor logs are made with LY stuck at 0x90
+            0xFF44 if self.doctor => 0x90,
+            0xFF00 => self.read_joypad(),
+            0xFF01 => self.serial.data,
+            0xFF02 => self.serial.read_control(),
+            0x8000..=0x9FFF => self.ppu.read_vram(position),
+            0xFF10..=0xFF3F => self.apu.read(position),
+            0xC000..=0xCFFF => self.wram[position - 0xC000],
+            0xD000..=0xDFFF => self.wram[self.wram_bank * WRAM_BANK_SIZE + position - 0xD000],
+            0xE000..=0xFDFF => self.peek(position - 0x2000),
+            0xFE00..=0xFE9F => self.ppu.read_oam(position),
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.ppu.read_register(position),
+            0xFF4D if self.cgb_mode => self.read_speed(),
+            0xFF4F | 0xFF68..=0xFF6C if self.cgb_mode => self.ppu.read_register(position),
+            0xFF51..=0xFF54 if self.cgb_mode => 0xFF,
+            0xFF55 if self.cgb_mode => self.hdma.read_status(),
+            0xFF70 if self.cgb_mode => 0xF8 | self.wram_bank as u8,
+            _ => self.memory[position],
+        }
+    }
+
+    pub fn write(&mut self, position: usize, value: u8) {
+        if !self.watchpoints.is_empty() {
+            self.watchpoints.check(position, value, true);
+        }
+        self.write_mapped(position, value);
+    }
+
+    fn write_mapped(&mut self, position: usize, value: u8) {
+        match position {
+            _ if self.flat => {
+                self.memory[position] = value;
+                self.accesses.borrow_mut().push(BusAccess {
+                    address: position as u16,
+                    value,
+                    write: true,
+                });
+            }
+            // Any write unmaps the boot ROM until the next reset
+            0xFF50 if !self.boot_rom.is_empty() => self.boot_rom.clear(),
+            0x8000..=0x9FFF => self.ppu.write_vram(position, value),
+            0xFF10..=0xFF3F => self.apu.write(position, value),
+            0xC000..=0xCFFF => self.wram[position - 0xC000] = value,
+            0xD000..=0xDFFF => {
+                self.wram[self.wram_bank * WRAM_BANK_SIZE + position - 0xD000] = value
+            }
+            0xE000..=0xFDFF => self.write_mapped(position - 0x2000, value),
+            0xFE00..=0xFE9F => self.ppu.write_oam(position, value),
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.ppu.write_register(position, value),
+            0xFF00 => self.write_joypad(value),
+            0xFF01 => self.serial.data = value,
+            0xFF02 => self.serial.write_control(value),
+            0xFF46 => self.oam_dma(value),
+            0xFF4D if self.cgb_mode => self.speed_switch_armed = value & 0x1 == 0x1,
+            0xFF4F | 0xFF68..=0xFF6C if self.cgb_mode => self.ppu.write_register(position, value),
+            0xFF51 if self.cgb_mode => self.hdma.write_source_high(value),
+            0xFF52 if self.cgb_mode => self.hdma.write_source_low(value),
+            0xFF53 if self.cgb_mode => self.hdma.write_destination_high(value),
+            0xFF54 if self.cgb_mode => self.hdma.write_destination_low(value),
+            0xFF55 if self.cgb_mode => self.write_hdma_control(value),
+            // Bank 0 cannot be selected, writing 0 maps bank 1
+            0xFF70 if self.cgb_mode => self.wram_bank = ((value & 0x7) as usize).max(1),
+            _ => self.memory[position] = value,
+        }
+    }
+
+    // The CGB boot ROM leaves the cartridge header at 0x100-0x1FF visible
+    fn boot_rom_mapped(&self, position: usize) -> bool {
+        position < self.boot_rom.len() && !(0x100..0x200).contains(&position)
+    }
+
+    fn read_joypad(&self) -> u8 {
+        match self.sgb.read_joypad_id() {
+            Some(id) if self.model == Model::Sgb => 0xF0 | id,
+            _ => self.joypad.read(),
+        }
+    }
+
+    fn write_joypad(&mut self, value: u8) {
+        let interrupts = self.joypad.write(value);
+        self.request_interrupt(interrupts);
+        if self.model == Model::Sgb {
+            self.sgb.write_joypad(value, &self.ppu);
+        }
+    }
+
+    pub fn press(&mut self, button: Button) {
+        let interrupts = self.joypad.press(button);
+        self.request_interrupt(interrupts);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.joypad.release(button);
+    }
+
+    // SGB output is the border with the last frame inset, 256x224 pixels
+    pub fn sgb_screen(&mut self) -> &[u16] {
+        self.sgb.compose(&self.ppu.shades)
+    }
+
+    // The copy is not a CPU read, so it does not trigger watchpoints
+    fn oam_dma(&mut self, value: u8) {
+        self.memory[0xFF46] = value;
+        let source = (value as usize) << 8;
+        for offset in 0..0xA0 {
+            self.ppu.oam[offset] = self.peek(source + offset);
+        }
+    }
+
+    pub fn request_interrupt(&mut self, interrupts: u8) {
+        self.memory[INTERRUPT_FLAG] |= interrupts;
+    }
+
+    fn read_speed(&self) -> u8 {
+        let speed = if self.double_speed { 0x80 } else { 0 };
+        let armed = if self.speed_switch_armed { 0x1 } else { 0 };
+        speed | 0x7E | armed
+    }
+
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.speed_switch_armed {
+            return false;
+        }
+        self.double_speed = !self.double_speed;
+        self.speed_switch_armed = false;
+        true
+    }
+
+    // Returns the cycles elapsed, including the ones the CPU spent stalled by a DMA
+    pub fn tick(&mut self, cycles: u32) -> u32 {
+        if self.flat {
+            return cycles;
+        }
+        let mut elapsed = cycles;
+        self.tick_components(cycles);
+        while self.stall_cycles > 0 {
+            let stall = self.stall_cycles;
+            self.stall_cycles = 0;
+            self.tick_components(stall);
+            elapsed += stall;
+        }
+        elapsed
+    }
+
+    fn tick_components(&mut self, cycles: u32) {
+        // The PPU and APU keep their pace when the CPU runs at double speed
+        let dots = if self.double_speed {
+            cycles / 2
+        } else {
+            cycles
+        };
+        self.apu.tick(dots);
+        let interrupts = self.ppu.tick(dots) | self.serial.tick(cycles);
+        if interrupts != 0 {
+            self.request_interrupt(interrupts);
+        }
+        if self.ppu.take_hblank() {
+            self.hblank_dma();
         }
     }
 
@@ -17,49 +306,56 @@ impl MemoryBus {
         self.fetch_next_byte()
     }
 
+    // The address space wraps around, a word at 0xFFFF ends at 0x0000
     fn move_pc_by(&mut self, to_add: usize) {
-        let value = self.pc;
-        self.pc = value.wrapping_add(to_add);
+        self.pc = (self.pc + to_add) & ADDRESS_MASK;
     }
 
     pub fn fetch_next_word(self: &mut MemoryBus) -> u16 {
-        let low = self.memory[self.pc] as u16;
-        let high = self.memory[self.pc + 1] as u16;
+        let low = self.peek(self.pc) as u16;
+        let high = self.peek((self.pc + 1) & ADDRESS_MASK) as u16;
         self.move_pc_by(2);
         (high << 8) | low
     }
 
     pub fn fetch_word_at(self: &mut MemoryBus, position: usize) -> u16 {
-        let low = self.memory[position] as u16;
-        let high = self.memory[position + 1] as u16;
+        let low = self.read(position) as u16;
+        let high = self.read((position + 1) & ADDRESS_MASK) as u16;
         (high << 8) | low
     }
 
     pub fn set_word(self: &mut MemoryBus, value: u16) {
         let value_1: u8 = (value >> 8) as u8;
         let value_2: u8 = (value & 0xFF) as u8;
-        self.memory[self.pc] = value_1;
-        self.memory[self.pc + 1] = value_2;
+        self.write(self.pc, value_1);
+        self.write((self.pc + 1) & ADDRESS_MASK, value_2);
     }
 
     pub fn fetch_byte_at(self: &mut MemoryBus, position: usize) -> u8 {
-        self.memory[position]
+        self.read(position)
     }
 
     pub fn fetch_next_byte(self: &mut MemoryBus) -> u8 {
         let position = self.pc;
         self.move_pc_by(1);
-        self.memory[position]
+        self.peek(position)
     }
 
     pub fn set_byte(self: &mut MemoryBus, value: u8, position: usize) {
-        self.memory[position] = value;
+        self.write(position, value);
+    }
+
+    pub fn load(&mut self, address: u16, bytes: &[u8]) {
+        for (offset, byte) in bytes.iter().enumerate() {
+            self.write(address as usize + offset, *byte);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::processor::watch::{Access, Watchpoint};
 
     #[test]
     fn it_fetch_next_word_from_memory() {
@@ -111,4 +407,182 @@ mod tests {
 
         assert_eq!(memory.memory[0xFF], 0xf8);
     }
+
+    #[test]
+    fn it_should_switch_wram_bank_in_cgb_mode() {
+        let mut memory = MemoryBus::new();
+        memory.set_model(Model::Cgb, true);
+        memory.set_byte(0x11, 0xD000);
+        memory.set_byte(0x03, 0xFF70);
+        memory.set_byte(0x33, 0xD000);
+
+        assert_eq!(memory.fetch_byte_at(0xD000), 0x33);
+        assert_eq!(memory.fetch_byte_at(0xFF70), 0xFB);
+        memory.set_byte(0x00, 0xFF70);
+        assert_eq!(memory.fetch_byte_at(0xD000), 0x11);
+    }
+
+    #[test]
+    fn it_should_ignore_svbk_in_dmg_mode() {
+        let mut memory = MemoryBus::new();
+        memory.set_byte(0x11, 0xD000);
+        memory.set_byte(0x03, 0xFF70);
+
+        assert_eq!(memory.fetch_byte_at(0xD000), 0x11);
+    }
+
+    #[test]
+    fn it_should_mirror_wram_in_echo_ram() {
+        let mut memory = MemoryBus::new();
+        memory.set_byte(0x42, 0xC010);
+
+        assert_eq!(memory.fetch_byte_at(0xE010), 0x42);
+    }
+
+    #[test]
+    fn it_should_write_palette_data_through_bcps_and_bcpd() {
+        let mut memory = MemoryBus::new();
+        memory.set_model(Model::Cgb, true);
+        memory.set_byte(0x80, 0xFF68);
+        memory.set_byte(0xFF, 0xFF69);
+        memory.set_byte(0x7F, 0xFF69);
+
+        assert_eq!(memory.fetch_byte_at(0xFF68), 0xC2);
+        assert_eq!(memory.ppu.bg_palette.color(0, 0), 0x7FFF);
+    }
+
+    #[test]
+    fn it_should_force_compatibility_palette_for_dmg_games_on_cgb() {
+        let cartridge = Cartridge::new(vec![0; 0x8000]);
+        let mut memory = MemoryBus::new();
+        memory.set_model(Model::Cgb, false);
+        memory.apply_compatibility_palette(&cartridge, Some(PaletteCombo::RightB));
+
+        assert_eq!(memory.ppu.bg_palette.color(0, 0), 0x0000);
+        assert_eq!(memory.ppu.bg_palette.color(0, 3), 0x7FFF);
+    }
+
+    #[test]
+    fn it_should_receive_sgb_packets_through_p1() {
+        let mut memory = MemoryBus::new();
+        memory.set_model(Model::Sgb, false);
+        let mut packet = [0u8; 16];
+        packet[0] = (0x11 << 3) | 1;
+        packet[1] = 0x01;
+        memory.set_byte(0x00, 0xFF00);
+        memory.set_byte(0x30, 0xFF00);
+        for byte in packet {
+            for bit in 0..8 {
+                let line = if byte & (1 << bit) != 0 { 0x10 } else { 0x20 };
+                memory.set_byte(line, 0xFF00);
+                memory.set_byte(0x30, 0xFF00);
+            }
+        }
+        memory.set_byte(0x20, 0xFF00);
+        memory.set_byte(0x30, 0xFF00);
+
+        assert_eq!(memory.sgb.players, 2);
+        assert_eq!(memory.fetch_byte_at(0xFF00), 0xFF);
+    }
+
+    #[test]
+    fn it_should_pick_model_from_cartridge() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = 0x80;
+        let mut memory = MemoryBus::new();
+        memory.load_cartridge(&Cartridge::new(rom));
+
+        assert_eq!(memory.model, Model::Cgb);
+        assert!(memory.cgb_mode);
+    }
+
+    #[test]
+    fn it_should_request_joypad_interrupt_on_press() {
+        let mut memory = MemoryBus::new();
+        memory.set_byte(0x10, 0xFF00);
+        memory.press(Button::Start);
+
+        assert_eq!(memory.fetch_byte_at(0xFF00), 0xD7);
+        assert_eq!(memory.fetch_byte_at(INTERRUPT_FLAG) & 0x10, 0x10);
+    }
+
+    #[test]
+    fn it_should_clock_the_apu_at_normal_speed() {
+        let mut memory = MemoryBus::new();
+        // Channel 1 with one length step left, which runs out at the next length clock
+        memory.write(0xFF12, 0xF0);
+        memory.write(0xFF11, 0x3F);
+        memory.write(0xFF14, 0xC0);
+        assert_eq!(memory.read(0xFF26) & 0x01, 0x01);
+
+        memory.double_speed = true;
+        memory.tick(8192);
+        assert_eq!(memory.read(0xFF26) & 0x01, 0x01);
+        memory.double_speed = false;
+        memory.tick(8192);
+        assert_eq!(memory.read(0xFF26) & 0x01, 0x00);
+    }
+
+    #[test]
+    fn it_should_store_the_interrupt_enable_register_at_ffff() {
+        let mut memory = MemoryBus::new();
+        memory.write(0xFFFF, 0x1F);
+
+        assert_eq!(memory.read(0xFFFF), 0x1F);
+        assert_eq!(memory.memory.len(), 0x10000);
+    }
+
+    #[test]
+    fn it_should_wrap_words_around_the_address_space() {
+        let mut memory = MemoryBus::flat();
+        memory.memory[0xFFFF] = 0x34;
+        memory.memory[0x0000] = 0x12;
+        memory.pc = 0xFFFF;
+
+        assert_eq!(memory.fetch_next_word(), 0x1234);
+        assert_eq!(memory.pc, 0x0001);
+        assert_eq!(memory.fetch_word_at(0xFFFF), 0x1234);
+        memory.pc = 0xFFFF;
+        assert_eq!(memory.fetch_next_byte(), 0x34);
+        assert_eq!(memory.pc, 0x0000);
+    }
+
+    #[test]
+    fn it_should_record_accesses_on_the_flat_bus() {
+        let mut memory = MemoryBus::flat();
+        memory.write(0xC000, 0x42);
+        memory.read(0xC000);
+
+        assert_eq!(
+            memory.accesses.take(),
+            vec![
+                BusAccess {
+                    address: 0xC000,
+                    value: 0x42,
+                    write: true
+                },
+                BusAccess {
+                    address: 0xC000,
+                    value: 0x42,
+                    write: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_copy_oam_without_triggering_watchpoints() {
+        let mut memory = MemoryBus::new();
+        memory.write(0xC001, 0x42);
+        memory.watchpoints.list.push(Watchpoint {
+            id: 1,
+            start: 0xC000,
+            end: 0xC09F,
+            access: Access::Read,
+        });
+        memory.write(0xFF46, 0xC0);
+
+        assert_eq!(memory.ppu.oam[1], 0x42);
+        assert_eq!(memory.watchpoints.take_hit(), None);
+    }
 }
@@ -0,0 +1,107 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::processor::memorybus::MemoryBus;
+use crate::processor::registers::Registers;
+
+// Writes the CPU state before every instruction, one line per instruction
+pub struct Tracer {
+    output: Box<dyn Write>,
+    pub error: Option<io::Error>,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.debug_struct("Tracer").finish_non_exhaustive()
+    }
+}
+
+impl Tracer {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Tracer> {
+        Ok(Tracer::new(Box::new(BufWriter::new(File::create(path)?))))
+    }
+
+    pub fn new(output: Box<dyn Write>) -> Tracer {
+        Tracer {
+            output,
+            error: None,
+        }
+    }
+
+    // Stops writing after the first error, it is reported by finish
+    pub fn record(&mut self, registers: &Registers, memory: &MemoryBus) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.output, "{}", trace_line(registers, memory)) {
+                self.error = Some(error);
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.output.flush(),
+        }
+    }
+}
+
+pub fn trace_line(registers: &Registers, memory: &MemoryBus) -> String {
+    let pc = memory.pc;
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        registers.a,
+        registers.f.f,
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+        registers.sp,
+        pc,
+        memory.peek(pc),
+        memory.peek((pc + 1) & 0xFFFF),
+        memory.peek((pc + 2) & 0xFFFF),
+        memory.peek((pc + 3) & 0xFFFF),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::watch::{Access, Watchpoint};
+
+    #[test]
+    fn it_should_format_registers_and_next_bytes() {
+        let mut registers = Registers::new();
+        registers.set_af(0x01B0);
+        registers.set_sp(0xFFFE);
+        let mut memory = MemoryBus::new();
+        memory.pc = 0x100;
+        memory.set_byte(0x00, 0x100);
+        memory.set_byte(0xC3, 0x101);
+
+        assert_eq!(
+            trace_line(&registers, &memory),
+            "A:01 F:B0 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0100 PCMEM:00,C3,FC,FC"
+        );
+    }
+
+    #[test]
+    fn it_should_not_trigger_watchpoints() {
+        let mut memory = MemoryBus::new();
+        memory.pc = 0xC000;
+        memory.watchpoints.list.push(Watchpoint {
+            id: 1,
+            start: 0xC000,
+            end: 0xC003,
+            access: Access::Read,
+        });
+        trace_line(&Registers::new(), &memory);
+
+        assert_eq!(memory.watchpoints.take_hit(), None);
+    }
+}
@@ -0,0 +1,113 @@
+#![allow(dead_code)]
+use std::cell::Cell;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn includes(&self, write: bool) -> bool {
+        match self {
+            Access::Read => !write,
+            Access::Write => write,
+            Access::ReadWrite => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub start: u16,
+    pub end: u16,
+    pub access: Access,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit {
+    pub id: usize,
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+// Checked by the bus on CPU reads and writes, reads take &self so the hit is kept in a Cell
+#[derive(Debug, Default)]
+pub struct Watchpoints {
+    pub list: Vec<Watchpoint>,
+    hit: Cell<Option<WatchHit>>,
+}
+
+impl Watchpoints {
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    // Only the first access is kept until the debugger takes it
+    pub fn check(&self, address: usize, value: u8, write: bool) {
+        if self.hit.get().is_some() {
+            return;
+        }
+        let address = address as u16;
+        let watchpoint = self.list.iter().find(|watchpoint| {
+            (watchpoint.start..=watchpoint.end).contains(&address)
+                && watchpoint.access.includes(write)
+        });
+        if let Some(watchpoint) = watchpoint {
+            self.hit.set(Some(WatchHit {
+                id: watchpoint.id,
+                address,
+                value,
+                write,
+            }));
+        }
+    }
+
+    pub fn take_hit(&self) -> Option<WatchHit> {
+        self.hit.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watchpoints(access: Access) -> Watchpoints {
+        Watchpoints {
+            list: vec![Watchpoint {
+                id: 1,
+                start: 0xC000,
+                end: 0xC00F,
+                access,
+            }],
+            ..Watchpoints::default()
+        }
+    }
+
+    #[test]
+    fn it_should_record_accesses_in_range() {
+        let watchpoints = watchpoints(Access::ReadWrite);
+        watchpoints.check(0xBFFF, 1, true);
+        assert_eq!(watchpoints.take_hit(), None);
+
+        watchpoints.check(0xC00F, 2, true);
+        watchpoints.check(0xC000, 3, false);
+        let hit = watchpoints.take_hit().unwrap();
+
+        assert_eq!((hit.address, hit.value, hit.write), (0xC00F, 2, true));
+        assert_eq!(watchpoints.take_hit(), None);
+    }
+
+    #[test]
+    fn it_should_filter_by_access() {
+        let watchpoints = watchpoints(Access::Write);
+        watchpoints.check(0xC000, 1, false);
+        assert_eq!(watchpoints.take_hit(), None);
+
+        watchpoints.check(0xC000, 1, true);
+        assert!(watchpoints.take_hit().is_some());
+    }
+}
@@ -0,0 +1,202 @@
+#![allow(dead_code)]
+use std::collections::VecDeque;
+
+use crate::processor::cpu::Cpu;
+use crate::rewind::delta::{apply, encode};
+use crate::savestate::{restore_snapshot, snapshot};
+
+pub mod delta;
+
+const MEGABYTE: usize = 1024 * 1024;
+
+// The newest snapshot is kept whole, every older one as the delta that turns the
+// snapshot after it back into it; the oldest are dropped to stay under the memory cap
+#[derive(Debug)]
+pub struct Rewind {
+    pub capacity: usize,
+    pub interval: u32,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    size: usize,
+    frames: u32,
+}
+
+impl Rewind {
+    pub fn new(megabytes: usize, interval: u32) -> Rewind {
+        Rewind {
+            capacity: megabytes * MEGABYTE,
+            interval: interval.max(1),
+            newest: None,
+            deltas: VecDeque::new(),
+            size: 0,
+            frames: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    // Bytes used by the snapshots
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Called after every frame, takes a snapshot every interval frames
+    pub fn record(&mut self, cpu: &Cpu) {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            self.push(snapshot(cpu));
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.newest.take() {
+            let delta = encode(&state, &previous);
+            self.size = self.size - previous.len() + delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.size += state.len();
+        self.newest = Some(state);
+        while self.size > self.capacity && !self.deltas.is_empty() {
+            if let Some(oldest) = self.deltas.pop_front() {
+                self.size -= oldest.len();
+            }
+        }
+    }
+
+    // Takes the newest snapshot out, the one before it becomes the newest
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        self.size -= newest.len();
+        if let Some(delta) = self.deltas.pop_back() {
+            self.size -= delta.len();
+            // A damaged delta ends the history rather than restoring garbage
+            match apply(&newest, &delta) {
+                Some(previous) => {
+                    self.size += previous.len();
+                    self.newest = Some(previous);
+                }
+                None => self.clear(),
+            }
+        }
+        Some(newest)
+    }
+
+    // Restores the previous snapshot, false once the history is exhausted. The restored
+    // snapshot stays the newest so recording picks up from it. The APU is not ticked
+    // while rewinding so no audio is produced
+    pub fn step_back(&mut self, cpu: &mut Cpu) -> bool {
+        // A snapshot taken this frame is the current state, going back starts before it
+        if self.frames == 0 {
+            self.pop();
+        }
+        self.frames = 0;
+        match &self.newest {
+            Some(state) => restore_snapshot(cpu, state).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.size = 0;
+        self.frames = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::run_frame;
+
+    fn halted_cpu() -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0x0000, &asm!("halt"));
+        cpu
+    }
+
+    // Numbers each frame in C000 so the tests can tell which one was restored
+    fn run_frames(cpu: &mut Cpu, rewind: &mut Rewind, frames: u8) {
+        for _ in 0..frames {
+            let count = frame_count(cpu);
+            cpu.memory.set_byte(count + 1, 0xC000);
+            run_frame(cpu);
+            rewind.record(cpu);
+        }
+    }
+
+    fn frame_count(cpu: &Cpu) -> u8 {
+        cpu.memory.peek(0xC000)
+    }
+
+    #[test]
+    fn it_should_rewind_frame_by_frame() {
+        let mut cpu = halted_cpu();
+        let mut rewind = Rewind::new(8, 1);
+        run_frames(&mut cpu, &mut rewind, 10);
+
+        assert!(rewind.step_back(&mut cpu));
+        assert_eq!(frame_count(&cpu), 9);
+        assert!(rewind.step_back(&mut cpu));
+        assert_eq!(frame_count(&cpu), 8);
+        assert_eq!(rewind.len(), 8);
+    }
+
+    #[test]
+    fn it_should_continue_recording_after_rewinding() {
+        let mut cpu = halted_cpu();
+        let mut rewind = Rewind::new(8, 1);
+        run_frames(&mut cpu, &mut rewind, 5);
+        rewind.step_back(&mut cpu);
+        run_frames(&mut cpu, &mut rewind, 2);
+
+        assert_eq!(frame_count(&cpu), 6);
+        assert!(rewind.step_back(&mut cpu));
+        assert_eq!(frame_count(&cpu), 5);
+        assert!(rewind.step_back(&mut cpu));
+        assert_eq!(frame_count(&cpu), 4);
+        assert!(rewind.step_back(&mut cpu));
+        assert_eq!(frame_count(&cpu), 3);
+    }
+
+    #[test]
+    fn it_should_snapshot_every_interval() {
+        let mut cpu = halted_cpu();
+        let mut rewind = Rewind::new(8, 4);
+        run_frames(&mut cpu, &mut rewind, 12);
+
+        assert_eq!(rewind.len(), 3);
+        assert!(rewind.step_back(&mut cpu));
+        assert_eq!(frame_count(&cpu), 8);
+    }
+
+    #[test]
+    fn it_should_stay_under_the_memory_cap() {
+        let mut rewind = Rewind::new(1, 1);
+        for value in 0..64u8 {
+            rewind.push(vec![value; 64 * 1024]);
+        }
+
+        assert!(rewind.size() <= MEGABYTE);
+        assert_eq!(rewind.pop(), Some(vec![63; 64 * 1024]));
+        assert_eq!(rewind.pop(), Some(vec![62; 64 * 1024]));
+    }
+
+    #[test]
+    fn it_should_stop_when_history_runs_out() {
+        let mut cpu = halted_cpu();
+        let mut rewind = Rewind::new(8, 1);
+        run_frames(&mut cpu, &mut rewind, 2);
+
+        assert!(rewind.step_back(&mut cpu));
+        assert!(!rewind.step_back(&mut cpu));
+        assert!(rewind.is_empty());
+    }
+}
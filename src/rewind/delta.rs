@@ -0,0 +1,122 @@
+#![allow(dead_code)]
+
+// Encodes target as its XOR with base, as runs of unchanged bytes followed by literal
+// XOR bytes; consecutive frames differ in a few places so the runs are long
+pub fn encode(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_number(&mut delta, target.len());
+    let xor: Vec<u8> = target
+        .iter()
+        .enumerate()
+        .map(|(position, byte)| byte ^ base.get(position).copied().unwrap_or(0))
+        .collect();
+    let mut position = 0;
+    while position < xor.len() {
+        let unchanged = xor[position..]
+            .iter()
+            .take_while(|byte| **byte == 0)
+            .count();
+        position += unchanged;
+        let changed = xor[position..]
+            .iter()
+            .take_while(|byte| **byte != 0)
+            .count();
+        write_number(&mut delta, unchanged);
+        write_number(&mut delta, changed);
+        delta.extend_from_slice(&xor[position..position + changed]);
+        position += changed;
+    }
+    delta
+}
+
+// Rebuilds the target from the base it was encoded against
+pub fn apply(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut position = 0;
+    let length = read_number(delta, &mut position)?;
+    let mut target: Vec<u8> = (0..length)
+        .map(|offset| base.get(offset).copied().unwrap_or(0))
+        .collect();
+    let mut offset = 0;
+    while position < delta.len() {
+        offset += read_number(delta, &mut position)?;
+        let changed = read_number(delta, &mut position)?;
+        let bytes = delta.get(position..position.checked_add(changed)?)?;
+        let targets = target.get_mut(offset..offset.checked_add(changed)?)?;
+        for (byte, xor) in targets.iter_mut().zip(bytes) {
+            *byte ^= xor;
+        }
+        position += changed;
+        offset += changed;
+    }
+    Some(target)
+}
+
+// LEB128, 7 bits per byte with the high bit set on all but the last
+fn write_number(output: &mut Vec<u8>, mut number: usize) {
+    while number >= 0x80 {
+        output.push(number as u8 | 0x80);
+        number >>= 7;
+    }
+    output.push(number as u8);
+}
+
+fn read_number(input: &[u8], position: &mut usize) -> Option<usize> {
+    let mut number = 0usize;
+    for shift in (0..usize::BITS).step_by(7) {
+        let byte = *input.get(*position)?;
+        *position += 1;
+        number |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(number);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_rebuild_the_target() {
+        let base = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let target = vec![1, 2, 9, 4, 5, 6, 0, 8];
+        let delta = encode(&base, &target);
+
+        assert_eq!(apply(&base, &delta), Some(target));
+    }
+
+    #[test]
+    fn it_should_handle_different_lengths() {
+        let short = vec![1, 2, 3];
+        let long = vec![1, 2, 3, 4, 5];
+
+        assert_eq!(apply(&short, &encode(&short, &long)), Some(long.clone()));
+        assert_eq!(apply(&long, &encode(&long, &short)), Some(short));
+    }
+
+    #[test]
+    fn it_should_shrink_mostly_unchanged_data() {
+        let base = vec![0x55; 100_000];
+        let mut target = base.clone();
+        target[50_000] = 0;
+
+        assert!(encode(&base, &target).len() < 16);
+    }
+
+    #[test]
+    fn it_should_reject_a_damaged_delta() {
+        assert_eq!(apply(&[0; 4], &[4, 2, 8, 1]), None);
+        assert_eq!(apply(&[0; 4], &[0x80]), None);
+    }
+
+    #[test]
+    fn it_should_encode_numbers_in_leb128() {
+        let mut output = Vec::new();
+        write_number(&mut output, 300);
+        let mut position = 0;
+
+        assert_eq!(output, vec![0xAC, 0x02]);
+        assert_eq!(read_number(&output, &mut position), Some(300));
+    }
+}
@@ -0,0 +1,425 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::frontend::capture;
+use crate::processor::cpu::Cpu;
+use crate::savestate::codec::{crc32, Reader, Writer};
+use crate::savestate::components::Persist;
+use crate::video::screenshot::Image;
+
+pub mod codec;
+pub mod components;
+
+const MAGIC: &[u8; 8] = b"GBSTATE\x1A";
+// Bumped whenever the layout of a component changes
+pub const VERSION: u16 = 1;
+pub const SLOTS: u8 = 10;
+
+const TITLE_START: usize = 0x134;
+const TITLE_SIZE: usize = 16;
+const THUMBNAIL_DIVISOR: usize = 2;
+// The SGB screen with its border is the largest one
+const MAX_THUMBNAIL_PIXELS: u32 = 256 * 224;
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(io::Error),
+    NotAState,
+    Version(u16),
+    Cartridge(String),
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Io(error) => write!(formatter, "{}", error),
+            StateError::NotAState => formatter.write_str("not a save state"),
+            StateError::Version(version) => write!(
+                formatter,
+                "save state version {} is not supported, this build reads version {}",
+                version, VERSION
+            ),
+            StateError::Cartridge(title) => {
+                write!(formatter, "save state was made with '{}'", title)
+            }
+            StateError::Corrupt => formatter.write_str("save state is damaged"),
+        }
+    }
+}
+
+impl From<io::Error> for StateError {
+    fn from(error: io::Error) -> StateError {
+        StateError::Io(error)
+    }
+}
+
+// The header and thumbnail come first so a slot can be previewed without restoring it:
+// magic, version, cartridge title, thumbnail, then the checksummed machine state
+// There is no mapper, timer or RTC emulation yet, so none of them has state of its own;
+// the timer registers are only saved as bytes of the flat memory array
+#[derive(Debug, Clone)]
+pub struct SaveState {
+    pub title: [u8; TITLE_SIZE],
+    pub thumbnail: Image,
+    payload: Vec<u8>,
+}
+
+impl SaveState {
+    pub fn capture(cpu: &mut Cpu) -> SaveState {
+        SaveState {
+            title: cartridge_title(cpu),
+            thumbnail: shrink(&capture(cpu), THUMBNAIL_DIVISOR),
+            payload: snapshot(cpu),
+        }
+    }
+
+    // Fails before touching the CPU when the state is for another game or damaged
+    pub fn restore(&self, cpu: &mut Cpu) -> Result<(), StateError> {
+        if self.title != cartridge_title(cpu) {
+            return Err(StateError::Cartridge(self.title_text()));
+        }
+        restore_snapshot(cpu, &self.payload)
+    }
+
+    pub fn title_text(&self) -> String {
+        self.title
+            .iter()
+            .take_while(|byte| **byte != 0)
+            .map(|byte| *byte as char)
+            .collect()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.raw(MAGIC);
+        writer.u16(VERSION);
+        writer.raw(&self.title);
+        writer.u16(self.thumbnail.width as u16);
+        writer.u16(self.thumbnail.height as u16);
+        writer.raw(&self.thumbnail.pixels);
+        writer.u32(crc32(&self.payload));
+        writer.bytes(&self.payload);
+        writer.bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<SaveState, StateError> {
+        let mut reader = Reader::new(bytes);
+        let mut magic = [0; MAGIC.len()];
+        reader.raw(&mut magic).map_err(|_| StateError::NotAState)?;
+        if &magic != MAGIC {
+            return Err(StateError::NotAState);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(StateError::Version(version));
+        }
+        let mut title = [0; TITLE_SIZE];
+        reader.raw(&mut title)?;
+        let width = reader.u16()? as u32;
+        let height = reader.u16()? as u32;
+        if width * height > MAX_THUMBNAIL_PIXELS {
+            return Err(StateError::Corrupt);
+        }
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        reader.raw(&mut pixels)?;
+        let checksum = reader.u32()?;
+        let payload = reader.bytes()?;
+        if crc32(&payload) != checksum || !reader.is_at_end() {
+            return Err(StateError::Corrupt);
+        }
+        Ok(SaveState {
+            title,
+            thumbnail: Image {
+                width,
+                height,
+                pixels,
+            },
+            payload,
+        })
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<SaveState, StateError> {
+        SaveState::decode(&fs::read(path)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.encode())
+    }
+}
+
+// Numbered slots named after the ROM, game.gb keeps slot 3 in game.ss3
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slots {
+    pub directory: PathBuf,
+    pub name: String,
+    pub slot: u8,
+}
+
+impl Slots {
+    pub fn new(directory: &Path, rom: &Path) -> Slots {
+        let name = rom
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "game".to_string());
+        Slots {
+            directory: directory.to_path_buf(),
+            name,
+            slot: 0,
+        }
+    }
+
+    pub fn path(&self, slot: u8) -> PathBuf {
+        self.directory.join(format!("{}.ss{}", self.name, slot))
+    }
+
+    pub fn save(&self, cpu: &mut Cpu, slot: u8) -> Result<PathBuf, StateError> {
+        let path = self.path(slot);
+        SaveState::capture(cpu).write(&path)?;
+        Ok(path)
+    }
+
+    pub fn load(&self, cpu: &mut Cpu, slot: u8) -> Result<PathBuf, StateError> {
+        let path = self.path(slot);
+        SaveState::read(&path)?.restore(cpu)?;
+        Ok(path)
+    }
+
+    pub fn thumbnail(&self, slot: u8) -> Result<Image, StateError> {
+        Ok(SaveState::read(self.path(slot))?.thumbnail)
+    }
+}
+
+// The machine state alone, without header or thumbnail, for snapshots kept in memory
+pub fn snapshot(cpu: &Cpu) -> Vec<u8> {
+    let mut writer = Writer::new();
+    cpu.save_state(&mut writer);
+    writer.bytes
+}
+
+// Either the whole snapshot is loaded or the machine is left as it was. Loading stops
+// at the first bad field, so the current state is kept aside and put back on failure
+pub fn restore_snapshot(cpu: &mut Cpu, bytes: &[u8]) -> Result<(), StateError> {
+    let backup = snapshot(cpu);
+    let result = load_snapshot(cpu, bytes);
+    if result.is_err() {
+        load_snapshot(cpu, &backup).expect("a snapshot of the running machine loads back");
+    }
+    result
+}
+
+fn load_snapshot(cpu: &mut Cpu, bytes: &[u8]) -> Result<(), StateError> {
+    let mut reader = Reader::new(bytes);
+    cpu.load_state(&mut reader)?;
+    if !reader.is_at_end() {
+        return Err(StateError::Corrupt);
+    }
+    Ok(())
+}
+
+fn cartridge_title(cpu: &Cpu) -> [u8; TITLE_SIZE] {
+    let mut title = [0; TITLE_SIZE];
+    for (offset, byte) in title.iter_mut().enumerate() {
+        *byte = cpu.memory.peek(TITLE_START + offset);
+    }
+    title
+}
+
+// Keeps the top-left pixel of every divisor x divisor square
+fn shrink(image: &Image, divisor: usize) -> Image {
+    let width = image.width as usize / divisor;
+    let height = image.height as usize / divisor;
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for x in 0..width {
+            let start = ((y * divisor) * image.width as usize + x * divisor) * 4;
+            pixels.extend_from_slice(&image.pixels[start..start + 4]);
+        }
+    }
+    Image {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::frontend::run_frame;
+    use crate::model::Model;
+
+    fn running_cpu(model: Model) -> Cpu {
+        let mut rom = vec![0; 0x8000];
+        rom[TITLE_START..TITLE_START + 4].copy_from_slice(b"TEST");
+        // The PPU and APU keep running while the CPU is halted
+        rom[0x100] = asm!("halt")[0];
+        let mut cpu = Cpu::from_cartridge_on(&Cartridge::new(rom), model);
+        cpu.memory.set_byte(0x80, 0xFF26);
+        cpu.memory.set_byte(0xF0, 0xFF12);
+        cpu.memory.set_byte(0x87, 0xFF14);
+        cpu.memory.set_byte(0x42, 0xC123);
+        cpu
+    }
+
+    #[test]
+    fn it_should_restore_the_machine_it_captured() {
+        let mut cpu = running_cpu(Model::Cgb);
+        run_frame(&mut cpu);
+        let state = SaveState::capture(&mut cpu);
+        let expected = snapshot(&cpu);
+
+        for _ in 0..3 {
+            run_frame(&mut cpu);
+        }
+        cpu.memory.set_byte(0x00, 0xC123);
+        state.restore(&mut cpu).unwrap();
+
+        assert_eq!(snapshot(&cpu), expected);
+        assert_eq!(cpu.memory.fetch_byte_at(0xC123), 0x42);
+    }
+
+    #[test]
+    fn it_should_run_the_same_after_a_restore() {
+        let mut cpu = running_cpu(Model::Dmg);
+        run_frame(&mut cpu);
+        let state = SaveState::decode(&SaveState::capture(&mut cpu).encode()).unwrap();
+        run_frame(&mut cpu);
+        let expected = snapshot(&cpu);
+
+        let mut other = running_cpu(Model::Dmg);
+        state.restore(&mut other).unwrap();
+        run_frame(&mut other);
+
+        assert_eq!(snapshot(&other), expected);
+    }
+
+    #[test]
+    fn it_should_carry_a_half_size_thumbnail() {
+        let mut cpu = running_cpu(Model::Dmg);
+        let state = SaveState::decode(&SaveState::capture(&mut cpu).encode()).unwrap();
+
+        assert_eq!((state.thumbnail.width, state.thumbnail.height), (80, 72));
+        assert_eq!(state.thumbnail.pixels.len(), 80 * 72 * 4);
+        assert_eq!(state.title_text(), "TEST");
+    }
+
+    #[test]
+    fn it_should_reject_another_version() {
+        let mut bytes = SaveState::capture(&mut running_cpu(Model::Dmg)).encode();
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+
+        let error = SaveState::decode(&bytes).unwrap_err();
+        assert!(matches!(error, StateError::Version(version) if version == VERSION + 1));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "save state version {} is not supported, this build reads version {}",
+                VERSION + 1,
+                VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_reject_damaged_and_foreign_files() {
+        let mut bytes = SaveState::capture(&mut running_cpu(Model::Dmg)).encode();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+
+        assert!(matches!(
+            SaveState::decode(&bytes),
+            Err(StateError::Corrupt)
+        ));
+        assert!(matches!(
+            SaveState::decode(&bytes[..100]),
+            Err(StateError::Corrupt)
+        ));
+        assert!(matches!(
+            SaveState::decode(b"PNG"),
+            Err(StateError::NotAState)
+        ));
+    }
+
+    #[test]
+    fn it_should_refuse_a_state_from_another_game() {
+        let state = SaveState::capture(&mut running_cpu(Model::Dmg));
+        let mut cpu = Cpu::new();
+        let before = snapshot(&cpu);
+
+        assert!(matches!(
+            state.restore(&mut cpu),
+            Err(StateError::Cartridge(title)) if title == "TEST"
+        ));
+        assert_eq!(snapshot(&cpu), before);
+    }
+
+    #[test]
+    fn it_should_leave_the_machine_untouched_by_a_bad_payload() {
+        let mut cpu = running_cpu(Model::Cgb);
+        run_frame(&mut cpu);
+        let before = snapshot(&cpu);
+        let mut other = running_cpu(Model::Cgb);
+        other.memory.set_byte(0x00, 0xC123);
+        let good = snapshot(&other);
+
+        // The start of a valid payload is loaded before the reader runs dry
+        let truncated = restore_snapshot(&mut cpu, &good[..good.len() / 2]);
+        let mut trailing = good.clone();
+        trailing.push(0);
+        let overlong = restore_snapshot(&mut cpu, &trailing);
+
+        assert!(matches!(truncated, Err(StateError::Corrupt)));
+        assert!(matches!(overlong, Err(StateError::Corrupt)));
+        assert_eq!(snapshot(&cpu), before);
+        assert_eq!(cpu.memory.fetch_byte_at(0xC123), 0x42);
+    }
+
+    #[test]
+    fn it_should_reject_an_out_of_range_bank() {
+        let mut cpu = running_cpu(Model::Cgb);
+        let before = snapshot(&cpu);
+        // Registers, the halt flag, the flat memory, pc, model, CGB mode and WRAM
+        let offset = 8 + 4 + 4 + 1 + cpu.memory.memory.len() + 4 + 1 + 1 + 4;
+        let bank = offset + cpu.memory.wram.len();
+        let mut bytes = before.clone();
+        assert_eq!(bytes[bank], cpu.memory.wram_bank as u8);
+        bytes[bank] = 8;
+
+        assert!(matches!(
+            restore_snapshot(&mut cpu, &bytes),
+            Err(StateError::Corrupt)
+        ));
+        assert_eq!(snapshot(&cpu), before);
+    }
+
+    #[test]
+    fn it_should_name_slots_after_the_rom() {
+        let slots = Slots::new(Path::new("saves"), Path::new("roms/tetris.gb"));
+
+        assert_eq!(slots.path(3), Path::new("saves").join("tetris.ss3"));
+    }
+
+    #[test]
+    fn it_should_save_and_load_slots() {
+        let directory = std::env::temp_dir().join(format!("states-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let slots = Slots::new(&directory, Path::new("test.gb"));
+        let mut cpu = running_cpu(Model::Dmg);
+
+        slots.save(&mut cpu, 1).unwrap();
+        cpu.memory.set_byte(0x00, 0xC123);
+        slots.load(&mut cpu, 1).unwrap();
+        let thumbnail = slots.thumbnail(1).unwrap();
+        let missing = slots.load(&mut cpu, 2);
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(cpu.memory.fetch_byte_at(0xC123), 0x42);
+        assert_eq!(thumbnail.width, 80);
+        assert!(matches!(missing, Err(StateError::Io(_))));
+    }
+}
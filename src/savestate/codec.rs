@@ -0,0 +1,205 @@
+#![allow(dead_code)]
+use crate::savestate::StateError;
+
+// Little-endian fields written one after the other, slices carry their length
+#[derive(Debug, Default)]
+pub struct Writer {
+    pub bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer::default()
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // Fixed-size data whose length the reader already knows
+    pub fn raw(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.raw(bytes);
+    }
+
+    pub fn words(&mut self, words: &[u16]) {
+        self.u32(words.len() as u32);
+        for word in words {
+            self.u16(*word);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, position: 0 }
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], StateError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(StateError::Corrupt)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, StateError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    pub fn raw(&mut self, target: &mut [u8]) -> Result<(), StateError> {
+        target.copy_from_slice(self.take(target.len())?);
+        Ok(())
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, StateError> {
+        let length = self.u32()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    pub fn words(&mut self) -> Result<Vec<u16>, StateError> {
+        let length = self.u32()? as usize;
+        let bytes = self.take(length.checked_mul(2).ok_or(StateError::Corrupt)?)?;
+        Ok(bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect())
+    }
+
+    // Vectors sized by the hardware must come back with the same length
+    pub fn bytes_into(&mut self, target: &mut Vec<u8>) -> Result<(), StateError> {
+        let bytes = self.bytes()?;
+        if bytes.len() != target.len() {
+            return Err(StateError::Corrupt);
+        }
+        *target = bytes;
+        Ok(())
+    }
+
+    pub fn words_into(&mut self, target: &mut Vec<u16>) -> Result<(), StateError> {
+        let words = self.words()?;
+        if words.len() != target.len() {
+            return Err(StateError::Corrupt);
+        }
+        *target = words;
+        Ok(())
+    }
+}
+
+// CRC-32 as used by PNG and zip, catches truncated and damaged files
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_read_back_what_was_written() {
+        let mut writer = Writer::new();
+        writer.u8(0x12);
+        writer.bool(true);
+        writer.u16(0x3456);
+        writer.u32(0x789A_BCDE);
+        writer.f32(-0.5);
+        writer.bytes(&[1, 2, 3]);
+        writer.words(&[0x7FFF, 0x0001]);
+
+        let mut reader = Reader::new(&writer.bytes);
+        assert_eq!(reader.u8().unwrap(), 0x12);
+        assert!(reader.bool().unwrap());
+        assert_eq!(reader.u16().unwrap(), 0x3456);
+        assert_eq!(reader.u32().unwrap(), 0x789A_BCDE);
+        assert_eq!(reader.f32().unwrap(), -0.5);
+        assert_eq!(reader.bytes().unwrap(), vec![1, 2, 3]);
+        assert_eq!(reader.words().unwrap(), vec![0x7FFF, 0x0001]);
+        assert!(reader.is_at_end());
+    }
+
+    #[test]
+    fn it_should_fail_past_the_end() {
+        let mut reader = Reader::new(&[0x01]);
+
+        assert!(matches!(reader.u16(), Err(StateError::Corrupt)));
+    }
+
+    #[test]
+    fn it_should_reject_a_length_that_does_not_fit() {
+        let mut reader = Reader::new(&[0xFF, 0xFF, 0xFF, 0xFF]);
+
+        assert!(matches!(reader.words(), Err(StateError::Corrupt)));
+    }
+
+    #[test]
+    fn it_should_compute_the_standard_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
@@ -0,0 +1,514 @@
+#![allow(dead_code)]
+use crate::apu::channel::{Envelope, LengthCounter};
+use crate::apu::noise::Noise;
+use crate::apu::pulse::{Pulse, Sweep};
+use crate::apu::sound::Apu;
+use crate::apu::wave::Wave;
+use crate::joypad::Joypad;
+use crate::model::Model;
+use crate::processor::cpu::Cpu;
+use crate::processor::memorybus::MemoryBus;
+use crate::processor::registers::Registers;
+use crate::savestate::codec::{Reader, Writer};
+use crate::savestate::StateError;
+use crate::serial::Serial;
+use crate::sgb::commands::{Mask, Sgb};
+use crate::sgb::packet::{PacketReceiver, PACKET_SIZE};
+use crate::video::hdma::Hdma;
+use crate::video::palette::ColorPalette;
+use crate::video::ppu::{Mode, Ppu};
+
+// Emulated state only: settings such as the sample rate, the doctor mode or the
+// watchpoints and host resources such as the audio ring buffer stay as they are
+pub trait Persist {
+    fn save_state(&self, writer: &mut Writer);
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError>;
+}
+
+impl Persist for Cpu {
+    fn save_state(&self, writer: &mut Writer) {
+        self.registers.save_state(writer);
+        writer.bool(self.pause);
+        self.memory.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.registers.load_state(reader)?;
+        self.pause = reader.bool()?;
+        self.memory.load_state(reader)
+    }
+}
+
+impl Persist for Registers {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.raw(&[
+            self.a, self.b, self.c, self.d, self.e, self.f.f, self.h, self.l,
+        ]);
+        writer.u32(self.pc as u32);
+        writer.u32(self.sp as u32);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        let mut registers = [0; 8];
+        reader.raw(&mut registers)?;
+        [
+            self.a, self.b, self.c, self.d, self.e, self.f.f, self.h, self.l,
+        ] = registers;
+        self.pc = reader.u32()? as usize;
+        self.sp = reader.u32()? as usize;
+        Ok(())
+    }
+}
+
+// Cartridge RAM and the registers without a component of their own live in the flat array
+impl Persist for MemoryBus {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.raw(&self.memory);
+        writer.u32(self.pc as u32);
+        writer.u8(model_code(self.model));
+        writer.bool(self.cgb_mode);
+        writer.bytes(&self.wram);
+        writer.u8(self.wram_bank as u8);
+        writer.bool(self.double_speed);
+        writer.bool(self.speed_switch_armed);
+        writer.u32(self.stall_cycles);
+        writer.bytes(&self.boot_rom);
+        self.ppu.save_state(writer);
+        self.hdma.save_state(writer);
+        self.apu.save_state(writer);
+        self.joypad.save_state(writer);
+        self.serial.save_state(writer);
+        self.sgb.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        reader.raw(&mut self.memory)?;
+        self.pc = reader.u32()? as usize;
+        self.model = model_from_code(reader.u8()?)?;
+        self.cgb_mode = reader.bool()?;
+        reader.bytes_into(&mut self.wram)?;
+        self.wram_bank = match reader.u8()? {
+            bank @ 1..=7 => bank as usize,
+            _ => return Err(StateError::Corrupt),
+        };
+        self.double_speed = reader.bool()?;
+        self.speed_switch_armed = reader.bool()?;
+        self.stall_cycles = reader.u32()?;
+        self.boot_rom = reader.bytes()?;
+        self.ppu.load_state(reader)?;
+        self.hdma.load_state(reader)?;
+        self.apu.load_state(reader)?;
+        self.joypad.load_state(reader)?;
+        self.serial.load_state(reader)?;
+        self.sgb.load_state(reader)
+    }
+}
+
+impl Persist for Ppu {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.bytes(&self.vram);
+        writer.u8(self.vram_bank as u8);
+        writer.raw(&self.oam);
+        writer.raw(&[
+            self.lcdc,
+            self.stat,
+            self.scy,
+            self.scx,
+            self.ly,
+            self.lyc,
+            self.bgp,
+            self.obp0,
+            self.obp1,
+            self.wy,
+            self.wx,
+            self.opri,
+            self.window_line,
+        ]);
+        writer.u8(self.mode as u8);
+        writer.u32(self.dots);
+        self.bg_palette.save_state(writer);
+        self.obj_palette.save_state(writer);
+        writer.u8(model_code(self.model));
+        writer.bool(self.cgb_mode);
+        writer.words(&self.framebuffer);
+        writer.bytes(&self.shades);
+        writer.bool(self.frame_ready);
+        writer.bool(self.hblank_entered);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.vram)?;
+        self.vram_bank = match reader.u8()? {
+            bank @ 0..=1 => bank as usize,
+            _ => return Err(StateError::Corrupt),
+        };
+        reader.raw(&mut self.oam)?;
+        let mut registers = [0; 13];
+        reader.raw(&mut registers)?;
+        [
+            self.lcdc,
+            self.stat,
+            self.scy,
+            self.scx,
+            self.ly,
+            self.lyc,
+            self.bgp,
+            self.obp0,
+            self.obp1,
+            self.wy,
+            self.wx,
+            self.opri,
+            self.window_line,
+        ] = registers;
+        self.mode = match reader.u8()? {
+            0 => Mode::HBlank,
+            1 => Mode::VBlank,
+            2 => Mode::OamScan,
+            3 => Mode::Drawing,
+            _ => return Err(StateError::Corrupt),
+        };
+        self.dots = reader.u32()?;
+        self.bg_palette.load_state(reader)?;
+        self.obj_palette.load_state(reader)?;
+        self.model = model_from_code(reader.u8()?)?;
+        self.cgb_mode = reader.bool()?;
+        reader.words_into(&mut self.framebuffer)?;
+        reader.bytes_into(&mut self.shades)?;
+        self.frame_ready = reader.bool()?;
+        self.hblank_entered = reader.bool()?;
+        Ok(())
+    }
+}
+
+impl Persist for ColorPalette {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.raw(&self.data);
+        writer.u8(self.index);
+        writer.bool(self.auto_increment);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        reader.raw(&mut self.data)?;
+        self.index = reader.u8()?;
+        self.auto_increment = reader.bool()?;
+        Ok(())
+    }
+}
+
+impl Persist for Hdma {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.u16(self.source);
+        writer.u16(self.destination);
+        writer.u8(self.remaining);
+        writer.bool(self.hblank_active);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.source = reader.u16()?;
+        self.destination = reader.u16()?;
+        self.remaining = reader.u8()?;
+        self.hblank_active = reader.bool()?;
+        Ok(())
+    }
+}
+
+// The resampling buffers and the high-pass capacitors only shape the host's audio output
+// and are not saved
+impl Persist for Apu {
+    fn save_state(&self, writer: &mut Writer) {
+        self.pulse1.save_state(writer);
+        self.pulse2.save_state(writer);
+        self.wave.save_state(writer);
+        self.noise.save_state(writer);
+        writer.raw(&self.registers);
+        writer.bool(self.powered);
+        writer.u8(self.frame_step);
+        writer.u32(self.frame_timer);
+        writer.u32(self.clock);
+        writer.f32(self.last_output.0);
+        writer.f32(self.last_output.1);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.pulse1.load_state(reader)?;
+        self.pulse2.load_state(reader)?;
+        self.wave.load_state(reader)?;
+        self.noise.load_state(reader)?;
+        reader.raw(&mut self.registers)?;
+        self.powered = reader.bool()?;
+        self.frame_step = reader.u8()?;
+        self.frame_timer = reader.u32()?;
+        self.clock = reader.u32()?;
+        self.last_output = (reader.f32()?, reader.f32()?);
+        Ok(())
+    }
+}
+
+impl Persist for Pulse {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.bool(self.enabled);
+        writer.bool(self.dac_enabled);
+        writer.u8(self.duty as u8);
+        writer.u8(self.duty_step as u8);
+        writer.u16(self.frequency);
+        writer.u32(self.timer);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+        if let Some(sweep) = &self.sweep {
+            sweep.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.enabled = reader.bool()?;
+        self.dac_enabled = reader.bool()?;
+        self.duty = reader.u8()? as usize % 4;
+        self.duty_step = reader.u8()? as usize % 8;
+        self.frequency = reader.u16()?;
+        self.timer = reader.u32()?;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        if let Some(sweep) = &mut self.sweep {
+            sweep.load_state(reader)?;
+        }
+        Ok(())
+    }
+}
+
+impl Persist for Sweep {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.u8(self.period);
+        writer.bool(self.negate);
+        writer.u8(self.shift);
+        writer.u8(self.timer);
+        writer.u16(self.shadow);
+        writer.bool(self.enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.period = reader.u8()?;
+        self.negate = reader.bool()?;
+        self.shift = reader.u8()?;
+        self.timer = reader.u8()?;
+        self.shadow = reader.u16()?;
+        self.enabled = reader.bool()?;
+        Ok(())
+    }
+}
+
+impl Persist for Wave {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.bool(self.enabled);
+        writer.bool(self.dac_enabled);
+        writer.u8(self.volume_code as u8);
+        writer.u16(self.frequency);
+        writer.u32(self.timer);
+        writer.u8(self.position as u8);
+        writer.u8(self.sample);
+        self.length.save_state(writer);
+        writer.raw(&self.ram);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.enabled = reader.bool()?;
+        self.dac_enabled = reader.bool()?;
+        self.volume_code = reader.u8()? as usize % 4;
+        self.frequency = reader.u16()?;
+        self.timer = reader.u32()?;
+        self.position = reader.u8()? as usize % 32;
+        self.sample = reader.u8()?;
+        self.length.load_state(reader)?;
+        reader.raw(&mut self.ram)
+    }
+}
+
+impl Persist for Noise {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.bool(self.enabled);
+        writer.bool(self.dac_enabled);
+        writer.u8(self.clock_shift);
+        writer.bool(self.short_mode);
+        writer.u8(self.divisor_code as u8);
+        writer.u32(self.timer);
+        writer.u16(self.lfsr);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.enabled = reader.bool()?;
+        self.dac_enabled = reader.bool()?;
+        self.clock_shift = reader.u8()?;
+        self.short_mode = reader.bool()?;
+        self.divisor_code = reader.u8()? as usize % 8;
+        self.timer = reader.u32()?;
+        self.lfsr = reader.u16()?;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)
+    }
+}
+
+impl Persist for LengthCounter {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.bool(self.enabled);
+        writer.u16(self.counter);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.enabled = reader.bool()?;
+        self.counter = reader.u16()?.min(self.max);
+        Ok(())
+    }
+}
+
+impl Persist for Envelope {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.u8(self.initial);
+        writer.bool(self.increase);
+        writer.u8(self.period);
+        writer.u8(self.volume);
+        writer.u8(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.initial = reader.u8()?;
+        self.increase = reader.bool()?;
+        self.period = reader.u8()?;
+        self.volume = reader.u8()?;
+        self.timer = reader.u8()?;
+        Ok(())
+    }
+}
+
+impl Persist for Joypad {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.u8(self.select);
+        writer.u8(self.pressed);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.select = reader.u8()?;
+        self.pressed = reader.u8()?;
+        Ok(())
+    }
+}
+
+impl Persist for Serial {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.u8(self.data);
+        writer.u8(self.control);
+        writer.u32(self.remaining);
+        writer.bytes(&self.output);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.data = reader.u8()?;
+        self.control = reader.u8()?;
+        self.remaining = reader.u32()?;
+        self.output = reader.bytes()?;
+        Ok(())
+    }
+}
+
+impl Persist for Sgb {
+    fn save_state(&self, writer: &mut Writer) {
+        self.receiver.save_state(writer);
+        for color in self.palettes.iter().flatten() {
+            writer.u16(*color);
+        }
+        writer.raw(&self.attributes);
+        writer.u8(self.mask as u8);
+        writer.u8(self.players);
+        writer.u8(self.current_player);
+        writer.bytes(&self.border_tiles);
+        writer.words(&self.border_map);
+        for color in self.border_palettes.iter().flatten() {
+            writer.u16(*color);
+        }
+        writer.words(&self.game_screen);
+        writer.words(&self.screen);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        self.receiver.load_state(reader)?;
+        for color in self.palettes.iter_mut().flatten() {
+            *color = reader.u16()?;
+        }
+        reader.raw(&mut self.attributes)?;
+        self.mask = match reader.u8()? {
+            0 => Mask::Cancel,
+            1 => Mask::Freeze,
+            2 => Mask::Black,
+            3 => Mask::Color0,
+            _ => return Err(StateError::Corrupt),
+        };
+        self.players = match reader.u8()? {
+            players @ (1 | 2 | 4) => players,
+            _ => return Err(StateError::Corrupt),
+        };
+        self.current_player = match reader.u8()? {
+            player if player < self.players => player,
+            _ => return Err(StateError::Corrupt),
+        };
+        reader.bytes_into(&mut self.border_tiles)?;
+        reader.words_into(&mut self.border_map)?;
+        for color in self.border_palettes.iter_mut().flatten() {
+            *color = reader.u16()?;
+        }
+        reader.words_into(&mut self.game_screen)?;
+        reader.words_into(&mut self.screen)
+    }
+}
+
+impl Persist for PacketReceiver {
+    fn save_state(&self, writer: &mut Writer) {
+        writer.raw(&self.buffer);
+        writer.u8(self.bit as u8);
+        writer.bool(self.receiving);
+        writer.u8(self.last_select);
+        writer.u8(self.packets.len() as u8);
+        for packet in &self.packets {
+            writer.raw(packet);
+        }
+        writer.u8(self.expected as u8);
+    }
+
+    fn load_state(&mut self, reader: &mut Reader) -> Result<(), StateError> {
+        reader.raw(&mut self.buffer)?;
+        // One past the last bit is the stop bit
+        self.bit = match reader.u8()? as usize {
+            bit if bit <= PACKET_SIZE * 8 => bit,
+            _ => return Err(StateError::Corrupt),
+        };
+        self.receiving = reader.bool()?;
+        self.last_select = reader.u8()?;
+        let count = reader.u8()?;
+        self.packets.clear();
+        for _ in 0..count {
+            let mut packet = [0; PACKET_SIZE];
+            reader.raw(&mut packet)?;
+            self.packets.push(packet);
+        }
+        self.expected = reader.u8()? as usize;
+        Ok(())
+    }
+}
+
+fn model_code(model: Model) -> u8 {
+    match model {
+        Model::Dmg => 0,
+        Model::Cgb => 1,
+        Model::Sgb => 2,
+        Model::Agb => 3,
+    }
+}
+
+fn model_from_code(code: u8) -> Result<Model, StateError> {
+    match code {
+        0 => Ok(Model::Dmg),
+        1 => Ok(Model::Cgb),
+        2 => Ok(Model::Sgb),
+        3 => Ok(Model::Agb),
+        _ => Err(StateError::Corrupt),
+    }
+}
@@ -0,0 +1,86 @@
+#![allow(dead_code)]
+
+pub const SERIAL_INTERRUPT: u8 = 0x08;
+
+const TRANSFER_START: u8 = 0x80;
+const INTERNAL_CLOCK: u8 = 0x01;
+// 8 bits shifted out at 8192 Hz
+const TRANSFER_CYCLES: u32 = 8 * 512;
+
+// No link cable is plugged in: bytes sent are kept as output and 0xFF is received
+#[derive(Debug, Clone)]
+pub struct Serial {
+    pub data: u8,
+    pub control: u8,
+    pub remaining: u32,
+    pub output: Vec<u8>,
+}
+
+impl Serial {
+    pub fn new() -> Serial {
+        Serial {
+            data: 0,
+            control: 0,
+            remaining: 0,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn read_control(&self) -> u8 {
+        self.control | 0x7E
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        self.control = value & (TRANSFER_START | INTERNAL_CLOCK);
+        // With an external clock the transfer waits for a peer that never comes
+        if value & (TRANSFER_START | INTERNAL_CLOCK) == TRANSFER_START | INTERNAL_CLOCK {
+            self.remaining = TRANSFER_CYCLES;
+        }
+    }
+
+    // Returns the serial interrupt once the byte is shifted out
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        if self.remaining == 0 {
+            return 0;
+        }
+        self.remaining = self.remaining.saturating_sub(cycles);
+        if self.remaining > 0 {
+            return 0;
+        }
+        self.output.push(self.data);
+        self.data = 0xFF;
+        self.control &= !TRANSFER_START;
+        SERIAL_INTERRUPT
+    }
+
+    pub fn output_text(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_send_byte_with_internal_clock() {
+        let mut serial = Serial::new();
+        serial.data = b'P';
+        serial.write_control(0x81);
+
+        assert_eq!(serial.tick(TRANSFER_CYCLES - 4), 0);
+        assert_eq!(serial.tick(4), SERIAL_INTERRUPT);
+        assert_eq!(serial.output_text(), "P");
+        assert_eq!(serial.read_control(), 0x7F);
+        assert_eq!(serial.data, 0xFF);
+    }
+
+    #[test]
+    fn it_should_wait_forever_with_external_clock() {
+        let mut serial = Serial::new();
+        serial.write_control(0x80);
+
+        assert_eq!(serial.tick(TRANSFER_CYCLES * 2), 0);
+        assert!(serial.output.is_empty());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod border;
+pub mod commands;
+pub mod packet;
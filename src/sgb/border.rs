@@ -0,0 +1,129 @@
+use crate::sgb::commands::{Mask, Sgb, ATTRIBUTE_COLUMNS};
+use crate::video::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const SGB_WIDTH: usize = 256;
+pub const SGB_HEIGHT: usize = 224;
+
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+const BORDER_COLUMNS: usize = 32;
+const BORDER_ROWS: usize = 28;
+const BORDER_TILE_SIZE: usize = 32;
+
+impl Sgb {
+    // Builds the 256x224 picture from the DMG shades of the last frame, with the
+    // game screen inset in the border
+    pub fn compose(&mut self, shades: &[u8]) -> &[u16] {
+        if self.mask != Mask::Freeze {
+            self.color_game_screen(shades);
+        }
+        let backdrop = self.palettes[0][0];
+        self.screen.fill(backdrop);
+        for y in 0..SCREEN_HEIGHT {
+            let start = (SCREEN_Y + y) * SGB_WIDTH + SCREEN_X;
+            self.screen[start..start + SCREEN_WIDTH]
+                .copy_from_slice(&self.game_screen[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH]);
+        }
+        self.draw_border();
+        &self.screen
+    }
+
+    fn color_game_screen(&mut self, shades: &[u8]) {
+        for (position, shade) in shades.iter().enumerate() {
+            let x = position % SCREEN_WIDTH;
+            let y = position / SCREEN_WIDTH;
+            let palette = self.attributes[(y / 8) * ATTRIBUTE_COLUMNS + x / 8] as usize;
+            self.game_screen[position] = match self.mask {
+                Mask::Black => 0x0000,
+                Mask::Color0 => self.palettes[0][0],
+                _ => self.palettes[palette][*shade as usize],
+            };
+        }
+    }
+
+    // Border tiles are SNES 4bpp, color 0 lets the game screen or backdrop through
+    fn draw_border(&mut self) {
+        for row in 0..BORDER_ROWS {
+            for column in 0..BORDER_COLUMNS {
+                let entry = self.border_map[row * BORDER_COLUMNS + column];
+                let tile = (entry & 0xFF) as usize * BORDER_TILE_SIZE;
+                let palette = ((entry >> 10) & 0x7).saturating_sub(4) as usize;
+                let x_flip = entry & 0x4000 != 0;
+                let y_flip = entry & 0x8000 != 0;
+                for tile_y in 0..8 {
+                    let line = if y_flip { 7 - tile_y } else { tile_y };
+                    let planes = [
+                        self.border_tiles[tile + line * 2],
+                        self.border_tiles[tile + line * 2 + 1],
+                        self.border_tiles[tile + 16 + line * 2],
+                        self.border_tiles[tile + 16 + line * 2 + 1],
+                    ];
+                    for tile_x in 0..8 {
+                        let bit = if x_flip { tile_x } else { 7 - tile_x };
+                        let color = planes.iter().enumerate().fold(0, |color, (plane, byte)| {
+                            color | (((byte >> bit) & 0x1) << plane)
+                        });
+                        if color != 0 {
+                            let x = column * 8 + tile_x;
+                            let y = row * 8 + tile_y;
+                            self.screen[y * SGB_WIDTH + x] =
+                                self.border_palettes[palette][color as usize];
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen_pixel(sgb: &Sgb, x: usize, y: usize) -> u16 {
+        sgb.screen[(SCREEN_Y + y) * SGB_WIDTH + SCREEN_X + x]
+    }
+
+    #[test]
+    fn it_should_color_game_screen_with_attribute_palettes() {
+        let mut sgb = Sgb::new();
+        sgb.palettes[1][2] = 0x1234;
+        sgb.attributes[ATTRIBUTE_COLUMNS + 1] = 1;
+        let shades = vec![2; SCREEN_WIDTH * SCREEN_HEIGHT];
+        sgb.compose(&shades);
+
+        assert_eq!(screen_pixel(&sgb, 8, 8), 0x1234);
+        assert_eq!(screen_pixel(&sgb, 0, 0), sgb.palettes[0][2]);
+    }
+
+    #[test]
+    fn it_should_draw_border_over_backdrop() {
+        let mut sgb = Sgb::new();
+        sgb.border_map[0] = 5 << 10 | 1;
+        sgb.border_tiles[BORDER_TILE_SIZE] = 0x80;
+        sgb.border_palettes[1][1] = 0x0ABC;
+        sgb.compose(&vec![0; SCREEN_WIDTH * SCREEN_HEIGHT]);
+
+        assert_eq!(sgb.screen[0], 0x0ABC);
+        assert_eq!(sgb.screen[1], sgb.palettes[0][0]);
+    }
+
+    #[test]
+    fn it_should_keep_last_screen_when_frozen() {
+        let mut sgb = Sgb::new();
+        sgb.compose(&vec![0; SCREEN_WIDTH * SCREEN_HEIGHT]);
+        sgb.mask = Mask::Freeze;
+        sgb.compose(&vec![3; SCREEN_WIDTH * SCREEN_HEIGHT]);
+
+        assert_eq!(screen_pixel(&sgb, 0, 0), sgb.palettes[0][0]);
+    }
+
+    #[test]
+    fn it_should_black_out_game_screen() {
+        let mut sgb = Sgb::new();
+        sgb.mask = Mask::Black;
+        sgb.compose(&vec![0; SCREEN_WIDTH * SCREEN_HEIGHT]);
+
+        assert_eq!(screen_pixel(&sgb, 10, 10), 0x0000);
+    }
+}
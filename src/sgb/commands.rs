@@ -0,0 +1,405 @@
+#![allow(dead_code)]
+use crate::sgb::border::{SGB_HEIGHT, SGB_WIDTH};
+use crate::sgb::packet::{Packet, PacketReceiver};
+use crate::video::palette::DMG_SHADES;
+use crate::video::ppu::{Ppu, LCDC_BG_MAP, LCDC_TILE_DATA, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const ATTRIBUTE_COLUMNS: usize = 20;
+pub const ATTRIBUTE_ROWS: usize = 18;
+pub const TRANSFER_SIZE: usize = 0x1000;
+pub const BORDER_TILES_SIZE: usize = 0x2000;
+pub const BORDER_MAP_SIZE: usize = 32 * 32;
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const MASK_EN: u8 = 0x17;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mask {
+    Cancel,
+    Freeze,
+    Black,
+    Color0,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sgb {
+    pub receiver: PacketReceiver,
+    pub palettes: [[u16; 4]; 4],
+    pub attributes: [u8; ATTRIBUTE_COLUMNS * ATTRIBUTE_ROWS],
+    pub mask: Mask,
+    pub players: u8,
+    pub current_player: u8,
+    pub border_tiles: Vec<u8>,
+    pub border_map: Vec<u16>,
+    pub border_palettes: [[u16; 16]; 4],
+    pub game_screen: Vec<u16>,
+    pub screen: Vec<u16>,
+}
+
+impl Sgb {
+    pub fn new() -> Sgb {
+        Sgb {
+            receiver: PacketReceiver::new(),
+            palettes: [DMG_SHADES; 4],
+            attributes: [0; ATTRIBUTE_COLUMNS * ATTRIBUTE_ROWS],
+            mask: Mask::Cancel,
+            players: 1,
+            current_player: 0,
+            border_tiles: vec![0; BORDER_TILES_SIZE],
+            border_map: vec![0; BORDER_MAP_SIZE],
+            border_palettes: [[0; 16]; 4],
+            game_screen: vec![DMG_SHADES[0]; SCREEN_WIDTH * SCREEN_HEIGHT],
+            screen: vec![DMG_SHADES[0]; SGB_WIDTH * SGB_HEIGHT],
+        }
+    }
+
+    pub fn write_joypad(&mut self, value: u8, ppu: &Ppu) {
+        // Each time P15 is released the next controller is selected
+        let p15_released = self.receiver.last_select & 0x20 == 0 && value & 0x20 != 0;
+        if p15_released && self.players > 1 {
+            self.current_player = (self.current_player + 1) % self.players;
+        }
+        if let Some(packets) = self.receiver.write(value) {
+            self.execute(&packets, ppu);
+        }
+    }
+
+    // With both lines released P1 returns the current controller ID
+    pub fn read_joypad_id(&self) -> Option<u8> {
+        if self.players > 1 && self.receiver.last_select == 0x30 {
+            Some(0xF - self.current_player)
+        } else {
+            None
+        }
+    }
+
+    pub fn execute(&mut self, packets: &[Packet], ppu: &Ppu) {
+        let data: Vec<u8> = packets
+            .iter()
+            .flat_map(|packet| packet.iter())
+            .copied()
+            .collect();
+        match data[0] >> 3 {
+            PAL01 => self.set_palettes(0, 1, &data),
+            PAL23 => self.set_palettes(2, 3, &data),
+            PAL03 => self.set_palettes(0, 3, &data),
+            PAL12 => self.set_palettes(1, 2, &data),
+            ATTR_BLK => self.attribute_blocks(&data),
+            ATTR_LIN => self.attribute_lines(&data),
+            ATTR_DIV => self.attribute_divide(&data),
+            ATTR_CHR => self.attribute_characters(&data),
+            MLT_REQ => self.multiplayer_request(data[1]),
+            CHR_TRN => self.character_transfer(data[1], ppu),
+            PCT_TRN => self.picture_transfer(ppu),
+            MASK_EN => self.mask = mask_from_byte(data[1]),
+            _ => {}
+        }
+    }
+
+    // Color 0 is shared by every palette
+    fn set_palettes(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color = |index: usize| color_at(data, 1 + index * 2);
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color(0);
+        }
+        for index in 1..4 {
+            self.palettes[first][index] = color(index);
+            self.palettes[second][index] = color(index + 3);
+        }
+    }
+
+    fn set_attribute(&mut self, x: usize, y: usize, palette: u8) {
+        if x < ATTRIBUTE_COLUMNS && y < ATTRIBUTE_ROWS {
+            self.attributes[y * ATTRIBUTE_COLUMNS + x] = palette & 0x3;
+        }
+    }
+
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        let count = (data[1] & 0x1F) as usize;
+        for block in data[2..].chunks(6).take(count) {
+            if block.len() < 6 {
+                break;
+            }
+            let control = block[0] & 0x7;
+            let inside = block[1] & 0x3;
+            let mut border = (block[1] >> 2) & 0x3;
+            let outside = (block[1] >> 4) & 0x3;
+            // Setting only one of inside or outside also colors the border with it
+            if control == 0x1 {
+                border = inside;
+            } else if control == 0x4 {
+                border = outside;
+            }
+            let color_border = control & 0x2 != 0 || control == 0x1 || control == 0x4;
+            let (x1, y1, x2, y2) = (
+                block[2] as usize,
+                block[3] as usize,
+                block[4] as usize,
+                block[5] as usize,
+            );
+            for y in 0..ATTRIBUTE_ROWS {
+                for x in 0..ATTRIBUTE_COLUMNS {
+                    let within = x >= x1 && x <= x2 && y >= y1 && y <= y2;
+                    let on_border = within && (x == x1 || x == x2 || y == y1 || y == y2);
+                    if on_border && color_border {
+                        self.set_attribute(x, y, border);
+                    } else if within && control & 0x1 != 0 {
+                        self.set_attribute(x, y, inside);
+                    } else if !within && control & 0x4 != 0 {
+                        self.set_attribute(x, y, outside);
+                    }
+                }
+            }
+        }
+    }
+
+    fn attribute_lines(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for line in data[2..].iter().take(count) {
+            let number = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0x3;
+            if line & 0x80 != 0 {
+                for x in 0..ATTRIBUTE_COLUMNS {
+                    self.set_attribute(x, number, palette);
+                }
+            } else {
+                for y in 0..ATTRIBUTE_ROWS {
+                    self.set_attribute(number, y, palette);
+                }
+            }
+        }
+    }
+
+    fn attribute_divide(&mut self, data: &[u8]) {
+        let after = data[1] & 0x3;
+        let before = (data[1] >> 2) & 0x3;
+        let on_line = (data[1] >> 4) & 0x3;
+        let horizontal = data[1] & 0x40 != 0;
+        let split = data[2] as usize;
+        for y in 0..ATTRIBUTE_ROWS {
+            for x in 0..ATTRIBUTE_COLUMNS {
+                let position = if horizontal { y } else { x };
+                let palette = match position.cmp(&split) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on_line,
+                    std::cmp::Ordering::Greater => after,
+                };
+                self.set_attribute(x, y, palette);
+            }
+        }
+    }
+
+    fn attribute_characters(&mut self, data: &[u8]) {
+        let mut x = data[1] as usize;
+        let mut y = data[2] as usize;
+        let count = (data[3] as usize) | ((data[4] as usize & 0x1) << 8);
+        let vertical = data[5] & 0x1 != 0;
+        for index in 0..count {
+            let Some(byte) = data.get(6 + index / 4) else {
+                break;
+            };
+            let palette = (byte >> (6 - (index % 4) * 2)) & 0x3;
+            self.set_attribute(x, y, palette);
+            if vertical {
+                y += 1;
+                if y == ATTRIBUTE_ROWS {
+                    y = 0;
+                    x += 1;
+                }
+            } else {
+                x += 1;
+                if x == ATTRIBUTE_COLUMNS {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+    }
+
+    fn multiplayer_request(&mut self, value: u8) {
+        self.players = match value & 0x3 {
+            0x1 => 2,
+            0x3 => 4,
+            _ => 1,
+        };
+        self.current_player = 0;
+    }
+
+    fn character_transfer(&mut self, value: u8, ppu: &Ppu) {
+        let offset = (value as usize & 0x1) * TRANSFER_SIZE;
+        let data = transferred_data(ppu);
+        self.border_tiles[offset..offset + TRANSFER_SIZE].copy_from_slice(&data);
+    }
+
+    fn picture_transfer(&mut self, ppu: &Ppu) {
+        let data = transferred_data(ppu);
+        for (entry, bytes) in self.border_map.iter_mut().zip(data.chunks(2)) {
+            *entry = (bytes[0] as u16) | ((bytes[1] as u16) << 8);
+        }
+        for (palette, colors) in self.border_palettes.iter_mut().enumerate() {
+            for (index, color) in colors.iter_mut().enumerate() {
+                *color = color_at(&data, 0x800 + palette * 32 + index * 2);
+            }
+        }
+    }
+}
+
+fn color_at(data: &[u8], position: usize) -> u16 {
+    let low = data.get(position).copied().unwrap_or(0) as u16;
+    let high = data.get(position + 1).copied().unwrap_or(0) as u16;
+    ((high << 8) | low) & 0x7FFF
+}
+
+fn mask_from_byte(value: u8) -> Mask {
+    match value & 0x3 {
+        0x1 => Mask::Freeze,
+        0x2 => Mask::Black,
+        0x3 => Mask::Color0,
+        _ => Mask::Cancel,
+    }
+}
+
+// VRAM transfers read the 256 tiles shown on screen, row by row through the BG map
+fn transferred_data(ppu: &Ppu) -> Vec<u8> {
+    let map = if ppu.lcdc & LCDC_BG_MAP != 0 {
+        0x1C00
+    } else {
+        0x1800
+    };
+    let mut data = Vec::with_capacity(TRANSFER_SIZE);
+    for index in 0..TRANSFER_SIZE / 16 {
+        let tile = ppu.vram[map + (index / 20) * 32 + index % 20];
+        let address = if ppu.lcdc & LCDC_TILE_DATA != 0 {
+            tile as usize * 16
+        } else {
+            (0x1000 + (tile as i8 as i32) * 16) as usize
+        };
+        data.extend_from_slice(&ppu.vram[address..address + 16]);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+    use crate::sgb::packet::PACKET_SIZE;
+
+    fn command(code: u8, body: &[u8]) -> Vec<Packet> {
+        let length = (body.len() + 1).div_ceil(PACKET_SIZE);
+        let mut data = vec![0; length * PACKET_SIZE];
+        data[0] = (code << 3) | length as u8;
+        data[1..1 + body.len()].copy_from_slice(body);
+        data.chunks(PACKET_SIZE)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect()
+    }
+
+    fn attribute(sgb: &Sgb, x: usize, y: usize) -> u8 {
+        sgb.attributes[y * ATTRIBUTE_COLUMNS + x]
+    }
+
+    #[test]
+    fn it_should_set_two_palettes_with_shared_color_0() {
+        let mut sgb = Sgb::new();
+        let ppu = Ppu::new(Model::Dmg, false);
+        let body = [
+            0x1F, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x04, 0x00, 0x05, 0x00, 0x06, 0x00,
+        ];
+        sgb.execute(&command(PAL23, &body), &ppu);
+
+        assert_eq!(sgb.palettes[0][0], 0x001F);
+        assert_eq!(sgb.palettes[2], [0x001F, 0x01, 0x02, 0x03]);
+        assert_eq!(sgb.palettes[3], [0x001F, 0x04, 0x05, 0x06]);
+    }
+
+    #[test]
+    fn it_should_color_inside_and_border_of_a_block() {
+        let mut sgb = Sgb::new();
+        let ppu = Ppu::new(Model::Dmg, false);
+        sgb.execute(
+            &command(ATTR_BLK, &[1, 0x03, 0b0001_1001, 2, 2, 5, 5]),
+            &ppu,
+        );
+
+        assert_eq!(attribute(&sgb, 2, 2), 2);
+        assert_eq!(attribute(&sgb, 3, 3), 1);
+        assert_eq!(attribute(&sgb, 0, 0), 0);
+    }
+
+    #[test]
+    fn it_should_color_a_horizontal_line() {
+        let mut sgb = Sgb::new();
+        let ppu = Ppu::new(Model::Dmg, false);
+        sgb.execute(&command(ATTR_LIN, &[1, 0x80 | (2 << 5) | 4]), &ppu);
+
+        assert_eq!(attribute(&sgb, 0, 4), 2);
+        assert_eq!(attribute(&sgb, 19, 4), 2);
+        assert_eq!(attribute(&sgb, 0, 5), 0);
+    }
+
+    #[test]
+    fn it_should_divide_the_screen() {
+        let mut sgb = Sgb::new();
+        let ppu = Ppu::new(Model::Dmg, false);
+        sgb.execute(&command(ATTR_DIV, &[0b0110_0111, 9]), &ppu);
+
+        assert_eq!(attribute(&sgb, 0, 0), 1);
+        assert_eq!(attribute(&sgb, 0, 9), 2);
+        assert_eq!(attribute(&sgb, 0, 10), 3);
+    }
+
+    #[test]
+    fn it_should_set_attributes_character_by_character() {
+        let mut sgb = Sgb::new();
+        let ppu = Ppu::new(Model::Dmg, false);
+        sgb.execute(&command(ATTR_CHR, &[18, 0, 3, 0, 0, 0b1110_0100]), &ppu);
+
+        assert_eq!(attribute(&sgb, 18, 0), 3);
+        assert_eq!(attribute(&sgb, 19, 0), 2);
+        assert_eq!(attribute(&sgb, 0, 1), 1);
+    }
+
+    #[test]
+    fn it_should_cycle_controller_id_in_multiplayer() {
+        let mut sgb = Sgb::new();
+        let ppu = Ppu::new(Model::Dmg, false);
+        sgb.execute(&command(MLT_REQ, &[0x01]), &ppu);
+        sgb.write_joypad(0x30, &ppu);
+        assert_eq!(sgb.read_joypad_id(), Some(0xF));
+
+        sgb.write_joypad(0x10, &ppu);
+        sgb.write_joypad(0x30, &ppu);
+        assert_eq!(sgb.read_joypad_id(), Some(0xE));
+    }
+
+    #[test]
+    fn it_should_transfer_border_tiles_from_screen() {
+        let mut sgb = Sgb::new();
+        let mut ppu = Ppu::new(Model::Dmg, false);
+        ppu.vram[0x1800] = 0x01;
+        ppu.vram[16] = 0xAB;
+        sgb.execute(&command(CHR_TRN, &[0x01]), &ppu);
+
+        assert_eq!(sgb.border_tiles[TRANSFER_SIZE], 0xAB);
+    }
+
+    #[test]
+    fn it_should_enable_mask() {
+        let mut sgb = Sgb::new();
+        let ppu = Ppu::new(Model::Dmg, false);
+        sgb.execute(&command(MASK_EN, &[0x02]), &ppu);
+
+        assert_eq!(sgb.mask, Mask::Black);
+    }
+}
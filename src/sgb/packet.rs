@@ -0,0 +1,135 @@
+#![allow(dead_code)]
+
+pub const PACKET_SIZE: usize = 16;
+const PACKET_BITS: usize = PACKET_SIZE * 8;
+
+pub type Packet = [u8; PACKET_SIZE];
+
+const RESET: u8 = 0x00;
+const BIT_ONE: u8 = 0x10;
+const BIT_ZERO: u8 = 0x20;
+const IDLE: u8 = 0x30;
+
+// Packets are sent bit by bit through P14/P15: a reset pulse, 128 data bits sent
+// LSB first with both lines released in between, then a 0 stop bit
+#[derive(Debug, Clone)]
+pub struct PacketReceiver {
+    pub buffer: Packet,
+    pub bit: usize,
+    pub receiving: bool,
+    pub last_select: u8,
+    pub packets: Vec<Packet>,
+    pub expected: usize,
+}
+
+impl PacketReceiver {
+    pub fn new() -> PacketReceiver {
+        PacketReceiver {
+            buffer: [0; PACKET_SIZE],
+            bit: 0,
+            receiving: false,
+            last_select: IDLE,
+            packets: Vec::new(),
+            expected: 0,
+        }
+    }
+
+    // Returns every packet of a command once its last packet is received
+    pub fn write(&mut self, value: u8) -> Option<Vec<Packet>> {
+        let select = value & 0x30;
+        let last = self.last_select;
+        self.last_select = select;
+        match select {
+            RESET => {
+                self.receiving = true;
+                self.bit = 0;
+                self.buffer = [0; PACKET_SIZE];
+                None
+            }
+            IDLE if self.receiving && (last == BIT_ONE || last == BIT_ZERO) => {
+                self.receive_bit(last == BIT_ONE)
+            }
+            _ => None,
+        }
+    }
+
+    fn receive_bit(&mut self, bit: bool) -> Option<Vec<Packet>> {
+        if self.bit == PACKET_BITS {
+            self.receiving = false;
+            return if bit { None } else { self.finish_packet() };
+        }
+        if bit {
+            self.buffer[self.bit / 8] |= 1 << (self.bit % 8);
+        }
+        self.bit += 1;
+        None
+    }
+
+    fn finish_packet(&mut self) -> Option<Vec<Packet>> {
+        if self.packets.is_empty() {
+            self.expected = (self.buffer[0] & 0x7).max(1) as usize;
+        }
+        self.packets.push(self.buffer);
+        if self.packets.len() < self.expected {
+            return None;
+        }
+        Some(std::mem::take(&mut self.packets))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub fn send_packet(receiver: &mut PacketReceiver, packet: &Packet) -> Option<Vec<Packet>> {
+        receiver.write(RESET);
+        receiver.write(IDLE);
+        for byte in packet {
+            for bit in 0..8 {
+                let line = if byte & (1 << bit) != 0 {
+                    BIT_ONE
+                } else {
+                    BIT_ZERO
+                };
+                receiver.write(line);
+                receiver.write(IDLE);
+            }
+        }
+        receiver.write(BIT_ZERO);
+        receiver.write(IDLE)
+    }
+
+    #[test]
+    fn it_should_receive_a_single_packet_command() {
+        let mut receiver = PacketReceiver::new();
+        let mut packet = [0; PACKET_SIZE];
+        packet[0] = (0x11 << 3) | 1;
+        packet[1] = 0x03;
+        let command = send_packet(&mut receiver, &packet).unwrap();
+
+        assert_eq!(command.len(), 1);
+        assert_eq!(command[0], packet);
+    }
+
+    #[test]
+    fn it_should_wait_for_every_packet_of_a_command() {
+        let mut receiver = PacketReceiver::new();
+        let mut first = [0; PACKET_SIZE];
+        first[0] = (0x04 << 3) | 2;
+        let second = [0xAA; PACKET_SIZE];
+
+        assert!(send_packet(&mut receiver, &first).is_none());
+        let command = send_packet(&mut receiver, &second).unwrap();
+        assert_eq!(command.len(), 2);
+        assert_eq!(command[1], second);
+    }
+
+    #[test]
+    fn it_should_ignore_bits_without_reset_pulse() {
+        let mut receiver = PacketReceiver::new();
+        receiver.write(BIT_ONE);
+        receiver.write(IDLE);
+
+        assert_eq!(receiver.bit, 0);
+    }
+}
@@ -0,0 +1,145 @@
+#![allow(dead_code)]
+
+// Speeds the hotkeys step through, from slow motion to fast-forward
+pub const MULTIPLIERS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+// How much emulation one host update asks for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pace {
+    Frames(u32),
+    Uncapped,
+}
+
+// Turns the host's updates, one per Game Boy frame, into emulated frames: fractional
+// speeds carry the remainder over to the next update, turbo runs as many as it can
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeedControl {
+    pub multiplier: f64,
+    pub turbo: bool,
+    pub paused: bool,
+    advance: u32,
+    credit: f64,
+}
+
+impl SpeedControl {
+    pub fn new(multiplier: f64) -> SpeedControl {
+        SpeedControl {
+            multiplier,
+            turbo: false,
+            paused: false,
+            advance: 0,
+            credit: 0.0,
+        }
+    }
+
+    // The next listed speed above the current one, which may be any multiplier
+    pub fn faster(&mut self) {
+        if let Some(next) = MULTIPLIERS.iter().find(|speed| **speed > self.multiplier) {
+            self.multiplier = *next;
+        }
+    }
+
+    pub fn slower(&mut self) {
+        if let Some(next) = MULTIPLIERS
+            .iter()
+            .rev()
+            .find(|speed| **speed < self.multiplier)
+        {
+            self.multiplier = *next;
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.advance = 0;
+        self.credit = 0.0;
+    }
+
+    // Pauses if needed and lets exactly one more frame run
+    pub fn advance_frame(&mut self) {
+        self.paused = true;
+        self.advance += 1;
+    }
+
+    // Audio only plays at 1x, it would be pitched up or down otherwise
+    pub fn is_normal(&self) -> bool {
+        !self.turbo && !self.paused && self.multiplier == 1.0
+    }
+
+    pub fn pace(&mut self) -> Pace {
+        if self.paused {
+            return Pace::Frames(std::mem::take(&mut self.advance));
+        }
+        if self.turbo {
+            return Pace::Uncapped;
+        }
+        self.credit += self.multiplier;
+        let frames = self.credit.floor();
+        self.credit -= frames;
+        Pace::Frames(frames as u32)
+    }
+}
+
+impl Default for SpeedControl {
+    fn default() -> SpeedControl {
+        SpeedControl::new(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(speed: &mut SpeedControl, updates: usize) -> u32 {
+        (0..updates)
+            .map(|_| match speed.pace() {
+                Pace::Frames(frames) => frames,
+                Pace::Uncapped => panic!("uncapped"),
+            })
+            .sum()
+    }
+
+    #[test]
+    fn it_should_run_frames_in_proportion_to_the_multiplier() {
+        assert_eq!(frames(&mut SpeedControl::new(1.0), 60), 60);
+        assert_eq!(frames(&mut SpeedControl::new(0.25), 60), 15);
+        assert_eq!(frames(&mut SpeedControl::new(8.0), 60), 480);
+        assert_eq!(frames(&mut SpeedControl::new(1.5), 4), 6);
+    }
+
+    #[test]
+    fn it_should_step_through_the_multipliers() {
+        let mut speed = SpeedControl::new(1.5);
+        speed.faster();
+        assert_eq!(speed.multiplier, 2.0);
+        for _ in 0..10 {
+            speed.faster();
+        }
+        assert_eq!(speed.multiplier, 8.0);
+        for _ in 0..10 {
+            speed.slower();
+        }
+        assert_eq!(speed.multiplier, 0.25);
+    }
+
+    #[test]
+    fn it_should_advance_one_frame_while_paused() {
+        let mut speed = SpeedControl::default();
+        speed.toggle_pause();
+        assert_eq!(frames(&mut speed, 10), 0);
+
+        speed.advance_frame();
+        assert_eq!(frames(&mut speed, 10), 1);
+        assert!(speed.paused);
+        assert!(!speed.is_normal());
+    }
+
+    #[test]
+    fn it_should_run_uncapped_in_turbo() {
+        let mut speed = SpeedControl::new(0.5);
+        speed.turbo = true;
+
+        assert_eq!(speed.pace(), Pace::Uncapped);
+        assert!(!speed.is_normal());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod blargg;
+pub mod mooneye;
+pub mod runner;
+pub mod sm83;
@@ -0,0 +1,145 @@
+use crate::processor::cpu::Cpu;
+use crate::testroms::runner::{boot, run_until, test_rom};
+
+const STATUS: usize = 0xA000;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const TEXT: usize = 0xA004;
+const MAX_TEXT: usize = 0x1000;
+const RUNNING: u8 = 0x80;
+// Two minutes of emulated time, the slowest ROMs finish well before
+const MAX_FRAMES: u32 = 60 * 120;
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+}
+
+// Newer ROMs write a status at 0xA000 once DE B0 61 follows it, older ones only
+// print "Passed" or "Failed" on the serial port
+pub fn outcome(cpu: &mut Cpu) -> Option<Outcome> {
+    let memory = &cpu.memory;
+    let signed = (0..3).all(|offset| memory.read(STATUS + 1 + offset) == SIGNATURE[offset]);
+    if signed {
+        return match memory.read(STATUS) {
+            RUNNING => None,
+            0 => Some(Outcome::Passed),
+            _ => Some(Outcome::Failed(memory_text(cpu))),
+        };
+    }
+    let text = cpu.memory.serial.output_text();
+    if text.contains("Passed") {
+        Some(Outcome::Passed)
+    } else if text.contains("Failed") {
+        Some(Outcome::Failed(text))
+    } else {
+        None
+    }
+}
+
+fn memory_text(cpu: &Cpu) -> String {
+    let bytes: Vec<u8> = (TEXT..TEXT + MAX_TEXT)
+        .map(|address| cpu.memory.read(address))
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn run(relative: &str) {
+    let path = test_rom(&format!("blargg/{}", relative));
+    let mut cpu = boot(&path);
+    match run_until(&mut cpu, MAX_FRAMES, outcome) {
+        Some(Outcome::Passed) => {}
+        Some(Outcome::Failed(text)) => panic!("{} failed:\n{}", relative, text),
+        None => panic!(
+            "{} did not finish, serial output:\n{}",
+            relative,
+            cpu.memory.serial.output_text()
+        ),
+    }
+}
+
+macro_rules! blargg_tests {
+    ($($name:ident: $path:expr,)*) => {
+        $(
+            #[test]
+            #[ignore = "needs the blargg ROMs, run with --ignored"]
+            fn $name() {
+                run($path);
+            }
+        )*
+    };
+}
+
+blargg_tests! {
+    cpu_instrs_01_special: "cpu_instrs/individual/01-special.gb",
+    cpu_instrs_02_interrupts: "cpu_instrs/individual/02-interrupts.gb",
+    cpu_instrs_03_op_sp_hl: "cpu_instrs/individual/03-op sp,hl.gb",
+    cpu_instrs_04_op_r_imm: "cpu_instrs/individual/04-op r,imm.gb",
+    cpu_instrs_05_op_rp: "cpu_instrs/individual/05-op rp.gb",
+    cpu_instrs_06_ld_r_r: "cpu_instrs/individual/06-ld r,r.gb",
+    cpu_instrs_07_jr_jp_call_ret_rst: "cpu_instrs/individual/07-jr,jp,call,ret,rst.gb",
+    cpu_instrs_08_misc_instrs: "cpu_instrs/individual/08-misc instrs.gb",
+    cpu_instrs_09_op_r_r: "cpu_instrs/individual/09-op r,r.gb",
+    cpu_instrs_10_bit_ops: "cpu_instrs/individual/10-bit ops.gb",
+    cpu_instrs_11_op_a_hl: "cpu_instrs/individual/11-op a,(hl).gb",
+    instr_timing: "instr_timing/instr_timing.gb",
+    mem_timing_01_read_timing: "mem_timing/individual/01-read_timing.gb",
+    mem_timing_02_write_timing: "mem_timing/individual/02-write_timing.gb",
+    mem_timing_03_modify_timing: "mem_timing/individual/03-modify_timing.gb",
+    halt_bug: "halt_bug.gb",
+    dmg_sound_01_registers: "dmg_sound/rom_singles/01-registers.gb",
+    dmg_sound_02_len_ctr: "dmg_sound/rom_singles/02-len ctr.gb",
+    dmg_sound_03_trigger: "dmg_sound/rom_singles/03-trigger.gb",
+    dmg_sound_04_sweep: "dmg_sound/rom_singles/04-sweep.gb",
+    dmg_sound_05_sweep_details: "dmg_sound/rom_singles/05-sweep details.gb",
+    dmg_sound_06_overflow_on_trigger: "dmg_sound/rom_singles/06-overflow on trigger.gb",
+    dmg_sound_07_len_sweep_period_sync: "dmg_sound/rom_singles/07-len sweep period sync.gb",
+    dmg_sound_08_len_ctr_during_power: "dmg_sound/rom_singles/08-len ctr during power.gb",
+    dmg_sound_09_wave_read_while_on: "dmg_sound/rom_singles/09-wave read while on.gb",
+    dmg_sound_10_wave_trigger_while_on: "dmg_sound/rom_singles/10-wave trigger while on.gb",
+    dmg_sound_11_regs_after_power: "dmg_sound/rom_singles/11-regs after power.gb",
+    dmg_sound_12_wave_write_while_on: "dmg_sound/rom_singles/12-wave write while on.gb",
+    cgb_sound_01_registers: "cgb_sound/rom_singles/01-registers.gb",
+    cgb_sound_02_len_ctr: "cgb_sound/rom_singles/02-len ctr.gb",
+    cgb_sound_03_trigger: "cgb_sound/rom_singles/03-trigger.gb",
+    cgb_sound_04_sweep: "cgb_sound/rom_singles/04-sweep.gb",
+    cgb_sound_05_sweep_details: "cgb_sound/rom_singles/05-sweep details.gb",
+    cgb_sound_06_overflow_on_trigger: "cgb_sound/rom_singles/06-overflow on trigger.gb",
+    cgb_sound_07_len_sweep_period_sync: "cgb_sound/rom_singles/07-len sweep period sync.gb",
+    cgb_sound_08_len_ctr_during_power: "cgb_sound/rom_singles/08-len ctr during power.gb",
+    cgb_sound_09_wave_read_while_on: "cgb_sound/rom_singles/09-wave read while on.gb",
+    cgb_sound_10_wave_trigger_while_on: "cgb_sound/rom_singles/10-wave trigger while on.gb",
+    cgb_sound_11_regs_after_power: "cgb_sound/rom_singles/11-regs after power.gb",
+    cgb_sound_12_wave: "cgb_sound/rom_singles/12-wave.gb",
+}
+
+#[test]
+fn it_should_read_result_from_signature() {
+    let mut cpu = Cpu::new();
+    for (offset, byte) in SIGNATURE.iter().enumerate() {
+        cpu.memory.set_byte(*byte, STATUS + 1 + offset);
+    }
+    cpu.memory.set_byte(RUNNING, STATUS);
+    assert_eq!(outcome(&mut cpu), None);
+
+    cpu.memory.set_byte(0x01, STATUS);
+    for (offset, byte) in b"Failed #2\0".iter().enumerate() {
+        cpu.memory.set_byte(*byte, TEXT + offset);
+    }
+    assert_eq!(
+        outcome(&mut cpu),
+        Some(Outcome::Failed("Failed #2".to_string()))
+    );
+
+    cpu.memory.set_byte(0x00, STATUS);
+    assert_eq!(outcome(&mut cpu), Some(Outcome::Passed));
+}
+
+#[test]
+fn it_should_read_result_from_serial_output() {
+    let mut cpu = Cpu::new();
+    cpu.memory.serial.output = b"01-special\n\n\nPassed\n".to_vec();
+
+    assert_eq!(outcome(&mut cpu), Some(Outcome::Passed));
+}
@@ -0,0 +1,178 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cartridge::Cartridge;
+use crate::model::Model;
+use crate::processor::cpu::Cpu;
+use crate::testroms::runner::{roms_directory, run_until, test_rom};
+
+const FIBONACCI: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const FAILURE: u8 = 0x42;
+const MAX_FRAMES: u32 = 60 * 20;
+// Tests in these directories need a human looking at the screen
+const SKIPPED_DIRECTORIES: [&str; 2] = ["manual-only", "utils"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    TimedOut,
+}
+
+// A test ends with LD B,B, B to L hold the Fibonacci numbers on success and 0x42 on failure
+pub fn outcome(cpu: &mut Cpu) -> Option<Outcome> {
+    if !cpu.breakpoint {
+        return None;
+    }
+    let registers = &cpu.registers;
+    let values = [
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+    ];
+    if values == FIBONACCI {
+        Some(Outcome::Passed)
+    } else {
+        if values.iter().all(|value| *value == FAILURE) {
+            return Some(Outcome::Failed);
+        }
+        cpu.breakpoint = false;
+        None
+    }
+}
+
+// The file name ends with the hardware the test expects, "-dmgABC", "-cgb", "-S" for
+// every SGB or "-C" for every CGB, the first one we emulate is picked
+pub fn model_for(name: &str) -> Model {
+    let Some((_, suffix)) = name.trim_end_matches(".gb").rsplit_once('-') else {
+        return Model::Dmg;
+    };
+    let models = [
+        ("dmg", Model::Dmg),
+        ("mgb", Model::Dmg),
+        ("G", Model::Dmg),
+        ("sgb", Model::Sgb),
+        ("S", Model::Sgb),
+        ("cgb", Model::Cgb),
+        ("C", Model::Cgb),
+        ("agb", Model::Agb),
+        ("ags", Model::Agb),
+        ("A", Model::Agb),
+    ];
+    models
+        .iter()
+        .filter_map(|(tag, model)| suffix.find(tag).map(|position| (position, *model)))
+        .min_by_key(|(position, _)| *position)
+        .map_or(Model::Dmg, |(_, model)| model)
+}
+
+fn find_roms(directory: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            let name = entry.file_name();
+            if !SKIPPED_DIRECTORIES.iter().any(|skipped| name == *skipped) {
+                find_roms(&path, roms);
+            }
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            roms.push(path);
+        }
+    }
+}
+
+fn run(path: &Path, model: Model) -> Outcome {
+    let cartridge = Cartridge::from_file(path).expect("test ROM should be readable");
+    let mut cpu = Cpu::from_cartridge_on(&cartridge, model);
+    run_until(&mut cpu, MAX_FRAMES, outcome).unwrap_or(Outcome::TimedOut)
+}
+
+// Runs every ROM and writes a markdown table to target/mooneye-results.md
+#[test]
+#[ignore = "needs the mooneye ROMs, run with --ignored"]
+fn mooneye_test_suite() {
+    let directory = test_rom("mooneye");
+    let mut roms = Vec::new();
+    find_roms(&directory, &mut roms);
+    roms.sort();
+
+    let mut table = String::from("| ROM | Model | Result |\n|---|---|---|\n");
+    let mut failures = Vec::new();
+    for path in &roms {
+        let name = path
+            .strip_prefix(&directory)
+            .unwrap_or(path)
+            .display()
+            .to_string();
+        let model = model_for(&name);
+        let outcome = run(path, model);
+        writeln!(table, "| {} | {:?} | {:?} |", name, model, outcome).unwrap();
+        if outcome != Outcome::Passed {
+            failures.push(name);
+        }
+    }
+    writeln!(
+        table,
+        "\n{}/{} passed",
+        roms.len() - failures.len(),
+        roms.len()
+    )
+    .unwrap();
+    println!("{}", table);
+    let results = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/mooneye-results.md");
+    fs::create_dir_all(results.parent().unwrap()).unwrap();
+    fs::write(&results, &table).unwrap();
+
+    assert!(
+        failures.is_empty(),
+        "{} mooneye ROMs failed in {}, see {}:\n{}",
+        failures.len(),
+        roms_directory().display(),
+        results.display(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn it_should_pick_model_from_file_name() {
+    assert_eq!(model_for("boot_regs-dmgABC.gb"), Model::Dmg);
+    assert_eq!(model_for("acceptance/boot_hwio-S.gb"), Model::Sgb);
+    assert_eq!(model_for("boot_div-cgbABCDE.gb"), Model::Cgb);
+    assert_eq!(model_for("boot_regs-A.gb"), Model::Agb);
+    assert_eq!(model_for("misc/bits/unused_hwio-C.gb"), Model::Cgb);
+    assert_eq!(model_for("boot_div2-S.gb"), Model::Sgb);
+    assert_eq!(model_for("add_sp_e_timing.gb"), Model::Dmg);
+    assert_eq!(model_for("timer/div_write.gb"), Model::Dmg);
+}
+
+#[test]
+fn it_should_detect_fibonacci_signature() {
+    let mut cpu = Cpu::new();
+    cpu.registers.b = 3;
+    cpu.registers.c = 5;
+    cpu.registers.d = 8;
+    cpu.registers.e = 13;
+    cpu.registers.h = 21;
+    cpu.registers.l = 34;
+    assert_eq!(outcome(&mut cpu), None);
+
+    cpu.breakpoint = true;
+    assert_eq!(outcome(&mut cpu), Some(Outcome::Passed));
+}
+
+#[test]
+fn it_should_detect_failure_signature() {
+    let mut cpu = Cpu::new();
+    cpu.registers.set_bc(0x4242);
+    cpu.registers.set_de(0x4242);
+    cpu.registers.set_hl(0x4242);
+    cpu.breakpoint = true;
+
+    assert_eq!(outcome(&mut cpu), Some(Outcome::Failed));
+}
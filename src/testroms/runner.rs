@@ -0,0 +1,53 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::cartridge::Cartridge;
+use crate::frontend::run_frame;
+use crate::processor::cpu::Cpu;
+
+// Test ROMs are not distributed with the emulator, they are looked up in this
+// directory or in `test-roms` at the root of the crate
+pub const ROMS_VARIABLE: &str = "GB_TEST_ROMS";
+
+pub fn roms_directory() -> PathBuf {
+    env::var_os(ROMS_VARIABLE)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("test-roms"))
+}
+
+// The suites are ignored by default, once asked for with --ignored a missing ROM is
+// an error rather than a silent pass
+pub fn test_rom(relative: &str) -> PathBuf {
+    let path = roms_directory().join(relative);
+    assert!(
+        path.exists(),
+        "{} not found in {}, point {} at the test ROMs",
+        relative,
+        roms_directory().display(),
+        ROMS_VARIABLE
+    );
+    path
+}
+
+pub fn boot(path: &Path) -> Cpu {
+    let cartridge = Cartridge::from_file(path).expect("test ROM should be readable");
+    Cpu::from_cartridge(&cartridge)
+}
+
+// Checks the result after every frame, gives up after `max_frames` or when the CPU exits
+pub fn run_until<T, F: Fn(&mut Cpu) -> Option<T>>(
+    cpu: &mut Cpu,
+    max_frames: u32,
+    check: F,
+) -> Option<T> {
+    for _ in 0..max_frames {
+        let running = run_frame(cpu);
+        if let Some(result) = check(cpu) {
+            return Some(result);
+        }
+        if !running {
+            break;
+        }
+    }
+    None
+}
@@ -0,0 +1,276 @@
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::processor::cpu::Cpu;
+use crate::processor::memorybus::{BusAccess, MemoryBus};
+use crate::testroms::runner::test_rom;
+
+// One case of the SM83 single step tests, `cycles` lists every bus access of 4 T-cycles
+#[derive(Debug, Deserialize)]
+struct Case {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct State {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+fn load(cpu: &mut Cpu, state: &State) {
+    let registers = &mut cpu.registers;
+    registers.a = state.a;
+    registers.b = state.b;
+    registers.c = state.c;
+    registers.d = state.d;
+    registers.e = state.e;
+    registers.f.f = state.f;
+    registers.h = state.h;
+    registers.l = state.l;
+    registers.set_sp(state.sp);
+    cpu.memory.pc = state.pc as usize;
+    cpu.pause = false;
+    for (address, value) in &state.ram {
+        cpu.memory.write(*address as usize, *value);
+    }
+}
+
+fn compare(cpu: &Cpu, expected: &State) -> Result<(), String> {
+    let registers = &cpu.registers;
+    let actual = [
+        ("a", registers.a),
+        ("b", registers.b),
+        ("c", registers.c),
+        ("d", registers.d),
+        ("e", registers.e),
+        ("f", registers.f.f),
+        ("h", registers.h),
+        ("l", registers.l),
+    ];
+    let wanted = [
+        expected.a, expected.b, expected.c, expected.d, expected.e, expected.f, expected.h,
+        expected.l,
+    ];
+    for ((name, value), wanted) in actual.iter().zip(wanted) {
+        if *value != wanted {
+            return Err(format!(
+                "{} is {:#04x}, expected {:#04x}",
+                name, value, wanted
+            ));
+        }
+    }
+    if cpu.memory.pc != expected.pc as usize {
+        return Err(format!(
+            "pc is {:#06x}, expected {:#06x}",
+            cpu.memory.pc, expected.pc
+        ));
+    }
+    if registers.sp != expected.sp as usize {
+        return Err(format!(
+            "sp is {:#06x}, expected {:#06x}",
+            registers.sp, expected.sp
+        ));
+    }
+    for (address, value) in &expected.ram {
+        let actual = cpu.memory.read(*address as usize);
+        if actual != *value {
+            return Err(format!(
+                "[{:#06x}] is {:#04x}, expected {:#04x}",
+                address, actual, value
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Each cycle is [address, value, activity], the activity reads like "r-m" or "-wm";
+// internal cycles ("---") have nothing to compare since the CPU does not report them
+fn bus_accesses(cycles: &[serde_json::Value]) -> Result<Vec<BusAccess>, String> {
+    let mut accesses = Vec::new();
+    for cycle in cycles {
+        let activity = cycle[2].as_str().unwrap_or("");
+        let write = activity.starts_with("-w");
+        if !write && !activity.starts_with('r') {
+            continue;
+        }
+        match (cycle[0].as_u64(), cycle[1].as_u64()) {
+            (Some(address), Some(value)) => accesses.push(BusAccess {
+                address: address as u16,
+                value: value as u8,
+                write,
+            }),
+            _ => return Err(format!("malformed cycle {}", cycle)),
+        }
+    }
+    Ok(accesses)
+}
+
+fn describe(access: &BusAccess) -> String {
+    let kind = if access.write { "write" } else { "read" };
+    format!("{} {:#04x} at {:#06x}", kind, access.value, access.address)
+}
+
+fn compare_accesses(actual: &[BusAccess], expected: &[BusAccess]) -> Result<(), String> {
+    for (index, wanted) in expected.iter().enumerate() {
+        match actual.get(index) {
+            Some(access) if access == wanted => {}
+            Some(access) => {
+                return Err(format!(
+                    "bus access {} was a {}, expected a {}",
+                    index,
+                    describe(access),
+                    describe(wanted)
+                ))
+            }
+            None => {
+                return Err(format!(
+                    "bus access {} missing: {}",
+                    index,
+                    describe(wanted)
+                ))
+            }
+        }
+    }
+    match actual.get(expected.len()) {
+        Some(extra) => Err(format!("unexpected bus access {}", describe(extra))),
+        None => Ok(()),
+    }
+}
+
+fn run_case(cpu: &mut Cpu, case: &Case) -> Result<(), String> {
+    load(cpu, &case.initial);
+    cpu.memory.accesses.take();
+    let cycles = cpu.step().unwrap_or(0);
+    let accesses = cpu.memory.accesses.take();
+    compare(cpu, &case.expected)?;
+    compare_accesses(&accesses, &bus_accesses(&case.cycles)?)?;
+    let expected_cycles = case.cycles.len() as u32 * 4;
+    if cycles != expected_cycles {
+        return Err(format!(
+            "took {} cycles, expected {}",
+            cycles, expected_cycles
+        ));
+    }
+    Ok(())
+}
+
+// Returns the first failing case of the file, every case shares a single CPU
+fn run_file(path: &Path) -> Result<usize, String> {
+    let json = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let cases: Vec<Case> = serde_json::from_str(&json).map_err(|error| error.to_string())?;
+    let mut cpu = Cpu::new();
+    cpu.memory = MemoryBus::flat();
+    for case in &cases {
+        run_case(&mut cpu, case).map_err(|error| format!("{}: {}", case.name, error))?;
+    }
+    Ok(cases.len())
+}
+
+// Vectors from SingleStepTests/sm83, one file per opcode like "3e.json" or "cb 7c.json"
+#[test]
+#[ignore = "needs the SingleStepTests sm83 vectors, run with --ignored"]
+fn sm83_single_step_tests() {
+    let directory = test_rom("sm83");
+    let mut files: Vec<_> = fs::read_dir(&directory)
+        .expect("sm83 directory should be readable")
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    files.sort();
+
+    let mut passed = 0;
+    let mut failures = Vec::new();
+    for path in &files {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        // A panicking opcode is one failure, the other files still run
+        match panic::catch_unwind(|| run_file(path)) {
+            Ok(Ok(cases)) => passed += cases,
+            Ok(Err(error)) => failures.push(format!("{}: {}", name, error)),
+            Err(_) => failures.push(format!("{}: panicked", name)),
+        }
+    }
+    println!(
+        "{} cases passed, {}/{} opcodes failed",
+        passed,
+        failures.len(),
+        files.len()
+    );
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn it_should_run_a_case_on_flat_memory() {
+    let json = r#"{
+        "name": "3e 0000",
+        "initial": {"pc": 49152, "sp": 65534, "a": 0, "b": 1, "c": 2, "d": 3, "e": 4,
+                    "f": 176, "h": 5, "l": 6, "ime": 0, "ram": [[49152, 62], [49153, 66]]},
+        "final": {"pc": 49154, "sp": 65534, "a": 66, "b": 1, "c": 2, "d": 3, "e": 4,
+                  "f": 176, "h": 5, "l": 6, "ime": 0, "ram": [[49152, 62], [49153, 66]]},
+        "cycles": [[49152, 62, "r-m"], [49153, 66, "r-m"]]
+    }"#;
+    let case: Case = serde_json::from_str(json).unwrap();
+    let mut cpu = Cpu::new();
+    cpu.memory = MemoryBus::flat();
+
+    assert_eq!(run_case(&mut cpu, &case), Ok(()));
+}
+
+#[test]
+fn it_should_compare_bus_activity() {
+    let json = r#"{
+        "name": "77 0000",
+        "initial": {"pc": 49152, "sp": 65534, "a": 66, "b": 1, "c": 2, "d": 3, "e": 4,
+                    "f": 176, "h": 208, "l": 0, "ime": 0, "ram": [[49152, 119]]},
+        "final": {"pc": 49153, "sp": 65534, "a": 66, "b": 1, "c": 2, "d": 3, "e": 4,
+                  "f": 176, "h": 208, "l": 0, "ime": 0, "ram": [[49152, 119], [53248, 66]]},
+        "cycles": [[49152, 119, "r-m"], [53248, 66, "-wm"]]
+    }"#;
+    let mut case: Case = serde_json::from_str(json).unwrap();
+    let mut cpu = Cpu::new();
+    cpu.memory = MemoryBus::flat();
+    assert_eq!(run_case(&mut cpu, &case), Ok(()));
+
+    case.cycles[1] = serde_json::json!([53249, 66, "-wm"]);
+    assert_eq!(
+        run_case(&mut cpu, &case),
+        Err("bus access 1 was a write 0x42 at 0xd000, expected a write 0x42 at 0xd001".to_string())
+    );
+}
+
+#[test]
+fn it_should_fetch_operands_across_the_end_of_memory() {
+    let json = r#"{
+        "name": "3e ffff",
+        "initial": {"pc": 65535, "sp": 65534, "a": 0, "b": 1, "c": 2, "d": 3, "e": 4,
+                    "f": 176, "h": 5, "l": 6, "ime": 0, "ram": [[65535, 62], [0, 66]]},
+        "final": {"pc": 1, "sp": 65534, "a": 66, "b": 1, "c": 2, "d": 3, "e": 4,
+                  "f": 176, "h": 5, "l": 6, "ime": 0, "ram": [[65535, 62], [0, 66]]},
+        "cycles": [[65535, 62, "r-m"], [0, 66, "r-m"]]
+    }"#;
+    let case: Case = serde_json::from_str(json).unwrap();
+    let mut cpu = Cpu::new();
+    cpu.memory = MemoryBus::flat();
+
+    assert_eq!(run_case(&mut cpu, &case), Ok(()));
+}
@@ -0,0 +1,7 @@
+pub mod compatibility;
+pub mod display;
+pub mod hdma;
+pub mod palette;
+pub mod ppu;
+pub mod render;
+pub mod screenshot;
@@ -0,0 +1,315 @@
+#![allow(dead_code)]
+use crate::cartridge::Cartridge;
+
+// Background, OBJ0 and OBJ1 colors loaded by the CGB boot ROM for a monochrome game
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompatibilityPalette {
+    pub bg: [u16; 4],
+    pub obj0: [u16; 4],
+    pub obj1: [u16; 4],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaletteCombo {
+    Up,
+    UpA,
+    UpB,
+    Left,
+    LeftA,
+    LeftB,
+    Down,
+    DownA,
+    DownB,
+    Right,
+    RightA,
+    RightB,
+}
+
+// Title checksums the boot ROM knows, in the order it searches them
+const TITLE_CHECKSUMS: [u8; 94] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+    0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+];
+
+// From here on checksums repeat, so the 4th letter of the title has to match as well
+const FIRST_CHECKSUM_WITH_DUPLICATE: usize = 65;
+const FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+// Combination used for each checksum above, the first one is for unknown games
+const PALETTE_PER_CHECKSUM: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44, 21, 32, 31, 20, 5, 33, 13, 14, 5, 29,
+    5, 18, 9, 3, 2, 26, 25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34, 5, 42, 6,
+    5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0, 39, 36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39,
+    24, 31, 50, 17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 19, 34, 23, 18, 29,
+];
+
+const fn palettes(obj0: usize, obj1: usize, bg: usize) -> [usize; 3] {
+    [obj0 * 4, obj1 * 4, bg * 4]
+}
+
+// OBJ0, OBJ1 and background of each combination as offsets into COLORS. Most start
+// on a palette, a few start mid-palette and borrow colors from the next one
+const COMBINATIONS: [[usize; 3]; 51] = [
+    palettes(4, 4, 29),
+    palettes(18, 18, 18),
+    palettes(20, 20, 20),
+    palettes(24, 24, 24),
+    palettes(9, 9, 9),
+    palettes(0, 0, 0),
+    palettes(27, 27, 27),
+    palettes(5, 5, 5),
+    palettes(12, 12, 12),
+    palettes(26, 26, 26),
+    palettes(16, 8, 8),
+    palettes(4, 28, 28),
+    palettes(4, 2, 2),
+    palettes(3, 4, 4),
+    palettes(4, 29, 29),
+    palettes(28, 4, 28),
+    palettes(2, 17, 2),
+    palettes(16, 16, 8),
+    palettes(4, 4, 7),
+    palettes(4, 4, 18),
+    palettes(4, 4, 20),
+    palettes(19, 19, 9),
+    [4 * 4 - 1, 4 * 4 - 1, 11 * 4],
+    palettes(17, 17, 2),
+    palettes(4, 4, 2),
+    palettes(4, 4, 3),
+    palettes(28, 28, 0),
+    palettes(3, 3, 0),
+    palettes(0, 0, 1),
+    palettes(18, 22, 18),
+    palettes(20, 22, 20),
+    palettes(24, 22, 24),
+    palettes(16, 22, 8),
+    palettes(17, 4, 13),
+    [28 * 4 - 1, 0, 14 * 4],
+    [28 * 4 - 1, 4 * 4, 15 * 4],
+    [19 * 4, 23 * 4 - 1, 9 * 4],
+    palettes(16, 28, 10),
+    palettes(4, 23, 28),
+    palettes(17, 22, 2),
+    palettes(4, 0, 2),
+    palettes(4, 28, 3),
+    palettes(28, 3, 0),
+    palettes(3, 28, 4),
+    palettes(21, 28, 4),
+    palettes(3, 28, 0),
+    palettes(25, 3, 28),
+    palettes(0, 28, 8),
+    palettes(4, 3, 28),
+    palettes(28, 3, 6),
+    palettes(4, 28, 29),
+];
+
+// The boot ROM's 30 palettes of four RGB555 colors, back to back
+const COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, 0x639F, 0x4279, 0x15B0, 0x04CB, 0x7FFF, 0x6E31, 0x454A, 0x0000,
+    0x7FFF, 0x1BEF, 0x0200, 0x0000, 0x7FFF, 0x421F, 0x1CF2, 0x0000, 0x7FFF, 0x5294, 0x294A, 0x0000,
+    0x7FFF, 0x03FF, 0x012F, 0x0000, 0x7FFF, 0x03EF, 0x01D6, 0x0000, 0x7FFF, 0x42B5, 0x3DC8, 0x0000,
+    0x7E74, 0x03FF, 0x0180, 0x0000, 0x67FF, 0x77AC, 0x1A13, 0x2D6B, 0x7ED6, 0x4BFF, 0x2175, 0x0000,
+    0x53FF, 0x4A5F, 0x7E52, 0x0000, 0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0, 0x03ED, 0x7FFF, 0x255F, 0x0000,
+    0x036A, 0x021F, 0x03FF, 0x7FFF, 0x7FFF, 0x01DF, 0x0112, 0x0000, 0x231F, 0x035F, 0x00F2, 0x0009,
+    0x7FFF, 0x03EA, 0x011F, 0x0000, 0x299F, 0x001A, 0x000C, 0x0000, 0x7FFF, 0x027F, 0x001F, 0x0000,
+    0x7FFF, 0x03E0, 0x0206, 0x0120, 0x7FFF, 0x7EEB, 0x001F, 0x7C00, 0x7FFF, 0x3FFF, 0x7E00, 0x001F,
+    0x7FFF, 0x03FF, 0x001F, 0x0000, 0x03FF, 0x001F, 0x000C, 0x0000, 0x7FFF, 0x033F, 0x0193, 0x0000,
+    0x0000, 0x4200, 0x037F, 0x7FFF, 0x7FFF, 0x7E8C, 0x7C00, 0x0000, 0x7FFF, 0x1BEF, 0x6180, 0x0000,
+];
+
+const fn colors(offset: usize) -> [u16; 4] {
+    [
+        COLORS[offset],
+        COLORS[offset + 1],
+        COLORS[offset + 2],
+        COLORS[offset + 3],
+    ]
+}
+
+const fn combination(index: usize) -> CompatibilityPalette {
+    let [obj0, obj1, bg] = COMBINATIONS[index];
+    CompatibilityPalette {
+        bg: colors(bg),
+        obj0: colors(obj0),
+        obj1: colors(obj1),
+    }
+}
+
+// Used for every game the boot ROM does not recognize, same colors as Right + A
+pub const DEFAULT_PALETTE: CompatibilityPalette = combination(0);
+
+impl PaletteCombo {
+    pub fn from_name(name: &str) -> Option<PaletteCombo> {
+        match name.to_lowercase().as_str() {
+            "up" => Some(PaletteCombo::Up),
+            "up+a" => Some(PaletteCombo::UpA),
+            "up+b" => Some(PaletteCombo::UpB),
+            "left" => Some(PaletteCombo::Left),
+            "left+a" => Some(PaletteCombo::LeftA),
+            "left+b" => Some(PaletteCombo::LeftB),
+            "down" => Some(PaletteCombo::Down),
+            "down+a" => Some(PaletteCombo::DownA),
+            "down+b" => Some(PaletteCombo::DownB),
+            "right" => Some(PaletteCombo::Right),
+            "right+a" => Some(PaletteCombo::RightA),
+            "right+b" => Some(PaletteCombo::RightB),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PaletteCombo::Up => "up",
+            PaletteCombo::UpA => "up+a",
+            PaletteCombo::UpB => "up+b",
+            PaletteCombo::Left => "left",
+            PaletteCombo::LeftA => "left+a",
+            PaletteCombo::LeftB => "left+b",
+            PaletteCombo::Down => "down",
+            PaletteCombo::DownA => "down+a",
+            PaletteCombo::DownB => "down+b",
+            PaletteCombo::Right => "right",
+            PaletteCombo::RightA => "right+a",
+            PaletteCombo::RightB => "right+b",
+        }
+    }
+
+    pub fn palette(&self) -> CompatibilityPalette {
+        let index = match self {
+            PaletteCombo::Up => 5,
+            PaletteCombo::UpA => 43,
+            PaletteCombo::UpB => 28,
+            PaletteCombo::Left => 48,
+            PaletteCombo::LeftA => 40,
+            PaletteCombo::LeftB => 7,
+            PaletteCombo::Down => 8,
+            PaletteCombo::DownA => 3,
+            PaletteCombo::DownB => 49,
+            PaletteCombo::Right => 1,
+            PaletteCombo::RightA => 0,
+            PaletteCombo::RightB => 6,
+        };
+        combination(index)
+    }
+}
+
+pub fn title_checksum(title: &[u8]) -> u8 {
+    title.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+// Mirrors the boot ROM lookup, a held button combo always takes precedence
+pub fn select_palette(cartridge: &Cartridge, combo: Option<PaletteCombo>) -> CompatibilityPalette {
+    if let Some(combo) = combo {
+        return combo.palette();
+    }
+    if !cartridge.is_nintendo_licensed() {
+        return DEFAULT_PALETTE;
+    }
+    let title = cartridge.title_bytes();
+    let checksum = title_checksum(&title);
+    TITLE_CHECKSUMS
+        .iter()
+        .enumerate()
+        .position(|(index, known)| {
+            *known == checksum
+                && (index < FIRST_CHECKSUM_WITH_DUPLICATE
+                    || FOURTH_LETTERS[index - FIRST_CHECKSUM_WITH_DUPLICATE] == title[3])
+        })
+        .map_or(DEFAULT_PALETTE, |index| {
+            combination(PALETTE_PER_CHECKSUM[index] as usize)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(title: &str, old_licensee: u8, new_licensee: &[u8; 2]) -> Cartridge {
+        let mut rom = vec![0; 0x8000];
+        rom[0x134..0x134 + title.len()].copy_from_slice(title.as_bytes());
+        rom[0x144..0x146].copy_from_slice(new_licensee);
+        rom[0x14B] = old_licensee;
+        Cartridge::new(rom)
+    }
+
+    const RED: [u16; 4] = [0x7FFF, 0x421F, 0x1CF2, 0x0000];
+    const GREEN: [u16; 4] = [0x7FFF, 0x1BEF, 0x0200, 0x0000];
+    const LIGHT_BLUE: [u16; 4] = [0x7FFF, 0x7E8C, 0x7C00, 0x0000];
+    const GRAYSCALE: [u16; 4] = [0x7FFF, 0x5294, 0x294A, 0x0000];
+
+    #[test]
+    fn it_should_pick_game_palette_from_title_checksum() {
+        let cartridge = rom("POKEMON RED", 0x33, b"01");
+        let selected = select_palette(&cartridge, None);
+
+        assert_eq!(selected.bg, RED);
+        assert_eq!(selected.obj0, GREEN);
+        assert_eq!(selected.obj1, RED);
+    }
+
+    #[test]
+    fn it_should_tell_colliding_checksums_apart_by_the_fourth_letter() {
+        let mario = rom("SUPER MARIOLAND", 0x01, b"00");
+        // Same letters, so the same checksum, but an R in 4th place
+        let swapped = rom("SUPRE MARIOLAND", 0x01, b"00");
+        let unknown = rom("SUPRE MARIOLANE", 0x01, b"00");
+
+        assert_eq!(title_checksum(&mario.title_bytes()), 0x46);
+        assert_eq!(select_palette(&mario, None), combination(22));
+        assert_eq!(select_palette(&swapped, None), combination(46));
+        assert_eq!(select_palette(&unknown, None), DEFAULT_PALETTE);
+    }
+
+    #[test]
+    fn it_should_borrow_colors_from_the_next_palette() {
+        let selected = combination(22);
+
+        assert_eq!(selected.obj0, [0x0000, 0x7FFF, 0x421F, 0x1CF2]);
+        assert_eq!(selected.bg, [0x7ED6, 0x4BFF, 0x2175, 0x0000]);
+    }
+
+    #[test]
+    fn it_should_use_default_palette_for_unknown_titles() {
+        let cartridge = rom("HOMEBREW GAME", 0x01, b"00");
+
+        assert_eq!(select_palette(&cartridge, None), DEFAULT_PALETTE);
+    }
+
+    #[test]
+    fn it_should_use_default_palette_for_other_licensees() {
+        let cartridge = rom("POKEMON RED", 0x33, b"08");
+
+        assert_eq!(select_palette(&cartridge, None), DEFAULT_PALETTE);
+    }
+
+    #[test]
+    fn it_should_let_button_combo_override_game_palette() {
+        let cartridge = rom("POKEMON RED", 0x01, b"00");
+        let selected = select_palette(&cartridge, Some(PaletteCombo::LeftB));
+
+        assert_eq!(selected.bg, GRAYSCALE);
+    }
+
+    #[test]
+    fn it_should_load_the_boot_rom_combo_for_up_a() {
+        let selected = PaletteCombo::UpA.palette();
+
+        assert_eq!(selected.bg, RED);
+        assert_eq!(selected.obj0, GREEN);
+        assert_eq!(selected.obj1, LIGHT_BLUE);
+    }
+
+    #[test]
+    fn it_should_parse_combo_names() {
+        assert_eq!(PaletteCombo::from_name("Down+B"), Some(PaletteCombo::DownB));
+        assert_eq!(PaletteCombo::from_name("sideways"), None);
+        assert_eq!(
+            PaletteCombo::from_name(PaletteCombo::UpA.name()),
+            Some(PaletteCombo::UpA)
+        );
+    }
+}
@@ -0,0 +1,145 @@
+#![allow(dead_code)]
+use std::sync::Arc;
+
+use gl::types::{GLint, GLsizei, GLuint};
+use glutin_window::GlutinWindow;
+use piston::window::OpenGLWindow;
+use winit::window::Window;
+
+use crate::frontend::Display;
+
+// Draws frames into a Glutin window: the pixels go to a texture that is blitted onto
+// the window, scaled without filtering and letterboxed to keep the aspect ratio
+pub struct GlDisplay {
+    window: Arc<Window>,
+    texture: GLuint,
+    framebuffer: GLuint,
+    size: (u32, u32),
+}
+
+impl GlDisplay {
+    pub fn new(window: &mut GlutinWindow) -> Result<GlDisplay, String> {
+        gl::load_with(|name| window.get_proc_address(name) as *const _);
+        // Blitting needs OpenGL 3.0 or GLES 3.0, the window may fall back to older ones
+        if !gl::BlitFramebuffer::is_loaded() || !gl::GenFramebuffers::is_loaded() {
+            return Err("the OpenGL context cannot blit framebuffers".to_string());
+        }
+        let (mut texture, mut framebuffer) = (0, 0);
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+            gl::GenFramebuffers(1, &mut framebuffer);
+        }
+        Ok(GlDisplay {
+            window: window.get_window(),
+            texture,
+            framebuffer,
+            size: (0, 0),
+        })
+    }
+}
+
+impl Display for GlDisplay {
+    fn present(&mut self, pixels: &[u8], width: u32, height: u32) {
+        let target = self.window.inner_size();
+        let [x0, y0, x1, y1] = fit((width, height), (target.width, target.height));
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+            if self.size != (width, height) {
+                // A new size needs new storage, which has to be attached again
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    gl::RGBA8 as GLint,
+                    width as GLsizei,
+                    height as GLsizei,
+                    0,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    pixels.as_ptr().cast(),
+                );
+                gl::FramebufferTexture2D(
+                    gl::READ_FRAMEBUFFER,
+                    gl::COLOR_ATTACHMENT0,
+                    gl::TEXTURE_2D,
+                    self.texture,
+                    0,
+                );
+                self.size = (width, height);
+            } else {
+                gl::TexSubImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    0,
+                    0,
+                    width as GLsizei,
+                    height as GLsizei,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    pixels.as_ptr().cast(),
+                );
+            }
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, target.width as GLsizei, target.height as GLsizei);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            // The first row of pixels is the top of the screen, OpenGL counts from the
+            // bottom so the destination is flipped
+            gl::BlitFramebuffer(
+                0,
+                0,
+                width as GLint,
+                height as GLint,
+                x0,
+                y1,
+                x1,
+                y0,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        }
+    }
+}
+
+impl Drop for GlDisplay {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+// Largest rectangle with the frame's aspect ratio centered in the window, as x0, y0,
+// x1, y1 from the bottom left
+fn fit(frame: (u32, u32), window: (u32, u32)) -> [GLint; 4] {
+    let scale = f64::min(
+        window.0 as f64 / frame.0.max(1) as f64,
+        window.1 as f64 / frame.1.max(1) as f64,
+    );
+    let width = (frame.0 as f64 * scale).round() as GLint;
+    let height = (frame.1 as f64 * scale).round() as GLint;
+    let x = (window.0 as GLint - width) / 2;
+    let y = (window.1 as GLint - height) / 2;
+    [x, y, x + width, y + height]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_fill_a_window_of_the_same_shape() {
+        assert_eq!(fit((320, 288), (640, 576)), [0, 0, 640, 576]);
+    }
+
+    #[test]
+    fn it_should_letterbox_a_window_of_another_shape() {
+        assert_eq!(fit((160, 144), (800, 576)), [80, 0, 720, 576]);
+        assert_eq!(fit((160, 144), (320, 600)), [0, 156, 320, 444]);
+    }
+}
@@ -0,0 +1,208 @@
+#![allow(dead_code)]
+use crate::processor::memorybus::MemoryBus;
+
+pub const BLOCK_SIZE: usize = 0x10;
+
+// A block takes 8 M-cycles at normal speed, twice as many CPU cycles at double speed
+const BLOCK_CYCLES: u32 = 32;
+
+#[derive(Debug, Clone)]
+pub struct Hdma {
+    pub source: u16,
+    pub destination: u16,
+    pub remaining: u8,
+    pub hblank_active: bool,
+}
+
+impl Hdma {
+    pub fn new() -> Hdma {
+        Hdma {
+            source: 0,
+            destination: 0,
+            remaining: 0,
+            hblank_active: false,
+        }
+    }
+
+    pub fn read_status(&self) -> u8 {
+        let length = self.remaining.wrapping_sub(1) & 0x7F;
+        if self.hblank_active {
+            length
+        } else {
+            0x80 | length
+        }
+    }
+
+    pub fn write_source_high(&mut self, value: u8) {
+        self.source = (self.source & 0x00F0) | ((value as u16) << 8);
+    }
+
+    pub fn write_source_low(&mut self, value: u8) {
+        self.source = (self.source & 0xFF00) | (value & 0xF0) as u16;
+    }
+
+    // The destination is always in VRAM, only bits 4-12 are used
+    pub fn write_destination_high(&mut self, value: u8) {
+        self.destination = (self.destination & 0x00F0) | (((value & 0x1F) as u16) << 8);
+    }
+
+    pub fn write_destination_low(&mut self, value: u8) {
+        self.destination = (self.destination & 0x1F00) | (value & 0xF0) as u16;
+    }
+}
+
+impl MemoryBus {
+    pub fn write_hdma_control(&mut self, value: u8) {
+        if self.hdma.hblank_active && value & 0x80 == 0 {
+            self.hdma.hblank_active = false;
+            return;
+        }
+        self.hdma.remaining = (value & 0x7F) + 1;
+        if value & 0x80 == 0x80 {
+            self.hdma.hblank_active = true;
+        } else {
+            while self.hdma.remaining > 0 {
+                self.transfer_hdma_block();
+            }
+        }
+    }
+
+    pub fn hblank_dma(&mut self) {
+        if self.hdma.hblank_active {
+            self.transfer_hdma_block();
+            if self.hdma.remaining == 0 {
+                self.hdma.hblank_active = false;
+            }
+        }
+    }
+
+    fn transfer_hdma_block(&mut self) {
+        for _ in 0..BLOCK_SIZE {
+            let value = self.read_hdma_source(self.hdma.source as usize);
+            let destination = 0x8000 | (self.hdma.destination as usize & 0x1FFF);
+            self.ppu.write_vram(destination, value);
+            self.hdma.source = self.hdma.source.wrapping_add(1);
+            self.hdma.destination = self.hdma.destination.wrapping_add(1) & 0x1FFF;
+        }
+        self.hdma.remaining -= 1;
+        self.stall_cycles += if self.double_speed {
+            BLOCK_CYCLES * 2
+        } else {
+            BLOCK_CYCLES
+        };
+    }
+
+    // VRAM cannot be a source, and 0xE000-0xFFFF reads from external RAM. The copy is
+    // not a CPU read, so watchpoints stay quiet
+    fn read_hdma_source(&self, position: usize) -> u8 {
+        match position {
+            0x8000..=0x9FFF => 0xFF,
+            0xE000..=0xFFFF => self.peek(position - 0x4000),
+            _ => self.peek(position),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Model;
+    use crate::processor::memorybus::MemoryBus;
+    use crate::processor::watch::{Access, Watchpoint};
+
+    fn cgb_bus() -> MemoryBus {
+        let mut memory = MemoryBus::new();
+        memory.set_model(Model::Cgb, true);
+        for offset in 0..0x40 {
+            memory.set_byte(offset as u8, 0xC000 + offset);
+        }
+        memory.set_byte(0xC0, 0xFF51);
+        memory.set_byte(0x00, 0xFF52);
+        memory.set_byte(0x81, 0xFF53);
+        memory.set_byte(0x00, 0xFF54);
+        memory
+    }
+
+    #[test]
+    fn it_should_copy_everything_at_once_with_general_dma() {
+        let mut memory = cgb_bus();
+        memory.set_byte(0x01, 0xFF55);
+
+        assert_eq!(memory.fetch_byte_at(0x8100), 0x00);
+        assert_eq!(memory.fetch_byte_at(0x811F), 0x1F);
+        assert_eq!(memory.fetch_byte_at(0xFF55), 0xFF);
+        assert_eq!(memory.stall_cycles, 64);
+    }
+
+    #[test]
+    fn it_should_copy_one_block_per_hblank() {
+        let mut memory = cgb_bus();
+        memory.set_byte(0x81, 0xFF55);
+        assert_eq!(memory.fetch_byte_at(0xFF55), 0x01);
+
+        memory.hblank_dma();
+        assert_eq!(memory.fetch_byte_at(0x810F), 0x0F);
+        assert_eq!(memory.fetch_byte_at(0x8110), 0x00);
+        assert_eq!(memory.fetch_byte_at(0xFF55), 0x00);
+
+        memory.hblank_dma();
+        assert_eq!(memory.fetch_byte_at(0x811F), 0x1F);
+        assert_eq!(memory.fetch_byte_at(0xFF55), 0xFF);
+    }
+
+    #[test]
+    fn it_should_cancel_hblank_dma() {
+        let mut memory = cgb_bus();
+        memory.set_byte(0x82, 0xFF55);
+        memory.hblank_dma();
+        memory.set_byte(0x00, 0xFF55);
+
+        assert_eq!(memory.fetch_byte_at(0xFF55), 0x81);
+        memory.hblank_dma();
+        assert_eq!(memory.fetch_byte_at(0x8110), 0x00);
+    }
+
+    #[test]
+    fn it_should_transfer_during_hblank_while_ppu_runs() {
+        let mut memory = cgb_bus();
+        memory.set_byte(0x80, 0xFF55);
+        memory.tick(456);
+
+        assert_eq!(memory.fetch_byte_at(0x810F), 0x0F);
+        assert_eq!(memory.fetch_byte_at(0xFF55), 0xFF);
+    }
+
+    #[test]
+    fn it_should_keep_destination_in_vram() {
+        let mut memory = cgb_bus();
+        memory.set_byte(0xFF, 0xFF53);
+        memory.set_byte(0xF0, 0xFF54);
+        memory.set_byte(0x00, 0xFF55);
+
+        assert_eq!(memory.fetch_byte_at(0x9FF0), 0x00);
+        assert_eq!(memory.fetch_byte_at(0x9FFF), 0x0F);
+    }
+
+    #[test]
+    fn it_should_double_stall_in_double_speed_mode() {
+        let mut memory = cgb_bus();
+        memory.double_speed = true;
+        memory.set_byte(0x00, 0xFF55);
+
+        assert_eq!(memory.stall_cycles, 64);
+    }
+
+    #[test]
+    fn it_should_copy_without_triggering_watchpoints() {
+        let mut memory = cgb_bus();
+        memory.watchpoints.list.push(Watchpoint {
+            id: 1,
+            start: 0xC000,
+            end: 0xC03F,
+            access: Access::Read,
+        });
+        memory.set_byte(0x01, 0xFF55);
+
+        assert_eq!(memory.fetch_byte_at(0x811F), 0x1F);
+        assert_eq!(memory.watchpoints.take_hit(), None);
+    }
+}
@@ -0,0 +1,112 @@
+#![allow(dead_code)]
+
+pub const PALETTE_RAM_SIZE: usize = 64;
+
+// Shades used when the game runs on a DMG, white to black, as 15-bit RGB
+pub const DMG_SHADES: [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+
+#[derive(Debug, Clone)]
+pub struct ColorPalette {
+    pub data: [u8; PALETTE_RAM_SIZE],
+    pub index: u8,
+    pub auto_increment: bool,
+}
+
+impl ColorPalette {
+    pub fn new() -> ColorPalette {
+        ColorPalette {
+            data: [0xFF; PALETTE_RAM_SIZE],
+            index: 0,
+            auto_increment: false,
+        }
+    }
+
+    pub fn read_spec(&self) -> u8 {
+        let increment = if self.auto_increment { 0x80 } else { 0 };
+        increment | 0x40 | self.index
+    }
+
+    pub fn write_spec(&mut self, value: u8) {
+        self.index = value & 0x3F;
+        self.auto_increment = value & 0x80 == 0x80;
+    }
+
+    pub fn read_data(&self) -> u8 {
+        self.data[self.index as usize]
+    }
+
+    pub fn write_data(&mut self, value: u8) {
+        self.data[self.index as usize] = value;
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0x3F;
+        }
+    }
+
+    pub fn color(&self, palette: usize, color_index: usize) -> u16 {
+        let position = palette * 8 + color_index * 2;
+        let low = self.data[position] as u16;
+        let high = self.data[position + 1] as u16;
+        ((high << 8) | low) & 0x7FFF
+    }
+
+    pub fn set_color(&mut self, palette: usize, color_index: usize, color: u16) {
+        let position = palette * 8 + color_index * 2;
+        self.data[position] = (color & 0xFF) as u8;
+        self.data[position + 1] = ((color >> 8) & 0x7F) as u8;
+    }
+}
+
+// Maps a color index through a DMG palette register (BGP, OBP0, OBP1)
+pub fn dmg_shade(palette: u8, color_index: u8) -> u8 {
+    (palette >> (color_index * 2)) & 0x3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_auto_increment_index_on_data_write() {
+        let mut palette = ColorPalette::new();
+        palette.write_spec(0x80 | 0x02);
+        palette.write_data(0x1F);
+        palette.write_data(0x00);
+
+        assert_eq!(palette.index, 0x04);
+        assert_eq!(palette.color(0, 1), 0x001F);
+    }
+
+    #[test]
+    fn it_should_not_increment_index_without_auto_increment() {
+        let mut palette = ColorPalette::new();
+        palette.write_spec(0x05);
+        palette.write_data(0x12);
+
+        assert_eq!(palette.index, 0x05);
+        assert_eq!(palette.read_data(), 0x12);
+    }
+
+    #[test]
+    fn it_should_wrap_index_after_last_byte() {
+        let mut palette = ColorPalette::new();
+        palette.write_spec(0x80 | 0x3F);
+        palette.write_data(0x00);
+
+        assert_eq!(palette.index, 0x00);
+    }
+
+    #[test]
+    fn it_should_read_spec_with_unused_bit_set() {
+        let mut palette = ColorPalette::new();
+        palette.write_spec(0x81);
+
+        assert_eq!(palette.read_spec(), 0xC1);
+    }
+
+    #[test]
+    fn it_should_map_color_index_through_dmg_palette() {
+        assert_eq!(dmg_shade(0b1110_0100, 0), 0);
+        assert_eq!(dmg_shade(0b1110_0100, 3), 3);
+        assert_eq!(dmg_shade(0b0001_1011, 0), 3);
+    }
+}
@@ -0,0 +1,337 @@
+#![allow(dead_code)]
+use crate::model::Model;
+use crate::video::palette::{ColorPalette, DMG_SHADES};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+pub const VRAM_BANK_SIZE: usize = 0x2000;
+pub const OAM_SIZE: usize = 0xA0;
+
+pub const VBLANK_INTERRUPT: u8 = 0b0000_0001;
+pub const STAT_INTERRUPT: u8 = 0b0000_0010;
+
+pub const LCDC_BG_ENABLE: u8 = 0b0000_0001;
+pub const LCDC_OBJ_ENABLE: u8 = 0b0000_0010;
+pub const LCDC_OBJ_SIZE: u8 = 0b0000_0100;
+pub const LCDC_BG_MAP: u8 = 0b0000_1000;
+pub const LCDC_TILE_DATA: u8 = 0b0001_0000;
+pub const LCDC_WINDOW_ENABLE: u8 = 0b0010_0000;
+pub const LCDC_WINDOW_MAP: u8 = 0b0100_0000;
+pub const LCDC_LCD_ENABLE: u8 = 0b1000_0000;
+
+const OAM_SCAN_DOTS: u32 = 80;
+const DRAWING_END_DOTS: u32 = 252;
+const DOTS_PER_LINE: u32 = 456;
+const VBLANK_LINE: u8 = 144;
+const LINES_PER_FRAME: u8 = 154;
+
+const STAT_HBLANK_SOURCE: u8 = 0b0000_1000;
+const STAT_VBLANK_SOURCE: u8 = 0b0001_0000;
+const STAT_OAM_SOURCE: u8 = 0b0010_0000;
+const STAT_LYC_SOURCE: u8 = 0b0100_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ppu {
+    pub vram: Vec<u8>,
+    pub vram_bank: usize,
+    pub oam: [u8; OAM_SIZE],
+    pub lcdc: u8,
+    pub stat: u8,
+    pub scy: u8,
+    pub scx: u8,
+    pub ly: u8,
+    pub lyc: u8,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    pub wy: u8,
+    pub wx: u8,
+    pub opri: u8,
+    pub window_line: u8,
+    pub mode: Mode,
+    pub dots: u32,
+    pub bg_palette: ColorPalette,
+    pub obj_palette: ColorPalette,
+    pub model: Model,
+    pub cgb_mode: bool,
+    pub framebuffer: Vec<u16>,
+    pub shades: Vec<u8>,
+    pub frame_ready: bool,
+    pub hblank_entered: bool,
+}
+
+impl Ppu {
+    pub fn new(model: Model, cgb_mode: bool) -> Ppu {
+        let mut ppu = Ppu {
+            vram: vec![0; VRAM_BANK_SIZE * 2],
+            vram_bank: 0,
+            oam: [0; OAM_SIZE],
+            lcdc: 0x91,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0xFC,
+            obp0: 0xFF,
+            obp1: 0xFF,
+            wy: 0,
+            wx: 0,
+            opri: if cgb_mode { 0 } else { 1 },
+            window_line: 0,
+            mode: Mode::OamScan,
+            dots: 0,
+            bg_palette: ColorPalette::new(),
+            obj_palette: ColorPalette::new(),
+            model,
+            cgb_mode,
+            framebuffer: vec![DMG_SHADES[0]; SCREEN_WIDTH * SCREEN_HEIGHT],
+            shades: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame_ready: false,
+            hblank_entered: false,
+        };
+        if model.is_cgb() && !cgb_mode {
+            ppu.set_compatibility_palettes(&DMG_SHADES, &DMG_SHADES, &DMG_SHADES);
+        }
+        ppu
+    }
+
+    // Monochrome games on CGB hardware render through BG palette 0 and OBJ palettes 0 and 1
+    pub fn set_compatibility_palettes(&mut self, bg: &[u16; 4], obj0: &[u16; 4], obj1: &[u16; 4]) {
+        for color_index in 0..4 {
+            self.bg_palette.set_color(0, color_index, bg[color_index]);
+            self.obj_palette
+                .set_color(0, color_index, obj0[color_index]);
+            self.obj_palette
+                .set_color(1, color_index, obj1[color_index]);
+        }
+    }
+
+    pub fn is_lcd_on(&self) -> bool {
+        self.lcdc & LCDC_LCD_ENABLE == LCDC_LCD_ENABLE
+    }
+
+    pub fn read_vram(&self, address: usize) -> u8 {
+        self.vram[self.vram_bank * VRAM_BANK_SIZE + address - 0x8000]
+    }
+
+    pub fn write_vram(&mut self, address: usize, value: u8) {
+        self.vram[self.vram_bank * VRAM_BANK_SIZE + address - 0x8000] = value;
+    }
+
+    pub fn read_oam(&self, address: usize) -> u8 {
+        self.oam[address - 0xFE00]
+    }
+
+    pub fn write_oam(&mut self, address: usize, value: u8) {
+        self.oam[address - 0xFE00] = value;
+    }
+
+    pub fn read_register(&self, address: usize) -> u8 {
+        match address {
+            0xFF40 => self.lcdc,
+            0xFF41 => 0x80 | self.stat | self.coincidence_bit() | self.mode as u8,
+            0xFF42 => self.scy,
+            0xFF43 => self.scx,
+            0xFF44 => self.ly,
+            0xFF45 => self.lyc,
+            0xFF47 => self.bgp,
+            0xFF48 => self.obp0,
+            0xFF49 => self.obp1,
+            0xFF4A => self.wy,
+            0xFF4B => self.wx,
+            0xFF4F => 0xFE | self.vram_bank as u8,
+            0xFF68 => self.bg_palette.read_spec(),
+            0xFF69 => self.bg_palette.read_data(),
+            0xFF6A => self.obj_palette.read_spec(),
+            0xFF6B => self.obj_palette.read_data(),
+            0xFF6C => 0xFE | self.opri,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, address: usize, value: u8) {
+        match address {
+            0xFF40 => self.write_lcdc(value),
+            0xFF41 => self.stat = value & 0b0111_1000,
+            0xFF42 => self.scy = value,
+            0xFF43 => self.scx = value,
+            0xFF44 => {}
+            0xFF45 => self.lyc = value,
+            0xFF47 => self.bgp = value,
+            0xFF48 => self.obp0 = value,
+            0xFF49 => self.obp1 = value,
+            0xFF4A => self.wy = value,
+            0xFF4B => self.wx = value,
+            0xFF4F => self.vram_bank = (value & 0x1) as usize,
+            0xFF68 => self.bg_palette.write_spec(value),
+            0xFF69 => self.bg_palette.write_data(value),
+            0xFF6A => self.obj_palette.write_spec(value),
+            0xFF6B => self.obj_palette.write_data(value),
+            0xFF6C => self.opri = value & 0x1,
+            _ => {}
+        }
+    }
+
+    fn write_lcdc(&mut self, value: u8) {
+        let was_on = self.is_lcd_on();
+        self.lcdc = value;
+        if was_on && !self.is_lcd_on() {
+            self.ly = 0;
+            self.dots = 0;
+            self.window_line = 0;
+            self.mode = Mode::HBlank;
+        } else if !was_on && self.is_lcd_on() {
+            self.mode = Mode::OamScan;
+        }
+    }
+
+    fn coincidence_bit(&self) -> u8 {
+        if self.ly == self.lyc {
+            0b0000_0100
+        } else {
+            0
+        }
+    }
+
+    // Advances the PPU by the given number of dots and returns the interrupts it requests
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        let mut interrupts = 0;
+        if !self.is_lcd_on() {
+            return interrupts;
+        }
+        for _ in 0..cycles {
+            interrupts |= self.tick_dot();
+        }
+        interrupts
+    }
+
+    fn tick_dot(&mut self) -> u8 {
+        let mut interrupts = 0;
+        self.dots += 1;
+        if self.ly < VBLANK_LINE {
+            if self.dots == OAM_SCAN_DOTS {
+                self.mode = Mode::Drawing;
+            } else if self.dots == DRAWING_END_DOTS {
+                self.render_scanline();
+                self.hblank_entered = true;
+                interrupts |= self.set_mode(Mode::HBlank);
+            }
+        }
+        if self.dots == DOTS_PER_LINE {
+            self.dots = 0;
+            self.ly += 1;
+            if self.ly == LINES_PER_FRAME {
+                self.ly = 0;
+                self.window_line = 0;
+            }
+            if self.ly == VBLANK_LINE {
+                self.frame_ready = true;
+                interrupts |= VBLANK_INTERRUPT;
+                interrupts |= self.set_mode(Mode::VBlank);
+            } else if self.ly < VBLANK_LINE {
+                interrupts |= self.set_mode(Mode::OamScan);
+            }
+            if self.ly == self.lyc && self.stat & STAT_LYC_SOURCE != 0 {
+                interrupts |= STAT_INTERRUPT;
+            }
+        }
+        interrupts
+    }
+
+    fn set_mode(&mut self, mode: Mode) -> u8 {
+        self.mode = mode;
+        let source = match mode {
+            Mode::HBlank => STAT_HBLANK_SOURCE,
+            Mode::VBlank => STAT_VBLANK_SOURCE,
+            Mode::OamScan => STAT_OAM_SOURCE,
+            Mode::Drawing => 0,
+        };
+        if self.stat & source != 0 {
+            STAT_INTERRUPT
+        } else {
+            0
+        }
+    }
+
+    pub fn take_hblank(&mut self) -> bool {
+        let entered = self.hblank_entered;
+        self.hblank_entered = false;
+        entered
+    }
+
+    pub fn take_frame(&mut self) -> bool {
+        let ready = self.frame_ready;
+        self.frame_ready = false;
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOTS_PER_FRAME: u32 = 70224;
+
+    #[test]
+    fn it_should_select_vram_bank_in_cgb_mode() {
+        let mut ppu = Ppu::new(Model::Cgb, true);
+        ppu.write_vram(0x8000, 0x11);
+        ppu.write_register(0xFF4F, 0x01);
+        ppu.write_vram(0x8000, 0x22);
+
+        assert_eq!(ppu.read_vram(0x8000), 0x22);
+        assert_eq!(ppu.read_register(0xFF4F), 0xFF);
+        ppu.write_register(0xFF4F, 0x00);
+        assert_eq!(ppu.read_vram(0x8000), 0x11);
+    }
+
+    #[test]
+    fn it_should_request_vblank_once_per_frame() {
+        let mut ppu = Ppu::new(Model::Dmg, false);
+        let interrupts = ppu.tick(DOTS_PER_FRAME);
+
+        assert_eq!(interrupts & VBLANK_INTERRUPT, VBLANK_INTERRUPT);
+        assert!(ppu.take_frame());
+        assert!(!ppu.take_frame());
+    }
+
+    #[test]
+    fn it_should_go_through_modes_on_a_line() {
+        let mut ppu = Ppu::new(Model::Dmg, false);
+        ppu.tick(OAM_SCAN_DOTS);
+        assert_eq!(ppu.mode, Mode::Drawing);
+        ppu.tick(DRAWING_END_DOTS - OAM_SCAN_DOTS);
+        assert_eq!(ppu.mode, Mode::HBlank);
+        ppu.tick(DOTS_PER_LINE - DRAWING_END_DOTS);
+        assert_eq!(ppu.mode, Mode::OamScan);
+        assert_eq!(ppu.ly, 1);
+    }
+
+    #[test]
+    fn it_should_reset_ly_when_lcd_turns_off() {
+        let mut ppu = Ppu::new(Model::Dmg, false);
+        ppu.tick(DOTS_PER_LINE * 3);
+        ppu.write_register(0xFF40, 0x00);
+
+        assert_eq!(ppu.ly, 0);
+        assert_eq!(ppu.tick(DOTS_PER_FRAME), 0);
+    }
+
+    #[test]
+    fn it_should_load_grey_compatibility_palettes_for_dmg_games_on_cgb() {
+        let ppu = Ppu::new(Model::Cgb, false);
+
+        assert_eq!(ppu.bg_palette.color(0, 0), DMG_SHADES[0]);
+        assert_eq!(ppu.obj_palette.color(1, 3), DMG_SHADES[3]);
+    }
+}
@@ -0,0 +1,393 @@
+use crate::video::palette::{dmg_shade, DMG_SHADES};
+use crate::video::ppu::{
+    Ppu, LCDC_BG_ENABLE, LCDC_BG_MAP, LCDC_OBJ_ENABLE, LCDC_OBJ_SIZE, LCDC_TILE_DATA,
+    LCDC_WINDOW_ENABLE, LCDC_WINDOW_MAP, SCREEN_WIDTH, VRAM_BANK_SIZE,
+};
+
+const MAP_LOW: usize = 0x1800;
+const MAP_HIGH: usize = 0x1C00;
+const MAX_SPRITES_PER_LINE: usize = 10;
+
+const ATTRIBUTE_PALETTE: u8 = 0b0000_0111;
+const ATTRIBUTE_BANK: u8 = 0b0000_1000;
+const ATTRIBUTE_DMG_PALETTE: u8 = 0b0001_0000;
+const ATTRIBUTE_X_FLIP: u8 = 0b0010_0000;
+const ATTRIBUTE_Y_FLIP: u8 = 0b0100_0000;
+const ATTRIBUTE_PRIORITY: u8 = 0b1000_0000;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct BgPixel {
+    color: u8,
+    palette: u8,
+    priority: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sprite {
+    x: i16,
+    y: i16,
+    tile: u8,
+    attributes: u8,
+}
+
+impl Ppu {
+    pub fn render_scanline(&mut self) {
+        let mut line = [BgPixel::default(); SCREEN_WIDTH];
+        // On CGB, clearing LCDC bit 0 only removes the background priority
+        let bg_enabled = self.cgb_mode || self.lcdc & LCDC_BG_ENABLE != 0;
+        if bg_enabled {
+            self.render_background(&mut line);
+            self.render_window(&mut line);
+        }
+
+        let sprites = self.sprites_on_line();
+        let start = self.ly as usize * SCREEN_WIDTH;
+        for (x, bg) in line.iter().enumerate() {
+            let mut color = if bg_enabled {
+                self.background_color(bg)
+            } else {
+                self.blank_color()
+            };
+            let mut shade = if bg_enabled {
+                dmg_shade(self.bgp, bg.color)
+            } else {
+                0
+            };
+            if let Some((sprite, sprite_color)) = self.sprite_pixel(&sprites, x) {
+                if self.sprite_wins(&sprite, bg) {
+                    color = self.sprite_color(&sprite, sprite_color);
+                    shade = self.sprite_shade(&sprite, sprite_color);
+                }
+            }
+            self.framebuffer[start + x] = color;
+            self.shades[start + x] = shade;
+        }
+    }
+
+    fn render_background(&self, line: &mut [BgPixel; SCREEN_WIDTH]) {
+        let map = if self.lcdc & LCDC_BG_MAP != 0 {
+            MAP_HIGH
+        } else {
+            MAP_LOW
+        };
+        let y = self.ly.wrapping_add(self.scy) as usize;
+        for (x, pixel) in line.iter_mut().enumerate() {
+            let map_x = (x as u8).wrapping_add(self.scx) as usize;
+            *pixel = self.map_pixel(map, map_x, y);
+        }
+    }
+
+    fn render_window(&mut self, line: &mut [BgPixel; SCREEN_WIDTH]) {
+        let window_x = self.wx as i16 - 7;
+        if self.lcdc & LCDC_WINDOW_ENABLE == 0 || self.ly < self.wy || window_x >= 160 {
+            return;
+        }
+        let map = if self.lcdc & LCDC_WINDOW_MAP != 0 {
+            MAP_HIGH
+        } else {
+            MAP_LOW
+        };
+        for (x, pixel) in line.iter_mut().enumerate() {
+            let map_x = x as i16 - window_x;
+            if map_x >= 0 {
+                *pixel = self.map_pixel(map, map_x as usize, self.window_line as usize);
+            }
+        }
+        self.window_line += 1;
+    }
+
+    fn map_pixel(&self, map: usize, x: usize, y: usize) -> BgPixel {
+        let map_position = map + (y / 8 % 32) * 32 + (x / 8 % 32);
+        let tile = self.vram[map_position];
+        let attributes = if self.cgb_mode {
+            self.vram[VRAM_BANK_SIZE + map_position]
+        } else {
+            0
+        };
+        let mut row = y % 8;
+        if attributes & ATTRIBUTE_Y_FLIP != 0 {
+            row = 7 - row;
+        }
+        let mut column = x % 8;
+        if attributes & ATTRIBUTE_X_FLIP != 0 {
+            column = 7 - column;
+        }
+        let tile_address = if self.lcdc & LCDC_TILE_DATA != 0 {
+            tile as usize * 16
+        } else {
+            (0x1000 + (tile as i8 as i32) * 16) as usize
+        };
+        let bank = if attributes & ATTRIBUTE_BANK != 0 {
+            VRAM_BANK_SIZE
+        } else {
+            0
+        };
+        BgPixel {
+            color: self.tile_color(bank + tile_address + row * 2, column),
+            palette: attributes & ATTRIBUTE_PALETTE,
+            priority: attributes & ATTRIBUTE_PRIORITY != 0,
+        }
+    }
+
+    fn tile_color(&self, address: usize, column: usize) -> u8 {
+        let low = self.vram[address];
+        let high = self.vram[address + 1];
+        let bit = 7 - column;
+        (((high >> bit) & 0x1) << 1) | ((low >> bit) & 0x1)
+    }
+
+    fn sprite_height(&self) -> i16 {
+        if self.lcdc & LCDC_OBJ_SIZE != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    fn sprites_on_line(&self) -> Vec<Sprite> {
+        if self.lcdc & LCDC_OBJ_ENABLE == 0 {
+            return Vec::new();
+        }
+        let line = self.ly as i16;
+        let height = self.sprite_height();
+        let mut sprites: Vec<Sprite> = self
+            .oam
+            .chunks(4)
+            .map(|entry| Sprite {
+                y: entry[0] as i16 - 16,
+                x: entry[1] as i16 - 8,
+                tile: entry[2],
+                attributes: entry[3],
+            })
+            .filter(|sprite| line >= sprite.y && line < sprite.y + height)
+            .take(MAX_SPRITES_PER_LINE)
+            .collect();
+        // DMG priority goes to the lowest X coordinate, CGB priority to the lowest OAM index
+        if self.opri & 0x1 != 0 {
+            sprites.sort_by_key(|sprite| sprite.x);
+        }
+        sprites
+    }
+
+    fn sprite_pixel(&self, sprites: &[Sprite], x: usize) -> Option<(Sprite, u8)> {
+        let x = x as i16;
+        let height = self.sprite_height();
+        for sprite in sprites {
+            if x < sprite.x || x >= sprite.x + 8 {
+                continue;
+            }
+            let mut column = (x - sprite.x) as usize;
+            if sprite.attributes & ATTRIBUTE_X_FLIP != 0 {
+                column = 7 - column;
+            }
+            let mut row = self.ly as i16 - sprite.y;
+            if sprite.attributes & ATTRIBUTE_Y_FLIP != 0 {
+                row = height - 1 - row;
+            }
+            let tile = if height == 16 {
+                sprite.tile & 0xFE
+            } else {
+                sprite.tile
+            };
+            let bank = if self.cgb_mode && sprite.attributes & ATTRIBUTE_BANK != 0 {
+                VRAM_BANK_SIZE
+            } else {
+                0
+            };
+            let color = self.tile_color(bank + tile as usize * 16 + row as usize * 2, column);
+            if color != 0 {
+                return Some((*sprite, color));
+            }
+        }
+        None
+    }
+
+    fn sprite_wins(&self, sprite: &Sprite, bg: &BgPixel) -> bool {
+        if self.cgb_mode && self.lcdc & LCDC_BG_ENABLE == 0 {
+            return true;
+        }
+        if bg.color == 0 {
+            return true;
+        }
+        let bg_priority = self.cgb_mode && bg.priority;
+        sprite.attributes & ATTRIBUTE_PRIORITY == 0 && !bg_priority
+    }
+
+    fn background_color(&self, pixel: &BgPixel) -> u16 {
+        if self.cgb_mode {
+            return self
+                .bg_palette
+                .color(pixel.palette as usize, pixel.color as usize);
+        }
+        let shade = dmg_shade(self.bgp, pixel.color) as usize;
+        if self.model.is_cgb() {
+            self.bg_palette.color(0, shade)
+        } else {
+            DMG_SHADES[shade]
+        }
+    }
+
+    fn sprite_color(&self, sprite: &Sprite, color: u8) -> u16 {
+        if self.cgb_mode {
+            return self.obj_palette.color(
+                (sprite.attributes & ATTRIBUTE_PALETTE) as usize,
+                color as usize,
+            );
+        }
+        let palette = (sprite.attributes & ATTRIBUTE_DMG_PALETTE != 0) as usize;
+        let shade = self.sprite_shade(sprite, color) as usize;
+        if self.model.is_cgb() {
+            self.obj_palette.color(palette, shade)
+        } else {
+            DMG_SHADES[shade]
+        }
+    }
+
+    fn sprite_shade(&self, sprite: &Sprite, color: u8) -> u8 {
+        let obp = if sprite.attributes & ATTRIBUTE_DMG_PALETTE != 0 {
+            self.obp1
+        } else {
+            self.obp0
+        };
+        dmg_shade(obp, color)
+    }
+
+    fn blank_color(&self) -> u16 {
+        if self.model.is_cgb() {
+            self.bg_palette.color(0, 0)
+        } else {
+            DMG_SHADES[0]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Model;
+    use crate::video::ppu::Ppu;
+
+    fn solid_tile(ppu: &mut Ppu, bank: usize, tile: usize, color: u8) {
+        let low = if color & 0x1 != 0 { 0xFF } else { 0x00 };
+        let high = if color & 0x2 != 0 { 0xFF } else { 0x00 };
+        for row in 0..8 {
+            let address = bank * 0x2000 + tile * 16 + row * 2;
+            ppu.vram[address] = low;
+            ppu.vram[address + 1] = high;
+        }
+    }
+
+    fn place_sprite(ppu: &mut Ppu, index: usize, x: u8, tile: u8, attributes: u8) {
+        ppu.oam[index * 4] = 16;
+        ppu.oam[index * 4 + 1] = x;
+        ppu.oam[index * 4 + 2] = tile;
+        ppu.oam[index * 4 + 3] = attributes;
+    }
+
+    fn cgb_ppu() -> Ppu {
+        let mut ppu = Ppu::new(Model::Cgb, true);
+        ppu.lcdc = 0x93;
+        ppu
+    }
+
+    #[test]
+    fn it_should_render_dmg_background_through_bgp() {
+        let mut ppu = Ppu::new(Model::Dmg, false);
+        solid_tile(&mut ppu, 0, 0, 3);
+        ppu.bgp = 0b1110_0100;
+        ppu.render_scanline();
+
+        assert_eq!(ppu.framebuffer[0], 0x0000);
+        assert_eq!(ppu.shades[0], 3);
+    }
+
+    #[test]
+    fn it_should_render_cgb_background_with_attribute_palette() {
+        let mut ppu = cgb_ppu();
+        solid_tile(&mut ppu, 0, 0, 1);
+        ppu.vram[0x2000 + 0x1800] = 0x02;
+        ppu.bg_palette.set_color(2, 1, 0x001F);
+        ppu.render_scanline();
+
+        assert_eq!(ppu.framebuffer[0], 0x001F);
+        assert_eq!(ppu.framebuffer[8], ppu.bg_palette.color(0, 0));
+    }
+
+    #[test]
+    fn it_should_read_tile_from_vram_bank_1() {
+        let mut ppu = cgb_ppu();
+        solid_tile(&mut ppu, 1, 0, 2);
+        ppu.vram[0x2000 + 0x1800] = 0x08;
+        ppu.bg_palette.set_color(0, 2, 0x03E0);
+        ppu.render_scanline();
+
+        assert_eq!(ppu.framebuffer[0], 0x03E0);
+    }
+
+    #[test]
+    fn it_should_flip_background_tile_horizontally() {
+        let mut ppu = cgb_ppu();
+        ppu.vram[0] = 0x80;
+        ppu.vram[0x2000 + 0x1800] = 0x20;
+        ppu.bg_palette.set_color(0, 0, 0x0000);
+        ppu.bg_palette.set_color(0, 1, 0x7C00);
+        ppu.render_scanline();
+
+        assert_eq!(ppu.framebuffer[0], 0x0000);
+        assert_eq!(ppu.framebuffer[7], 0x7C00);
+    }
+
+    #[test]
+    fn it_should_keep_background_on_top_with_attribute_priority() {
+        let mut ppu = cgb_ppu();
+        solid_tile(&mut ppu, 0, 0, 1);
+        solid_tile(&mut ppu, 0, 1, 3);
+        ppu.vram[0x2000 + 0x1800] = 0x80;
+        ppu.bg_palette.set_color(0, 1, 0x0001);
+        ppu.obj_palette.set_color(0, 3, 0x0002);
+        place_sprite(&mut ppu, 0, 8, 1, 0);
+        ppu.render_scanline();
+
+        assert_eq!(ppu.framebuffer[0], 0x0001);
+    }
+
+    #[test]
+    fn it_should_draw_sprites_on_top_when_bg_master_priority_is_off() {
+        let mut ppu = cgb_ppu();
+        ppu.lcdc &= !0x01;
+        solid_tile(&mut ppu, 0, 0, 1);
+        solid_tile(&mut ppu, 0, 1, 3);
+        ppu.vram[0x2000 + 0x1800] = 0x80;
+        ppu.obj_palette.set_color(0, 3, 0x0002);
+        place_sprite(&mut ppu, 0, 8, 1, 0x80);
+        ppu.render_scanline();
+
+        assert_eq!(ppu.framebuffer[0], 0x0002);
+    }
+
+    #[test]
+    fn it_should_prioritize_sprites_by_oam_index_in_cgb_mode() {
+        let mut ppu = cgb_ppu();
+        solid_tile(&mut ppu, 0, 1, 3);
+        ppu.obj_palette.set_color(0, 3, 0x0001);
+        ppu.obj_palette.set_color(1, 3, 0x0002);
+        place_sprite(&mut ppu, 0, 12, 1, 0x00);
+        place_sprite(&mut ppu, 1, 8, 1, 0x01);
+        ppu.render_scanline();
+
+        assert_eq!(ppu.framebuffer[4], 0x0001);
+    }
+
+    #[test]
+    fn it_should_prioritize_sprites_by_x_coordinate_in_dmg_mode() {
+        let mut ppu = Ppu::new(Model::Dmg, false);
+        ppu.lcdc = 0x93;
+        solid_tile(&mut ppu, 0, 1, 3);
+        solid_tile(&mut ppu, 0, 2, 1);
+        ppu.obp0 = 0b1110_0100;
+        place_sprite(&mut ppu, 0, 12, 1, 0x00);
+        place_sprite(&mut ppu, 1, 8, 2, 0x00);
+        ppu.render_scanline();
+
+        assert_eq!(ppu.framebuffer[4], 0x56B5);
+    }
+}
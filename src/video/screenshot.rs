@@ -0,0 +1,116 @@
+#![allow(dead_code)]
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+
+// An RGBA8 picture of the screen, compared pixel by pixel against reference images
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut encoder =
+            Encoder::new(BufWriter::new(File::create(path)?), self.width, self.height);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_depth(BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+
+    // Any PNG is accepted, palettes, grayscale and 16-bit channels are converted to RGBA8
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+        let mut decoder = Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(io::Error::other)?;
+        let size = reader
+            .output_buffer_size()
+            .ok_or_else(|| io::Error::other("image too large"))?;
+        let mut buffer = vec![0; size];
+        let info = reader.next_frame(&mut buffer).map_err(io::Error::other)?;
+        buffer.truncate(info.buffer_size());
+        let pixels = match info.color_type {
+            ColorType::Rgba => buffer,
+            ColorType::Rgb => expand(&buffer, 3, |pixel| [pixel[0], pixel[1], pixel[2], 0xFF]),
+            ColorType::GrayscaleAlpha => {
+                expand(&buffer, 2, |pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+            }
+            _ => expand(&buffer, 1, |pixel| [pixel[0], pixel[0], pixel[0], 0xFF]),
+        };
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    // Number of pixels that differ, every pixel counts when the sizes do not match
+    pub fn difference(&self, other: &Image) -> usize {
+        if self.width != other.width || self.height != other.height {
+            return (self.width * self.height).max(other.width * other.height) as usize;
+        }
+        self.pixels
+            .chunks_exact(4)
+            .zip(other.pixels.chunks_exact(4))
+            .filter(|(left, right)| left != right)
+            .count()
+    }
+}
+
+fn expand<F: Fn(&[u8]) -> [u8; 4]>(buffer: &[u8], channels: usize, convert: F) -> Vec<u8> {
+    buffer.chunks_exact(channels).flat_map(convert).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(pixels: Vec<u8>) -> Image {
+        Image {
+            width: 2,
+            height: 1,
+            pixels,
+        }
+    }
+
+    #[test]
+    fn it_should_save_and_load_png() {
+        let path = std::env::temp_dir().join(format!("screen-{}.png", std::process::id()));
+        let saved = image(vec![0xFF, 0x00, 0x00, 0xFF, 0x10, 0x20, 0x30, 0xFF]);
+        saved.save(&path).unwrap();
+        let loaded = Image::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, saved);
+    }
+
+    #[test]
+    fn it_should_count_differing_pixels() {
+        let left = image(vec![0; 8]);
+        let mut right = image(vec![0; 8]);
+        right.pixels[5] = 1;
+
+        assert_eq!(left.difference(&left), 0);
+        assert_eq!(left.difference(&right), 1);
+    }
+
+    #[test]
+    fn it_should_treat_other_sizes_as_different() {
+        let left = image(vec![0; 8]);
+        let right = Image {
+            width: 1,
+            height: 1,
+            pixels: vec![0; 4],
+        };
+
+        assert_eq!(left.difference(&right), 2);
+    }
+}